@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS idx_manifest_revisions_path;
+DROP INDEX IF EXISTS idx_manifest_revisions_key;
+DROP TABLE IF EXISTS manifest_revisions;
@@ -0,0 +1,13 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS manifest_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    widget_key TEXT NOT NULL,
+    manifest_path TEXT NOT NULL,
+    source TEXT NOT NULL,
+    name TEXT,
+    manifest TEXT NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (unixepoch('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_manifest_revisions_key ON manifest_revisions(widget_key);
+CREATE INDEX IF NOT EXISTS idx_manifest_revisions_path ON manifest_revisions(manifest_path);
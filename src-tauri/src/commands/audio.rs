@@ -1,19 +1,24 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use std::collections::HashSet;
 use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tauri::{AppHandle, Emitter, Manager, State};
 
+use crate::commands::subscriptions::{subscribe, unsubscribe};
+
 const CHUNK_SIZE: usize = 256;
 const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(1); // Check every 1 second
 
+/// RMS of the last emitted chunk as `f32` bits. Kept out of `AudioState` so
+/// the stream callback never waits on the lock `stop_capture` holds.
+static LEVEL: AtomicU32 = AtomicU32::new(0);
+
 /// Holds the audio stream and related state
 pub struct AudioState {
     stream: Option<cpal::Stream>,
     last_device_id: String,
     is_running: bool,
-    pub listening_windows: HashSet<String>,
 }
 
 impl fmt::Debug for AudioState {
@@ -23,13 +28,11 @@ impl fmt::Debug for AudioState {
         struct AudioState<'a> {
             last_device_id: &'a String,
             is_running: &'a bool,
-            listening_windows: &'a HashSet<String>,
         }
 
         let Self {
             last_device_id,
             is_running,
-            listening_windows,
             stream: _,
         } = self;
 
@@ -37,7 +40,6 @@ impl fmt::Debug for AudioState {
             &AudioState {
                 last_device_id,
                 is_running,
-                listening_windows,
             },
             f,
         )
@@ -50,20 +52,13 @@ impl AudioState {
             stream: None,
             last_device_id: String::new(),
             is_running: false,
-            listening_windows: HashSet::new(),
         }
     }
 }
 
 /// Start audio capture and monitor for device changes
-pub fn start_capture(
-    app: AppHandle,
-    window: &tauri::WebviewWindow,
-    audio_state: &State<Mutex<AudioState>>,
-) {
+pub fn start_capture(app: AppHandle, audio_state: &State<Mutex<AudioState>>) {
     let mut state = audio_state.lock().unwrap();
-    state.listening_windows.insert(window.label().to_string());
-
     if state.is_running {
         println!("Audio capture already running");
         return;
@@ -189,23 +184,23 @@ fn build_and_start_stream(app: AppHandle, device: &cpal::Device) -> Result<(), S
 }
 
 /// Stop audio capture
-pub fn stop_capture(label: &String, audio_state: &State<Mutex<AudioState>>) {
+pub fn stop_capture(audio_state: &State<Mutex<AudioState>>) {
     let mut state = audio_state.lock().unwrap();
     state.is_running = false;
     state.stream = None;
-    state.listening_windows.remove(label);
+    LEVEL.store(0, Ordering::Relaxed);
     println!("Audio capture stopped");
 }
 
 /// Restart audio capture (useful when device changes or for manual restart)
-pub fn restart_capture(
-    app: AppHandle,
-    window: &tauri::WebviewWindow,
-    audio_state: State<Mutex<AudioState>>,
-) {
-    stop_capture(&window.label().to_string(), &audio_state);
+pub fn restart_capture(app: AppHandle, audio_state: State<Mutex<AudioState>>) {
+    // Capture belongs to its subscribers, do not start it for nobody
+    if !audio_state.lock().unwrap().is_running {
+        return;
+    }
+    stop_capture(&audio_state);
     std::thread::sleep(Duration::from_millis(100)); // Brief delay
-    start_capture(app, window, &audio_state);
+    start_capture(app, &audio_state);
 }
 
 pub fn get_current_device() -> Result<String, String> {
@@ -220,6 +215,11 @@ pub fn get_current_device() -> Result<String, String> {
         .map_err(|e| format!("Failed to get device ID: {}", e))
 }
 
+/// Loudness of the captured output between 0 and 1.
+pub fn current_level() -> f32 {
+    f32::from_bits(LEVEL.load(Ordering::Relaxed))
+}
+
 fn downsample(samples: &[f32], target: usize) -> Vec<f32> {
     let len = samples.len();
     if len <= target {
@@ -259,6 +259,11 @@ fn emit_samples(app: &AppHandle, samples: &[f32], last_emit: &Arc<Mutex<tokio::t
         return;
     }
 
+    if !samples.is_empty() {
+        let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+        LEVEL.store(rms.min(1.0).to_bits(), Ordering::Relaxed);
+    }
+
     if let Err(e) = app.emit("audio-samples", downsample(&samples, CHUNK_SIZE)) {
         eprintln!("Failed to emit audio samples: {}", e);
     }
@@ -299,27 +304,23 @@ where
         .map_err(|e| format!("{}: {}", error_msg, e))
 }
 
+/// Subscribes the calling window to audio samples, see `subscriptions`.
 #[tauri::command]
-pub fn start_audio_capture(
+pub async fn start_audio_capture(
     app: tauri::AppHandle,
     window: tauri::WebviewWindow,
-    audio_state: State<Mutex<AudioState>>,
-) {
-    start_capture(app, &window, &audio_state);
+) -> Result<(), String> {
+    subscribe(&app, window.label(), "audio").await
 }
 
 #[tauri::command]
-pub fn stop_audio_capture(window: tauri::WebviewWindow, audio_state: State<Mutex<AudioState>>) {
-    stop_capture(&window.label().to_string(), &audio_state);
+pub async fn stop_audio_capture(app: tauri::AppHandle, window: tauri::WebviewWindow) {
+    unsubscribe(&app, window.label(), "audio").await;
 }
 
 #[tauri::command]
-pub fn restart_audio_capture(
-    app: tauri::AppHandle,
-    window: tauri::WebviewWindow,
-    audio_state: State<Mutex<AudioState>>,
-) {
-    restart_capture(app, &window, audio_state);
+pub fn restart_audio_capture(app: tauri::AppHandle, audio_state: State<Mutex<AudioState>>) {
+    restart_capture(app, audio_state);
 }
 
 #[tauri::command]
@@ -144,6 +144,49 @@ pub async fn update_chat_widget_keys(
     Ok(())
 }
 
+/// Drops `key` from the `widgetKeys` of every chat that created it.
+pub async fn remove_chat_widget_key(pool: &sqlx::SqlitePool, key: &str) -> Result<(), String> {
+    let stmt = r#"
+        SELECT
+            id,
+            name,
+            data,
+            created_at,
+            updated_at
+        FROM chats
+    "#;
+    let chats = sqlx::query_as::<_, Chat>(stmt)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    for chat in chats {
+        let mut data = chat.data;
+        let Some(keys) = data.get_mut("widgetKeys").and_then(|k| k.as_array_mut()) else {
+            continue;
+        };
+        let len = keys.len();
+        keys.retain(|k| k.as_str() != Some(key));
+        if keys.len() == len {
+            continue;
+        }
+
+        let stmt = r#"
+            UPDATE chats
+            SET data = $1
+            WHERE id = $2
+        "#;
+        sqlx::query(stmt)
+            .bind(&data)
+            .bind(&chat.id)
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn get_chat_by_id(
     state: tauri::State<'_, DatabaseState>,
@@ -0,0 +1,234 @@
+use base64::{prelude::BASE64_STANDARD, Engine};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    commands::{
+        chat::remove_chat_widget_key, data_files::forget_file_approvals, history::delete_revisions,
+        permissions::forget_consent, secrets::delete_widget_secrets, shell::forget_approvals,
+        widget::close_widget_window,
+    },
+    db::DatabaseState,
+    manifest::{WidgetManifest, WidgetType},
+};
+
+/// Cache entries younger than this are left alone by `gc_cache`, the creator
+/// copies assets before the draft referencing them is saved.
+const GC_GRACE_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Everything in AppCache that is still used by a manifest in `widgets/` or `saves/`.
+#[derive(Default)]
+struct CacheReferences {
+    keys: HashSet<String>,
+    assets: HashSet<String>,
+    thumbs: HashSet<String>,
+}
+
+impl CacheReferences {
+    fn add(&mut self, manifest: &WidgetManifest) {
+        self.keys.insert(manifest.key.clone());
+        self.assets.extend(manifest.file_asset_keys());
+        if let Some(thumb) = thumb_file_name(manifest) {
+            self.thumbs.insert(thumb);
+        }
+    }
+}
+
+/// Mirrors `createUrlThumbnail` on the frontend: `<base64(hostname)>.png`.
+fn thumb_file_name(manifest: &WidgetManifest) -> Option<String> {
+    if manifest.widget_type != WidgetType::Url {
+        return None;
+    }
+    let url = reqwest::Url::parse(manifest.url.as_deref()?).ok()?;
+    Some(format!("{}.png", BASE64_STANDARD.encode(url.host_str()?)))
+}
+
+fn widget_manifests(app: &AppHandle, sub_dir: &str) -> Vec<(PathBuf, WidgetManifest)> {
+    let Ok(base_path) = app
+        .path()
+        .resolve(sub_dir, tauri::path::BaseDirectory::AppData)
+    else {
+        return vec![];
+    };
+    let Ok(entries) = fs::read_dir(&base_path) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let manifest = WidgetManifest::load(&entry.path().join("manifest.json")).ok()?;
+            Some((entry.path(), manifest))
+        })
+        .collect()
+}
+
+fn collect_references(app: &AppHandle) -> CacheReferences {
+    let mut references = CacheReferences::default();
+    for sub_dir in ["widgets", "saves"] {
+        for (_, manifest) in widget_manifests(app, sub_dir) {
+            references.add(&manifest);
+        }
+    }
+    references
+}
+
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Removes the cache entries of `removed` that no remaining manifest uses.
+fn remove_cached_artifacts(app: &AppHandle, removed: &[WidgetManifest]) -> anyhow::Result<()> {
+    let cache_dir = app.path().app_cache_dir()?;
+    let references = collect_references(app);
+
+    let mut candidates = vec![];
+    for manifest in removed {
+        if !references.keys.contains(&manifest.key) {
+            candidates.push(cache_dir.join("files").join(&manifest.key));
+            candidates.push(cache_dir.join("assets").join(&manifest.key));
+            candidates.push(cache_dir.join("http").join(&manifest.key));
+        }
+        for asset in manifest.file_asset_keys() {
+            if !references.assets.contains(&asset) {
+                candidates.push(cache_dir.join("assets").join(asset));
+            }
+        }
+        if let Some(thumb) = thumb_file_name(manifest) {
+            if !references.thumbs.contains(&thumb) {
+                candidates.push(cache_dir.join("thumbs").join(thumb));
+            }
+        }
+    }
+
+    for path in candidates.iter().filter(|p| p.exists()) {
+        if let Err(e) = remove_path(path) {
+            eprintln!("Error removing {}: {}", path.display(), e);
+        }
+    }
+    Ok(())
+}
+
+async fn close_widget_windows(app: &AppHandle, key: &str) -> Result<(), String> {
+    for label in [format!("widget-{key}"), format!("widget-preview-{key}")] {
+        close_widget_window(app.clone(), label).await?;
+    }
+    Ok(())
+}
+
+/// Removes the published copy of `key` and returns its manifest.
+async fn unpublish(app: &AppHandle, key: &str) -> Result<Vec<WidgetManifest>, String> {
+    close_widget_windows(app, key).await?;
+
+    let mut removed = vec![];
+    for (dir, manifest) in widget_manifests(app, "widgets") {
+        if manifest.key == key {
+            fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+            removed.push(manifest);
+        }
+    }
+    Ok(removed)
+}
+
+#[tauri::command]
+pub async fn unpublish_widget(app: AppHandle, key: String) -> Result<(), String> {
+    let removed = unpublish(&app, &key).await?;
+
+    // Keep the draft, but it is no longer published
+    for (dir, mut manifest) in widget_manifests(&app, "saves") {
+        if manifest.key == key {
+            manifest.published = Some(false);
+            manifest.published_at = None;
+            manifest
+                .save(&dir.join("manifest.json"))
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    remove_cached_artifacts(&app, &removed).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_widget(app: AppHandle, key: String) -> Result<(), String> {
+    let mut removed = unpublish(&app, &key).await?;
+    for (dir, manifest) in widget_manifests(&app, "saves") {
+        if manifest.key == key {
+            fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+            removed.push(manifest);
+        }
+    }
+
+    remove_cached_artifacts(&app, &removed).map_err(|e| e.to_string())?;
+    for manifest in &removed {
+        delete_widget_secrets(&app, manifest);
+    }
+    if let Err(e) = delete_widget_passwords(&app, &key) {
+        eprintln!("Error deleting widget passwords: {}", e);
+    }
+    if let Err(e) = forget_consent(&app, &key) {
+        eprintln!("Error deleting widget permissions: {}", e);
+    }
+    if let Err(e) = forget_approvals(&app, &key) {
+        eprintln!("Error deleting shell command approvals: {}", e);
+    }
+    if let Err(e) = forget_file_approvals(&app, &key) {
+        eprintln!("Error deleting data file approvals: {}", e);
+    }
+
+    let db_state = app.state::<DatabaseState>();
+    remove_chat_widget_key(&db_state.0, &key).await?;
+    if let Err(e) = delete_revisions(&db_state.0, &key).await {
+        eprintln!("Error deleting manifest revisions: {}", e);
+    }
+    Ok(())
+}
+
+/// Sweeps `assets/`, `files/`, `http/` and `thumbs/` for entries no widget references,
+/// returning the removed paths.
+#[tauri::command]
+pub async fn gc_cache(app: AppHandle) -> Result<Vec<String>, String> {
+    let cache_dir = app.path().app_cache_dir().map_err(|e| e.to_string())?;
+    let references = collect_references(&app);
+    let mut removed = vec![];
+
+    for sub_dir in ["assets", "files", "http", "thumbs"] {
+        let Ok(entries) = fs::read_dir(cache_dir.join(sub_dir)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_referenced = match sub_dir {
+                "assets" => references.assets.contains(&name) || references.keys.contains(&name),
+                "files" | "http" => references.keys.contains(&name),
+                _ => references.thumbs.contains(&name),
+            };
+            if is_referenced {
+                continue;
+            }
+            let is_recent = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .map(|age| age < GC_GRACE_PERIOD)
+                .unwrap_or(true);
+            if is_recent {
+                continue;
+            }
+            match remove_path(&entry.path()) {
+                Ok(_) => removed.push(entry.path().to_string_lossy().to_string()),
+                Err(e) => eprintln!("Error removing {}: {}", entry.path().display(), e),
+            }
+        }
+    }
+
+    Ok(removed)
+}
@@ -0,0 +1,105 @@
+use serde_json::{json, Map, Value};
+use std::path::Path;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+use crate::{
+    commands::store::{get_or_create_store, store_path, write_to_store, KVPair},
+    persist,
+    providers::ProviderRegistry,
+};
+
+/// Widget key -> data file id -> path the user allowed it to read. Files in
+/// the widget's own folder need no approval.
+const APPROVED_FILES_KEY: &str = "approvedDataFiles";
+
+fn approvals(app: &AppHandle) -> anyhow::Result<Map<String, Value>> {
+    let store = get_or_create_store(app)?;
+    Ok(store
+        .get(APPROVED_FILES_KEY)
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default())
+}
+
+fn save_approvals(app: &AppHandle, approvals: Map<String, Value>) -> anyhow::Result<()> {
+    write_to_store(
+        app,
+        vec![KVPair {
+            key: APPROVED_FILES_KEY.to_string(),
+            value: Value::Object(approvals),
+        }],
+    )
+}
+
+/// Whether the user allowed data file `id` of the widget to read `path`.
+pub fn is_approved(app: &AppHandle, widget_key: &str, id: &str, path: &Path) -> bool {
+    approvals(app)
+        .map_err(|e| eprintln!("Error reading data file approvals: {}", e))
+        .ok()
+        .and_then(|approvals| {
+            approvals
+                .get(widget_key)?
+                .get(id)?
+                .as_str()
+                .map(|approved| Path::new(approved) == path)
+        })
+        .unwrap_or(false)
+}
+
+fn approve(app: &AppHandle, widget_key: &str, id: &str, path: &Path) -> anyhow::Result<()> {
+    // Held across the read, so approving two files at once keeps both
+    persist::with_lock(&store_path(app)?, || {
+        let mut approvals = approvals(app)?;
+        let widget = approvals
+            .entry(widget_key.to_string())
+            .or_insert_with(|| json!({}));
+        if !widget.is_object() {
+            *widget = json!({});
+        }
+        widget
+            .as_object_mut()
+            .expect("checked above")
+            .insert(id.to_string(), json!(path.to_string_lossy()));
+        save_approvals(app, approvals)
+    })
+}
+
+/// Asks whether the widget may read `path`, which is outside its folder. The
+/// file provider reads it again once allowed.
+pub fn request_approval(app: &AppHandle, widget_key: &str, id: &str, path: &Path) {
+    let (widget_key, id, path) = (widget_key.to_string(), id.to_string(), path.to_path_buf());
+    app.dialog()
+        .message(format!(
+            "The widget \"{}\" wants to read this file outside its folder:\n\n{}\n\nOnly allow files you are fine with the widget showing.",
+            widget_key,
+            path.display()
+        ))
+        .title("Allow file access?")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancel)
+        .show({
+            let app = app.clone();
+            move |allowed| {
+                if !allowed {
+                    return;
+                }
+                if let Err(e) = approve(&app, &widget_key, &id, &path) {
+                    eprintln!("Error saving data file approval: {}", e);
+                    return;
+                }
+                app.state::<ProviderRegistry>().notify_changed("file");
+            }
+        });
+}
+
+/// Drops every approval of a deleted widget.
+pub fn forget_file_approvals(app: &AppHandle, widget_key: &str) -> anyhow::Result<()> {
+    persist::with_lock(&store_path(app)?, || {
+        let mut approvals = approvals(app)?;
+        if approvals.remove(widget_key).is_none() {
+            return Ok(());
+        }
+        save_approvals(app, approvals)
+    })
+}
@@ -0,0 +1,345 @@
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::prelude::*;
+use std::path::Path;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{db::DatabaseState, manifest::WidgetManifest, persist};
+
+/// Unnamed revisions kept per manifest file
+const MAX_REVISIONS: i64 = 50;
+/// Named revisions kept per manifest file, pruned separately so edits do not
+/// push out publishes
+const MAX_NAMED_REVISIONS: i64 = 20;
+/// Change whenever a window is moved or resized, which is not worth a revision
+const GEOMETRY_FIELDS: [&str; 4] = ["position", "anchor", "monitorPositions", "dimensions"];
+
+#[derive(Debug, Clone, Copy)]
+pub enum RevisionSource {
+    /// The file as it was before a change, when history did not have it yet
+    Snapshot,
+    Publish,
+    Update,
+    Restore,
+}
+
+impl RevisionSource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RevisionSource::Snapshot => "snapshot",
+            RevisionSource::Publish => "publish",
+            RevisionSource::Update => "update",
+            RevisionSource::Restore => "restore",
+        }
+    }
+}
+
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestRevision {
+    pub id: i64,
+    pub widget_key: String,
+    pub manifest_path: String,
+    pub source: String,
+    pub name: Option<String>,
+    pub manifest: Value,
+    pub created_at: i64, // Unix timestamp
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestChange {
+    pub path: String,
+    pub kind: ChangeKind,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+fn without_geometry(value: &Value) -> Value {
+    let mut value = value.clone();
+    if let Some(obj) = value.as_object_mut() {
+        for field in GEOMETRY_FIELDS {
+            obj.remove(field);
+        }
+    }
+    value
+}
+
+async fn insert_revision(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    widget_key: &str,
+    manifest_path: &str,
+    source: RevisionSource,
+    name: &Option<String>,
+    value: &Value,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO manifest_revisions (widget_key, manifest_path, source, name, manifest)
+        VALUES (?, ?, ?, ?, ?)
+    "#,
+    )
+    .bind(widget_key)
+    .bind(manifest_path)
+    .bind(source.as_str())
+    .bind(name)
+    .bind(value)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Stores `manifest` as the latest revision of the file at `path`, unless it
+/// only differs from the previous one in window geometry, then prunes old
+/// revisions. `previous` is the file before the change, recorded first when
+/// history does not have it, so the first edit can be undone too.
+pub async fn record_revision(
+    app: &AppHandle,
+    path: &Path,
+    previous: Option<&WidgetManifest>,
+    manifest: &WidgetManifest,
+    source: RevisionSource,
+    name: Option<String>,
+) -> anyhow::Result<()> {
+    let db_state = app.state::<DatabaseState>();
+    let pool = &db_state.0;
+    let manifest_path = path.to_string_lossy().to_string();
+    let value = manifest.to_value()?;
+
+    let latest: Option<(Value,)> = sqlx::query_as(
+        r#"
+        SELECT manifest FROM manifest_revisions
+        WHERE manifest_path = ?
+        ORDER BY id DESC
+        LIMIT 1
+    "#,
+    )
+    .bind(&manifest_path)
+    .fetch_optional(pool)
+    .await?;
+    let mut latest = latest.map(|(latest,)| without_geometry(&latest));
+    let current = without_geometry(&value);
+
+    let mut tx = pool.begin().await?;
+    if let Some(previous) = previous {
+        let previous_value = previous.to_value()?;
+        let previous_design = without_geometry(&previous_value);
+        if latest.as_ref() != Some(&previous_design) && previous_design != current {
+            insert_revision(
+                &mut tx,
+                &previous.key,
+                &manifest_path,
+                RevisionSource::Snapshot,
+                &None,
+                &previous_value,
+            )
+            .await?;
+            latest = Some(previous_design);
+        }
+    }
+    if name.is_none() && latest.as_ref() == Some(&current) {
+        tx.commit().await?;
+        return Ok(());
+    }
+    insert_revision(
+        &mut tx,
+        &manifest.key,
+        &manifest_path,
+        source,
+        &name,
+        &value,
+    )
+    .await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM manifest_revisions
+        WHERE manifest_path = ?1 AND name IS NULL AND id NOT IN (
+            SELECT id FROM manifest_revisions
+            WHERE manifest_path = ?1 AND name IS NULL
+            ORDER BY id DESC
+            LIMIT ?2
+        )
+    "#,
+    )
+    .bind(&manifest_path)
+    .bind(MAX_REVISIONS)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        DELETE FROM manifest_revisions
+        WHERE manifest_path = ?1 AND name IS NOT NULL AND id NOT IN (
+            SELECT id FROM manifest_revisions
+            WHERE manifest_path = ?1 AND name IS NOT NULL
+            ORDER BY id DESC
+            LIMIT ?2
+        )
+    "#,
+    )
+    .bind(&manifest_path)
+    .bind(MAX_NAMED_REVISIONS)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub async fn delete_revisions(pool: &sqlx::SqlitePool, key: &str) -> anyhow::Result<()> {
+    sqlx::query("DELETE FROM manifest_revisions WHERE widget_key = ?")
+        .bind(key)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+async fn get_revision(pool: &sqlx::SqlitePool, id: i64) -> Result<ManifestRevision, String> {
+    sqlx::query_as::<_, ManifestRevision>("SELECT * FROM manifest_revisions WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Revision {} not found", id))
+}
+
+fn diff_values(path: String, old: &Value, new: &Value, changes: &mut Vec<ManifestChange>) {
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            for (key, old_value) in old_map {
+                let child = format!("{path}.{key}");
+                match new_map.get(key) {
+                    Some(new_value) => diff_values(child, old_value, new_value, changes),
+                    None => changes.push(ManifestChange {
+                        path: child,
+                        kind: ChangeKind::Removed,
+                        old: Some(old_value.clone()),
+                        new: None,
+                    }),
+                }
+            }
+            for (key, new_value) in new_map {
+                if !old_map.contains_key(key) {
+                    changes.push(ManifestChange {
+                        path: format!("{path}.{key}"),
+                        kind: ChangeKind::Added,
+                        old: None,
+                        new: Some(new_value.clone()),
+                    });
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) => {
+            for i in 0..old_items.len().max(new_items.len()) {
+                let child = format!("{path}[{i}]");
+                match (old_items.get(i), new_items.get(i)) {
+                    (Some(old_value), Some(new_value)) => {
+                        diff_values(child, old_value, new_value, changes)
+                    }
+                    (Some(old_value), None) => changes.push(ManifestChange {
+                        path: child,
+                        kind: ChangeKind::Removed,
+                        old: Some(old_value.clone()),
+                        new: None,
+                    }),
+                    (None, Some(new_value)) => changes.push(ManifestChange {
+                        path: child,
+                        kind: ChangeKind::Added,
+                        old: None,
+                        new: Some(new_value.clone()),
+                    }),
+                    (None, None) => {}
+                }
+            }
+        }
+        _ if old != new => changes.push(ManifestChange {
+            path,
+            kind: ChangeKind::Changed,
+            old: Some(old.clone()),
+            new: Some(new.clone()),
+        }),
+        _ => {}
+    }
+}
+
+#[tauri::command]
+pub async fn list_manifest_revisions(
+    state: tauri::State<'_, DatabaseState>,
+    key: String,
+) -> Result<Vec<ManifestRevision>, String> {
+    let pool = &state.0;
+
+    let stmt = r#"
+        SELECT * FROM manifest_revisions
+        WHERE widget_key = $1
+        ORDER BY id DESC
+    "#;
+
+    sqlx::query_as::<_, ManifestRevision>(stmt)
+        .bind(&key)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Diffs two revisions, or a revision against the current file when `to_id` is omitted.
+#[tauri::command]
+pub async fn diff_manifest_revisions(
+    state: tauri::State<'_, DatabaseState>,
+    from_id: i64,
+    to_id: Option<i64>,
+) -> Result<Vec<ManifestChange>, String> {
+    let pool = &state.0;
+    let from = get_revision(pool, from_id).await?;
+    let to = match to_id {
+        Some(id) => get_revision(pool, id).await?.manifest,
+        None => WidgetManifest::load(Path::new(&from.manifest_path))
+            .and_then(|m| m.to_value())
+            .map_err(|e| e.to_string())?,
+    };
+
+    let mut changes = vec![];
+    diff_values("$".to_string(), &from.manifest, &to, &mut changes);
+    Ok(changes)
+}
+
+#[tauri::command]
+pub async fn restore_manifest_revision(
+    app: AppHandle,
+    state: tauri::State<'_, DatabaseState>,
+    id: i64,
+) -> Result<String, String> {
+    let revision = get_revision(&state.0, id).await?;
+    let path = Path::new(&revision.manifest_path);
+    let manifest = WidgetManifest::from_value(revision.manifest).map_err(|e| e.to_string())?;
+
+    let (previous, json_string) = persist::with_lock(path, || {
+        let previous = WidgetManifest::load(path).ok();
+        manifest
+            .save(path)
+            .map(|json_string| (previous, json_string))
+    })
+    .map_err(|e| e.to_string())?;
+    let result = record_revision(
+        &app,
+        path,
+        previous.as_ref(),
+        &manifest,
+        RevisionSource::Restore,
+        None,
+    )
+    .await;
+    if let Err(e) = result {
+        eprintln!("Error recording manifest revision: {}", e);
+    }
+    app.emit_to(manifest.window_label(), "update-manifest", 1)
+        .map_err(|e| e.to_string())?;
+    Ok(json_string)
+}
@@ -0,0 +1,295 @@
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+};
+use tauri::{AppHandle, Emitter, Manager, PhysicalPosition, Size};
+
+use crate::{
+    commands::{
+        monitors::{anchored_position, manifest_size, set_position},
+        store::{get_or_create_store, write_to_store, KVPair},
+        utils::ensure_window_position_bounds,
+        widget::{close_widget_window, create_widget_window},
+    },
+    manifest::{Anchor, Dimensions, Position, WidgetManifest, WidgetType},
+    persist,
+    setup::init::refresh_tray_menu,
+};
+
+const LAYOUTS_KEY: &str = "layouts";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LayoutWidget {
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<Anchor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<Dimensions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub always_on_top: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Layout {
+    pub name: String,
+    pub widgets: Vec<LayoutWidget>,
+    pub created_at: i64,
+}
+
+pub fn get_layouts(app: &AppHandle) -> anyhow::Result<Vec<Layout>> {
+    let store = get_or_create_store(app)?;
+    Ok(store
+        .get(LAYOUTS_KEY)
+        .cloned()
+        .map(serde_json::from_value)
+        .transpose()?
+        .unwrap_or_default())
+}
+
+fn set_layouts(app: &AppHandle, layouts: Vec<Layout>) -> anyhow::Result<()> {
+    write_to_store(
+        app,
+        vec![KVPair {
+            key: LAYOUTS_KEY.to_string(),
+            value: json!(layouts),
+        }],
+    )?;
+    refresh_tray_menu(app);
+    Ok(())
+}
+
+/// Published widgets by key, with the path of their manifest.
+fn published_widgets(
+    app: &AppHandle,
+) -> anyhow::Result<HashMap<String, (PathBuf, WidgetManifest)>> {
+    let widgets_dir = app
+        .path()
+        .resolve("widgets", tauri::path::BaseDirectory::AppData)?;
+    let mut widgets = HashMap::new();
+    for entry in fs::read_dir(&widgets_dir)?.flatten() {
+        let manifest_path = entry.path().join("manifest.json");
+        match WidgetManifest::load(&manifest_path) {
+            Ok(manifest) => {
+                widgets.insert(manifest.key.clone(), (manifest_path, manifest));
+            }
+            Err(e) => eprintln!("Skipping {}: {}", manifest_path.display(), e),
+        }
+    }
+    Ok(widgets)
+}
+
+/// Moves an already open widget window to the state in its manifest.
+fn apply_window_state(app: &AppHandle, manifest: &WidgetManifest) -> tauri::Result<()> {
+    let Some(window) = app.get_webview_window(&manifest.window_label()) else {
+        return Ok(());
+    };
+    let size = match manifest_size(manifest) {
+        Some(size) => {
+            window.set_size(size)?;
+            size.to_physical(window.scale_factor()?)
+        }
+        None => window.inner_size()?,
+    };
+    let anchored = manifest
+        .anchor
+        .as_ref()
+        .and_then(|anchor| anchored_position(app, anchor, Size::Physical(size)));
+    if let Some(position) = anchored {
+        window.set_position(position)?;
+    } else if let Some(position) = &manifest.position {
+        window.set_position(ensure_window_position_bounds(
+            app,
+            PhysicalPosition {
+                x: position.x.unwrap_or(30.0) as i32,
+                y: position.y.unwrap_or(30.0) as i32,
+            },
+            size,
+        ))?;
+    }
+
+    let always_on_top = manifest.always_on_top.unwrap_or(false);
+    window.set_always_on_bottom(!always_on_top)?;
+    window.set_always_on_top(always_on_top)?;
+
+    let pinned = manifest.pinned.unwrap_or(false);
+    window.set_resizable(!pinned)?;
+    if manifest.widget_type == WidgetType::Url {
+        window.set_decorations(!pinned)?;
+    }
+    Ok(())
+}
+
+/// Shows and places a widget the way `state` has it, hides one the layout
+/// does not have.
+fn apply_layout_state(
+    app: &AppHandle,
+    manifest: &mut WidgetManifest,
+    state: Option<&LayoutWidget>,
+) {
+    let Some(state) = state else {
+        manifest.visible = Some(false);
+        return;
+    };
+    manifest.visible = Some(true);
+    match &state.position {
+        Some(position) => {
+            let position = PhysicalPosition {
+                x: position.x.unwrap_or(30.0) as i32,
+                y: position.y.unwrap_or(30.0) as i32,
+            };
+            set_position(app, manifest, position, state.anchor.clone());
+        }
+        None if state.anchor.is_some() => manifest.anchor = state.anchor.clone(),
+        None => {}
+    }
+    manifest.dimensions = state.dimensions.clone().or(manifest.dimensions.take());
+    manifest.always_on_top = state.always_on_top;
+    manifest.pinned = state.pinned;
+}
+
+pub async fn apply_layout_by_name(app: &AppHandle, name: &str) -> anyhow::Result<()> {
+    let Some(layout) = get_layouts(app)?.into_iter().find(|l| l.name == name) else {
+        bail!("Layout {} not found", name);
+    };
+    let mut widgets = published_widgets(app)?;
+    let in_layout: HashMap<&str, &LayoutWidget> =
+        layout.widgets.iter().map(|w| (w.key.as_str(), w)).collect();
+
+    // Each manifest is read again and written under its lock, so an edit
+    // made since it was listed is not lost. The originals undo a failure.
+    let mut written = vec![];
+    for (key, (path, manifest)) in widgets.iter_mut() {
+        let state = in_layout.get(key.as_str()).copied();
+        let result = persist::with_lock(path, || -> anyhow::Result<Option<WidgetManifest>> {
+            let mut current = WidgetManifest::load(path)?;
+            let original = current.clone();
+            apply_layout_state(app, &mut current, state);
+            let changed = current != original;
+            if changed {
+                current.save(path)?;
+            }
+            *manifest = current;
+            Ok(changed.then_some(original))
+        });
+        match result {
+            Ok(Some(original)) => written.push((path.clone(), original)),
+            Ok(None) => {}
+            Err(e) => {
+                for (path, original) in &written {
+                    let _ = persist::with_lock(path, || original.save(path));
+                }
+                return Err(e);
+            }
+        }
+    }
+    for key in in_layout.keys().filter(|k| !widgets.contains_key(**k)) {
+        eprintln!("Layout {} references missing widget {}", name, key);
+    }
+
+    let changed: HashSet<&str> = written
+        .iter()
+        .map(|(_, original)| original.key.as_str())
+        .collect();
+    for (path, manifest) in widgets.values() {
+        let label = manifest.window_label();
+        let is_open = app.get_webview_window(&label).is_some();
+        if !manifest.visible.unwrap_or(false) {
+            if let Err(e) = close_widget_window(app.clone(), label).await {
+                eprintln!("Error closing {}: {}", manifest.key, e);
+            }
+        } else if !is_open {
+            let path = json!(path.to_string_lossy()).to_string();
+            if let Err(e) = create_widget_window(app.clone(), path, Some(false)).await {
+                eprintln!("Error opening {}: {}", manifest.key, e);
+            }
+        } else if changed.contains(manifest.key.as_str()) {
+            if let Err(e) = apply_window_state(app, manifest) {
+                eprintln!("Error updating {}: {}", manifest.key, e);
+            }
+        }
+    }
+
+    let _ = app.emit_to("main", "widgets-changed", 1);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_layouts(app: AppHandle) -> Result<Vec<Layout>, String> {
+    get_layouts(&app).map_err(|e| e.to_string())
+}
+
+/// Snapshots the visible widgets into `name`, replacing a layout with the same name.
+#[tauri::command]
+pub fn save_layout(app: AppHandle, name: String) -> Result<Layout, String> {
+    if name.trim().is_empty() {
+        return Err("Layout name is required".to_string());
+    }
+    let mut widgets: Vec<LayoutWidget> = published_widgets(&app)
+        .map_err(|e| e.to_string())?
+        .into_values()
+        .filter(|(_, m)| m.visible.unwrap_or(false))
+        .map(|(_, m)| LayoutWidget {
+            key: m.key,
+            position: m.position,
+            anchor: m.anchor,
+            dimensions: m.dimensions,
+            always_on_top: m.always_on_top,
+            pinned: m.pinned,
+        })
+        .collect();
+    widgets.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let layout = Layout {
+        name,
+        widgets,
+        created_at: chrono::Utc::now().timestamp_millis(),
+    };
+    let mut layouts = get_layouts(&app).map_err(|e| e.to_string())?;
+    match layouts.iter_mut().find(|l| l.name == layout.name) {
+        Some(existing) => *existing = layout.clone(),
+        None => layouts.push(layout.clone()),
+    }
+    set_layouts(&app, layouts).map_err(|e| e.to_string())?;
+    Ok(layout)
+}
+
+#[tauri::command]
+pub async fn apply_layout(app: AppHandle, name: String) -> Result<(), String> {
+    apply_layout_by_name(&app, &name)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn rename_layout(app: AppHandle, name: String, new_name: String) -> Result<(), String> {
+    if new_name.trim().is_empty() {
+        return Err("Layout name is required".to_string());
+    }
+    let mut layouts = get_layouts(&app).map_err(|e| e.to_string())?;
+    if layouts.iter().any(|l| l.name == new_name) {
+        return Err(format!("Layout {} already exists", new_name));
+    }
+    let layout = layouts
+        .iter_mut()
+        .find(|l| l.name == name)
+        .ok_or_else(|| format!("Layout {} not found", name))?;
+    layout.name = new_name;
+    set_layouts(&app, layouts).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_layout(app: AppHandle, name: String) -> Result<(), String> {
+    let mut layouts = get_layouts(&app).map_err(|e| e.to_string())?;
+    layouts.retain(|l| l.name != name);
+    set_layouts(&app, layouts).map_err(|e| e.to_string())
+}
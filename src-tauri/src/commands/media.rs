@@ -27,9 +27,12 @@ use windows::{
 };
 
 use crate::{
-    commands::utils::{
-        get_app_icon, get_encoded_app_id, get_player_icon_path, get_win32_icon,
-        read_cached_app_name, upsert_media_history,
+    commands::{
+        subscriptions::{subscribe, unsubscribe},
+        utils::{
+            get_app_icon, get_encoded_app_id, get_player_icon_path, get_win32_icon,
+            read_cached_app_name, upsert_media_history,
+        },
     },
     db::DatabaseState,
 };
@@ -75,6 +78,14 @@ pub struct MediaInfo {
     pub is_current_session: bool,
 }
 
+impl MediaInfo {
+    pub fn is_playing(&self) -> bool {
+        self.playback_info
+            .as_ref()
+            .is_some_and(|p| p.status == "playing")
+    }
+}
+
 // create the error type that represents all errors possible in our program
 #[derive(Debug, thiserror::Error)]
 pub enum CommandError {
@@ -286,7 +297,6 @@ pub struct MediaState {
     is_listening: bool,
     fetch_lock: Arc<Mutex<()>>,
     pub last_upsert_at: i64,
-    pub listening_windows: HashSet<String>,
 }
 
 impl MediaState {
@@ -297,7 +307,6 @@ impl MediaState {
             is_listening: false,
             fetch_lock: Arc::new(Mutex::new(())),
             last_upsert_at: chrono::Utc::now().timestamp_millis(),
-            listening_windows: HashSet::new(),
         }
     }
 }
@@ -536,12 +545,9 @@ fn build_media_info(
 
 pub async fn start_media_listener(
     app: &AppHandle,
-    window: &WebviewWindow,
     media_state: &State<'_, Mutex<MediaState>>,
 ) -> CommandResult<()> {
     let mut state = media_state.lock().await;
-    state.listening_windows.insert(window.label().to_string());
-
     if state.is_listening {
         println!("media listener already running");
         return Ok(());
@@ -558,19 +564,13 @@ pub async fn start_media_listener(
     Ok(())
 }
 
+/// Subscribes the calling window to media updates, see `subscriptions`.
 #[tauri::command]
-pub async fn start_media_listener_cmd(
-    app: AppHandle,
-    window: WebviewWindow,
-    media_state: State<'_, Mutex<MediaState>>,
-) -> CommandResult<()> {
-    start_media_listener(&app, &window, &media_state).await
+pub async fn start_media_listener_cmd(app: AppHandle, window: WebviewWindow) -> Result<(), String> {
+    subscribe(&app, window.label(), "media").await
 }
 
-pub async fn stop_media_listener(
-    label: &String,
-    media_state: &State<'_, Mutex<MediaState>>,
-) -> CommandResult<()> {
+pub async fn stop_media_listener(media_state: &State<'_, Mutex<MediaState>>) -> CommandResult<()> {
     let mut state = media_state.lock().await;
 
     if !state.is_listening {
@@ -584,18 +584,14 @@ pub async fn stop_media_listener(
 
     state.session_manager = None;
     state.is_listening = false;
-    state.listening_windows.remove(label);
 
     println!("media listener stopped");
     Ok(())
 }
 
 #[tauri::command]
-pub async fn stop_media_listener_cmd(
-    window: WebviewWindow,
-    media_state: State<'_, Mutex<MediaState>>,
-) -> CommandResult<()> {
-    stop_media_listener(&window.label().to_string(), &media_state).await
+pub async fn stop_media_listener_cmd(app: AppHandle, window: WebviewWindow) {
+    unsubscribe(&app, window.label(), "media").await;
 }
 
 fn get_current_player_id(manager: &MediaSessionManager) -> String {
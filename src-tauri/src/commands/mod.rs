@@ -1,10 +1,23 @@
 pub mod analytics;
 pub mod audio;
 pub mod chat;
+pub mod cleanup;
+pub mod data_files;
+pub mod history;
+pub mod layouts;
 pub mod media;
 pub mod migrate;
+pub mod monitors;
+pub mod package;
+pub mod permissions;
+pub mod secrets;
 pub mod services;
+pub mod shell;
+pub mod signing;
 pub mod store;
+pub mod subscriptions;
 pub mod system;
 pub mod utils;
+pub mod variables;
+pub mod vault;
 pub mod widget;
@@ -0,0 +1,380 @@
+use tauri::{AppHandle, LogicalSize, PhysicalPosition, PhysicalSize, Size};
+
+use crate::manifest::{
+    Anchor, AnchorPoint, MonitorInfo, MonitorPosition, Position, WidgetManifest, WidgetType,
+};
+
+/// Connected monitors sorted left to right, top to bottom.
+pub fn current_monitors(app: &AppHandle) -> Vec<MonitorInfo> {
+    let mut monitors: Vec<MonitorInfo> = app
+        .available_monitors()
+        .unwrap_or_default()
+        .iter()
+        .map(|m| MonitorInfo {
+            x: m.position().x,
+            y: m.position().y,
+            width: m.size().width,
+            height: m.size().height,
+            scale: m.scale_factor(),
+        })
+        .collect();
+    monitors.sort_by_key(|m| (m.x, m.y));
+    monitors
+}
+
+/// Work areas (monitor minus taskbar and docks), in the same order as
+/// `current_monitors`.
+pub fn current_work_areas(app: &AppHandle) -> Vec<MonitorInfo> {
+    let mut monitors = app.available_monitors().unwrap_or_default();
+    monitors.sort_by_key(|m| (m.position().x, m.position().y));
+    monitors
+        .iter()
+        .map(|m| {
+            let area = m.work_area();
+            MonitorInfo {
+                x: area.position.x,
+                y: area.position.y,
+                width: area.size.width,
+                height: area.size.height,
+                scale: m.scale_factor(),
+            }
+        })
+        .collect()
+}
+
+/// e.g. `2560x1440@0,0*1.5;1920x1080@2560,0*1`
+pub fn monitor_fingerprint(monitors: &[MonitorInfo]) -> String {
+    monitors
+        .iter()
+        .map(|m| format!("{}x{}@{},{}*{}", m.width, m.height, m.x, m.y, m.scale))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn contains(monitor: &MonitorInfo, position: PhysicalPosition<i32>) -> bool {
+    position.x >= monitor.x
+        && position.y >= monitor.y
+        && position.x < monitor.x + monitor.width as i32
+        && position.y < monitor.y + monitor.height as i32
+}
+
+pub fn fits(
+    monitor: &MonitorInfo,
+    position: PhysicalPosition<i32>,
+    size: PhysicalSize<u32>,
+) -> bool {
+    position.x >= monitor.x
+        && position.y >= monitor.y
+        && position.x + size.width as i32 <= monitor.x + monitor.width as i32
+        && position.y + size.height as i32 <= monitor.y + monitor.height as i32
+}
+
+/// Index of the monitor closest to `position`.
+fn nearest(monitors: &[MonitorInfo], position: PhysicalPosition<i32>) -> Option<usize> {
+    monitors
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, m)| {
+            let dx = (position.x - position.x.clamp(m.x, m.x + m.width as i32)) as i64;
+            let dy = (position.y - position.y.clamp(m.y, m.y + m.height as i32)) as i64;
+            dx * dx + dy * dy
+        })
+        .map(|(i, _)| i)
+}
+
+/// The window size a manifest asks for. JSON widgets keep theirs in logical
+/// pixels, the others in physical ones.
+pub fn manifest_size(manifest: &WidgetManifest) -> Option<Size> {
+    let dimensions = manifest.dimensions.as_ref()?;
+    Some(if manifest.widget_type == WidgetType::Json {
+        Size::Logical(LogicalSize::new(dimensions.width, dimensions.height))
+    } else {
+        Size::Physical(PhysicalSize::new(
+            dimensions.width as u32,
+            dimensions.height as u32,
+        ))
+    })
+}
+
+/// `size` in physical pixels on the monitor closest to `position`.
+pub fn physical_size_at(
+    monitors: &[MonitorInfo],
+    position: PhysicalPosition<i32>,
+    size: Size,
+) -> PhysicalSize<u32> {
+    let scale = nearest(monitors, position)
+        .map(|i| monitors[i].scale)
+        .unwrap_or(1.0);
+    size.to_physical(scale)
+}
+
+/// Keeps a window of `size` inside `monitor`, moving it as little as possible.
+pub fn clamp_to_monitor(
+    monitor: &MonitorInfo,
+    position: PhysicalPosition<i32>,
+    size: PhysicalSize<u32>,
+) -> PhysicalPosition<i32> {
+    let max_x = (monitor.x + monitor.width as i32 - size.width as i32).max(monitor.x);
+    let max_y = (monitor.y + monitor.height as i32 - size.height as i32).max(monitor.y);
+    PhysicalPosition {
+        x: position.x.clamp(monitor.x, max_x),
+        y: position.y.clamp(monitor.y, max_y),
+    }
+}
+
+pub fn clamp_to_nearest_monitor(
+    monitors: &[MonitorInfo],
+    position: PhysicalPosition<i32>,
+    size: PhysicalSize<u32>,
+) -> Option<PhysicalPosition<i32>> {
+    let monitor = &monitors[nearest(monitors, position)?];
+    Some(clamp_to_monitor(monitor, position, size))
+}
+
+/// Moves `position` from the monitor it was on in `from` to the same relative
+/// spot on the matching monitor in `to`.
+fn remap_position(
+    position: PhysicalPosition<i32>,
+    size: PhysicalSize<u32>,
+    from: &[MonitorInfo],
+    to: &[MonitorInfo],
+) -> Option<PhysicalPosition<i32>> {
+    let index = from
+        .iter()
+        .position(|m| contains(m, position))
+        .or_else(|| nearest(from, position))?;
+    let source = &from[index];
+    let target = to.get(index).or_else(|| to.first())?;
+
+    let fx = (position.x - source.x) as f64 / source.width.max(1) as f64;
+    let fy = (position.y - source.y) as f64 / source.height.max(1) as f64;
+    let remapped = PhysicalPosition {
+        x: target.x + (fx * target.width as f64).round() as i32,
+        y: target.y + (fy * target.height as f64).round() as i32,
+    };
+    Some(clamp_to_monitor(target, remapped, size))
+}
+
+/// Records `position` for the current monitor configuration.
+pub fn remember_position(
+    app: &AppHandle,
+    manifest: &mut WidgetManifest,
+    position: PhysicalPosition<i32>,
+) {
+    let monitors = current_monitors(app);
+    if monitors.is_empty() {
+        return;
+    }
+    manifest
+        .monitor_positions
+        .get_or_insert_with(Default::default)
+        .insert(
+            monitor_fingerprint(&monitors),
+            MonitorPosition {
+                x: position.x as f64,
+                y: position.y as f64,
+                monitors,
+            },
+        );
+}
+
+/// Moves `manifest` to `position` with `anchor`. The position remembered for
+/// the current monitor configuration and an anchor win over the last
+/// position when the widget opens, so they are replaced too.
+pub fn set_position(
+    app: &AppHandle,
+    manifest: &mut WidgetManifest,
+    position: PhysicalPosition<i32>,
+    anchor: Option<Anchor>,
+) {
+    manifest.position = Some(Position {
+        x: Some(position.x as f64),
+        y: Some(position.y as f64),
+    });
+    remember_position(app, manifest, position);
+    manifest.anchor = anchor;
+}
+
+/// Picks where to open a widget: its position for the current monitor
+/// configuration if it has one, otherwise its last position remapped from the
+/// configuration it was saved under.
+pub fn restore_position(
+    app: &AppHandle,
+    manifest: &WidgetManifest,
+    size: Size,
+) -> PhysicalPosition<i32> {
+    let monitors = current_monitors(app);
+    let saved = manifest.monitor_positions.as_ref();
+    let last = manifest.position.as_ref();
+    let last = PhysicalPosition {
+        x: last.and_then(|p| p.x).unwrap_or(30.0) as i32,
+        y: last.and_then(|p| p.y).unwrap_or(30.0) as i32,
+    };
+    let size = physical_size_at(&monitors, last, size);
+
+    if let Some(known) = saved.and_then(|s| s.get(&monitor_fingerprint(&monitors))) {
+        let position = PhysicalPosition {
+            x: known.x as i32,
+            y: known.y as i32,
+        };
+        if monitors.iter().any(|m| fits(m, position, size)) {
+            return position;
+        }
+    }
+
+    if monitors.iter().any(|m| fits(m, last, size)) {
+        return last;
+    }
+
+    // The configuration the last position was saved under
+    let previous = saved.and_then(|s| {
+        s.values()
+            .find(|p| p.x as i32 == last.x && p.y as i32 == last.y)
+    });
+    previous
+        .and_then(|p| remap_position(last, size, &p.monitors, &monitors))
+        .or_else(|| clamp_to_nearest_monitor(&monitors, last, size))
+        .unwrap_or(PhysicalPosition { x: 30, y: 30 })
+}
+
+/// Top-left corner of a window of `size` whose `point` sits on the same point of `area`.
+fn anchor_origin(
+    area: &MonitorInfo,
+    point: AnchorPoint,
+    size: PhysicalSize<u32>,
+) -> PhysicalPosition<i32> {
+    let free_x = area.width as i32 - size.width as i32;
+    let free_y = area.height as i32 - size.height as i32;
+    let (fx, fy) = match point {
+        AnchorPoint::TopLeft => (0, 0),
+        AnchorPoint::Top => (1, 0),
+        AnchorPoint::TopRight => (2, 0),
+        AnchorPoint::Left => (0, 1),
+        AnchorPoint::Center => (1, 1),
+        AnchorPoint::Right => (2, 1),
+        AnchorPoint::BottomLeft => (0, 2),
+        AnchorPoint::Bottom => (1, 2),
+        AnchorPoint::BottomRight => (2, 2),
+    };
+    PhysicalPosition {
+        x: area.x + free_x * fx / 2,
+        y: area.y + free_y * fy / 2,
+    }
+}
+
+/// Resolves an anchor against the current work areas.
+pub fn anchored_position(
+    app: &AppHandle,
+    anchor: &Anchor,
+    size: Size,
+) -> Option<PhysicalPosition<i32>> {
+    let areas = current_work_areas(app);
+    let area = areas
+        .get(anchor.monitor.unwrap_or(0))
+        .or_else(|| areas.first())?;
+    // Same unit as the size `anchor_for_position` is given when saving
+    let size = size.to_physical(area.scale);
+    let origin = anchor_origin(area, anchor.point, size);
+    let position = PhysicalPosition {
+        x: origin.x + (anchor.offset_x * area.scale).round() as i32,
+        y: origin.y + (anchor.offset_y * area.scale).round() as i32,
+    };
+    Some(clamp_to_monitor(area, position, size))
+}
+
+/// Re-expresses `position` relative to `point` of the work area the window is on.
+pub fn anchor_for_position(
+    app: &AppHandle,
+    point: AnchorPoint,
+    position: PhysicalPosition<i32>,
+    size: PhysicalSize<u32>,
+) -> Option<Anchor> {
+    let areas = current_work_areas(app);
+    let center = PhysicalPosition {
+        x: position.x + size.width as i32 / 2,
+        y: position.y + size.height as i32 / 2,
+    };
+    let index = areas
+        .iter()
+        .position(|a| contains(a, center))
+        .or_else(|| nearest(&areas, center))?;
+    let area = &areas[index];
+    let origin = anchor_origin(area, point, size);
+    Some(Anchor {
+        point,
+        offset_x: ((position.x - origin.x) as f64 / area.scale).round(),
+        offset_y: ((position.y - origin.y) as f64 / area.scale).round(),
+        monitor: Some(index),
+    })
+}
+
+pub struct SnapSettings {
+    /// Grid size in logical pixels, 0 turns grid snapping off
+    pub grid: u32,
+    /// Snap to the edges of other widgets and of the work area
+    pub edges: bool,
+    /// How close an edge has to be to snap, in logical pixels
+    pub threshold: u32,
+}
+
+/// Window rectangle, in physical pixels.
+#[derive(Clone, Copy)]
+pub struct Rect {
+    pub position: PhysicalPosition<i32>,
+    pub size: PhysicalSize<u32>,
+}
+
+/// Closest of `targets` to `value` within `threshold`.
+fn snap_axis(value: i32, targets: &[i32], threshold: i32) -> Option<i32> {
+    targets
+        .iter()
+        .copied()
+        .filter(|t| (t - value).abs() <= threshold)
+        .min_by_key(|t| (t - value).abs())
+}
+
+/// Snaps a window to nearby edges first, then to the grid.
+pub fn snap_position(
+    app: &AppHandle,
+    window: Rect,
+    others: &[Rect],
+    settings: &SnapSettings,
+) -> PhysicalPosition<i32> {
+    let areas = current_work_areas(app);
+    let Some(index) = nearest(&areas, window.position) else {
+        return window.position;
+    };
+    let area = &areas[index];
+    let (x, y) = (window.position.x, window.position.y);
+    let (w, h) = (window.size.width as i32, window.size.height as i32);
+
+    let mut snapped_x = None;
+    let mut snapped_y = None;
+    if settings.edges {
+        let threshold = (settings.threshold as f64 * area.scale).round() as i32;
+        let mut xs = vec![area.x, area.x + area.width as i32 - w];
+        let mut ys = vec![area.y, area.y + area.height as i32 - h];
+        for other in others {
+            let (ox, oy) = (other.position.x, other.position.y);
+            let (ow, oh) = (other.size.width as i32, other.size.height as i32);
+            // Line up with, or sit right next to, each edge of the other widget
+            xs.extend([ox, ox + ow, ox - w, ox + ow - w]);
+            ys.extend([oy, oy + oh, oy - h, oy + oh - h]);
+        }
+        snapped_x = snap_axis(x, &xs, threshold);
+        snapped_y = snap_axis(y, &ys, threshold);
+    }
+
+    let grid = (settings.grid as f64 * area.scale).round() as i32;
+    let to_grid = |value: i32, origin: i32| {
+        if grid > 0 {
+            origin + ((value - origin) as f64 / grid as f64).round() as i32 * grid
+        } else {
+            value
+        }
+    };
+    PhysicalPosition {
+        x: snapped_x.unwrap_or_else(|| to_grid(x, area.x)),
+        y: snapped_y.unwrap_or_else(|| to_grid(y, area.y)),
+    }
+}
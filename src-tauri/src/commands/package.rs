@@ -0,0 +1,351 @@
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+use tauri::{AppHandle, Manager};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::{
+    commands::{
+        signing::{sign_package, verify_package, PackageTrust, PackageVerification},
+        utils::{copy_dir_all, get_existing_keys, unique_key},
+    },
+    manifest::{WidgetManifest, WidgetType},
+    migration::{applied_migrations, migrate_manifest_value},
+    migrations::all_migrations,
+};
+
+pub const PACKAGE_EXTENSION: &str = "deltawidget";
+const PACKAGE_FORMAT_VERSION: u32 = 1;
+
+const META_ENTRY: &str = "meta.json";
+const MANIFEST_ENTRY: &str = "manifest.json";
+const ASSETS_PREFIX: &str = "assets/";
+const HTML_PREFIX: &str = "html/";
+/// Limits on what an import unpacks, so a small archive can not exhaust memory
+const MAX_ENTRY_SIZE: u64 = 50 * 1024 * 1024;
+const MAX_PACKAGE_SIZE: u64 = 200 * 1024 * 1024;
+const MAX_ENTRIES: usize = 10_000;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PackageMeta {
+    format_version: u32,
+    app_version: String,
+    exported_at: i64,
+    /// Manifest migrations the exporting install had applied.
+    migrations: Vec<String>,
+}
+
+/// Contents of a `.deltawidget` archive, keyed by entry name.
+#[derive(Default)]
+pub struct WidgetPackage {
+    pub entries: BTreeMap<String, Vec<u8>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedWidget {
+    pub key: String,
+    pub original_key: String,
+    pub path: String,
+    pub verification: PackageVerification,
+}
+
+/// Accepts either a widget folder or the path to its `manifest.json`.
+pub fn resolve_manifest_file(path: &str) -> PathBuf {
+    let path = PathBuf::from(path);
+    if path.is_dir() {
+        path.join("manifest.json")
+    } else {
+        path
+    }
+}
+
+fn collect_dir(
+    dir: &Path,
+    prefix: &str,
+    entries: &mut BTreeMap<String, Vec<u8>>,
+) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            collect_dir(&entry.path(), &format!("{name}/"), entries)?;
+        } else {
+            entries.insert(name, fs::read(entry.path())?);
+        }
+    }
+    Ok(())
+}
+
+pub fn build_package(app: &AppHandle, manifest_path: &Path) -> anyhow::Result<WidgetPackage> {
+    let manifest = WidgetManifest::load(manifest_path)?;
+    let cache_dir = app.path().app_cache_dir()?;
+    let mut package = WidgetPackage::default();
+
+    let meta = PackageMeta {
+        format_version: PACKAGE_FORMAT_VERSION,
+        app_version: app.package_info().version.to_string(),
+        exported_at: chrono::Utc::now().timestamp_millis(),
+        migrations: applied_migrations(app)?,
+    };
+    package
+        .entries
+        .insert(META_ENTRY.into(), serde_json::to_vec_pretty(&meta)?);
+    package
+        .entries
+        .insert(MANIFEST_ENTRY.into(), serde_json::to_vec_pretty(&manifest)?);
+
+    for key in manifest.file_asset_keys() {
+        let asset_path = cache_dir.join("assets").join(&key);
+        match fs::read(&asset_path) {
+            Ok(bytes) => {
+                package
+                    .entries
+                    .insert(format!("{ASSETS_PREFIX}{key}"), bytes);
+            }
+            Err(e) => eprintln!("Skipping asset {}: {}", asset_path.display(), e),
+        }
+    }
+
+    if manifest.widget_type == WidgetType::Html {
+        // Prefer the author's source folder, fall back to the cached copy.
+        let source = manifest
+            .file
+            .as_ref()
+            .map(PathBuf::from)
+            .filter(|p| p.join("index.html").is_file())
+            .unwrap_or_else(|| cache_dir.join("files").join(&manifest.key));
+        if !source.join("index.html").is_file() {
+            bail!("No index.html found for HTML widget {}", manifest.key);
+        }
+        collect_dir(&source, HTML_PREFIX, &mut package.entries)?;
+    }
+
+    Ok(package)
+}
+
+pub fn write_package(package: &WidgetPackage, destination: &Path) -> anyhow::Result<()> {
+    let mut zip = ZipWriter::new(File::create(destination)?);
+    let options = SimpleFileOptions::default();
+    for (name, bytes) in &package.entries {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(bytes)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+fn read_entries<R: Read + Seek>(reader: R) -> anyhow::Result<BTreeMap<String, Vec<u8>>> {
+    let mut archive = ZipArchive::new(reader)?;
+    if archive.len() > MAX_ENTRIES {
+        bail!("Package has more than {} entries", MAX_ENTRIES);
+    }
+    let mut entries = BTreeMap::new();
+    let mut total: u64 = 0;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        // Reject absolute paths and `..` so nothing is written outside the widget
+        let Some(name) = entry.enclosed_name() else {
+            bail!("Unsafe path in package: {}", entry.name());
+        };
+        let name = name
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let too_large = || {
+            anyhow!(
+                "{} is larger than {} MB",
+                name,
+                MAX_ENTRY_SIZE / 1024 / 1024
+            )
+        };
+        if entry.size() > MAX_ENTRY_SIZE {
+            return Err(too_large());
+        }
+        // The declared size can lie, so the read is capped as well
+        let mut bytes = Vec::new();
+        (&mut entry)
+            .take(MAX_ENTRY_SIZE + 1)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() as u64 > MAX_ENTRY_SIZE {
+            return Err(too_large());
+        }
+        total += bytes.len() as u64;
+        if total > MAX_PACKAGE_SIZE {
+            bail!(
+                "Package unpacks to more than {} MB",
+                MAX_PACKAGE_SIZE / 1024 / 1024
+            );
+        }
+        entries.insert(name, bytes);
+    }
+    Ok(entries)
+}
+
+pub fn read_package(path: &Path) -> anyhow::Result<WidgetPackage> {
+    Ok(WidgetPackage {
+        entries: read_entries(File::open(path)?)?,
+    })
+}
+
+fn write_widget_files(
+    package: &WidgetPackage,
+    manifest: &mut WidgetManifest,
+    widget_dir: &Path,
+    cache_dir: &Path,
+) -> anyhow::Result<()> {
+    let assets_dir = cache_dir.join("assets");
+    fs::create_dir_all(&assets_dir)?;
+    for key in manifest.file_asset_keys() {
+        let destination = assets_dir.join(&key);
+        // Asset keys are unique ids, an existing file is the same asset
+        if destination.exists() {
+            continue;
+        }
+        match package.entries.get(&format!("{ASSETS_PREFIX}{key}")) {
+            Some(bytes) => fs::write(destination, bytes)?,
+            None => eprintln!("Package is missing asset {}", key),
+        }
+    }
+
+    if manifest.widget_type == WidgetType::Html {
+        let html_dir = widget_dir.join("html");
+        for (name, bytes) in &package.entries {
+            let Some(relative) = name.strip_prefix(HTML_PREFIX) else {
+                continue;
+            };
+            let destination = relative.split('/').fold(html_dir.clone(), |p, c| p.join(c));
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(destination, bytes)?;
+        }
+        if !html_dir.join("index.html").is_file() {
+            bail!("Package has no html/index.html");
+        }
+        copy_dir_all(&html_dir, cache_dir.join("files").join(&manifest.key))?;
+        manifest.file = Some(html_dir.to_string_lossy().to_string());
+    }
+
+    manifest.save(&widget_dir.join("manifest.json"))?;
+    Ok(())
+}
+
+pub fn install_package(
+    app: &AppHandle,
+    package: &WidgetPackage,
+    allow_unverified: bool,
+) -> anyhow::Result<ImportedWidget> {
+    // Checked before anything is written into widgets/
+    let verification = verify_package(app, package);
+    match verification.status {
+        PackageTrust::Verified => {}
+        PackageTrust::Tampered => bail!("Package signature does not match its contents"),
+        PackageTrust::Unsigned | PackageTrust::Untrusted if !allow_unverified => {
+            bail!("Package is not signed by a trusted publisher")
+        }
+        _ => {}
+    }
+
+    let meta: PackageMeta = serde_json::from_slice(
+        package
+            .entries
+            .get(META_ENTRY)
+            .ok_or_else(|| anyhow!("Not a widget package: missing {META_ENTRY}"))?,
+    )?;
+    if meta.format_version > PACKAGE_FORMAT_VERSION {
+        bail!(
+            "Package was created by a newer version of Delta Widgets ({})",
+            meta.app_version
+        );
+    }
+
+    let mut json: Value = serde_json::from_slice(
+        package
+            .entries
+            .get(MANIFEST_ENTRY)
+            .ok_or_else(|| anyhow!("Not a widget package: missing {MANIFEST_ENTRY}"))?,
+    )?;
+    migrate_manifest_value(&mut json, all_migrations(), &meta.migrations);
+    let mut manifest = WidgetManifest::from_value(json)?;
+
+    let widgets_dir = app
+        .path()
+        .resolve("widgets", tauri::path::BaseDirectory::AppData)?;
+    let mut existing_keys = get_existing_keys(app, String::new());
+    for entry in fs::read_dir(&widgets_dir)?.flatten() {
+        existing_keys.insert(entry.file_name().to_string_lossy().to_string(), None);
+    }
+
+    let original_key = manifest.key.clone();
+    manifest.key = unique_key(&original_key, &existing_keys);
+    manifest.visible = Some(false);
+
+    let widget_dir = widgets_dir.join(&manifest.key);
+    fs::create_dir_all(&widget_dir)?;
+    let cache_dir = app.path().app_cache_dir()?;
+    if let Err(e) = write_widget_files(package, &mut manifest, &widget_dir, &cache_dir) {
+        let _ = fs::remove_dir_all(&widget_dir);
+        return Err(e);
+    }
+
+    Ok(ImportedWidget {
+        key: manifest.key,
+        original_key,
+        path: widget_dir.to_string_lossy().to_string(),
+        verification,
+    })
+}
+
+#[tauri::command]
+pub async fn export_widget(
+    app: AppHandle,
+    manifest_path: String,
+    destination: String,
+    sign: Option<bool>,
+) -> Result<String, String> {
+    let mut package =
+        build_package(&app, &resolve_manifest_file(&manifest_path)).map_err(|e| e.to_string())?;
+    if sign.unwrap_or(true) {
+        sign_package(&app, &mut package).map_err(|e| e.to_string())?;
+    }
+
+    let mut destination = PathBuf::from(destination);
+    if destination.extension().is_none() {
+        destination.set_extension(PACKAGE_EXTENSION);
+    }
+    write_package(&package, &destination).map_err(|e| e.to_string())?;
+
+    Ok(destination.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub async fn import_widget(
+    app: AppHandle,
+    package_path: String,
+    allow_unverified: Option<bool>,
+) -> Result<ImportedWidget, String> {
+    let package = read_package(Path::new(&package_path)).map_err(|e| e.to_string())?;
+    install_package(&app, &package, allow_unverified.unwrap_or(false)).map_err(|e| e.to_string())
+}
+
+/// Reports the signature status of a package without installing it.
+#[tauri::command]
+pub async fn verify_widget_package(
+    app: AppHandle,
+    package_path: String,
+) -> Result<PackageVerification, String> {
+    let package = read_package(Path::new(&package_path)).map_err(|e| e.to_string())?;
+    Ok(verify_package(&app, &package))
+}
@@ -0,0 +1,399 @@
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+};
+use tauri::{AppHandle, Manager, WebviewWindow};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+use crate::{
+    commands::store::{get_or_create_store, store_path, write_to_store, KVPair},
+    manifest::{WidgetManifest, WidgetType, PERMISSION_SCOPES},
+    persist,
+    providers::{widget_key_for, ProviderContext},
+    template,
+};
+
+/// Widget key -> `{ "scopes": [...], "granted": bool }`, the scopes the user
+/// answered for. A widget asking for other scopes is asked again.
+const CONSENTS_KEY: &str = "widgetPermissions";
+
+/// Commands every HTML widget may call
+const BASE_COMMANDS: [&str; 2] = ["list_variables", "unsubscribe_template"];
+/// Commands that take a `widgetKey`, allowed for the calling widget's own key
+const OWN_KEY_COMMANDS: [&str; 2] = ["list_shell_commands", "list_widget_secrets"];
+/// Commands that render templates, allowed when the scopes of the namespaces
+/// they use are
+const TEMPLATE_COMMANDS: [&str; 2] = ["render_template", "subscribe_template"];
+
+/// Scope, what it allows in words, and the commands it allows
+const SCOPE_COMMANDS: [(&str, &str, &[&str]); 9] = [
+    (
+        "media:read",
+        "See what is playing and your play history",
+        &[
+            "get_media",
+            "start_media_listener_cmd",
+            "stop_media_listener_cmd",
+            "get_media_metadata",
+            "query_media_history",
+        ],
+    ),
+    (
+        "media:control",
+        "Play, pause, skip and seek media",
+        &["media_action"],
+    ),
+    (
+        "audio:read",
+        "Listen to the sound your device plays",
+        &[
+            "start_audio_capture",
+            "stop_audio_capture",
+            "restart_audio_capture",
+            "get_current_device_cmd",
+        ],
+    ),
+    (
+        "system:read",
+        "Read CPU, memory, disk, battery and network usage",
+        &["get_system_info"],
+    ),
+    (
+        "store",
+        "Save its own settings in the app",
+        &["write_to_store_cmd"],
+    ),
+    (
+        "keyring",
+        "Keep its own passwords in the system keyring",
+        &[
+            "get_widget_password",
+            "set_widget_password",
+            "delete_widget_password",
+        ],
+    ),
+    ("file:read", "Read the data files it lists", &[]),
+    ("http:read", "Request the web addresses it lists", &[]),
+    ("cmd:read", "Show the output of the commands it lists", &[]),
+];
+
+/// Provider namespaces whose data needs a scope
+const NAMESPACE_SCOPES: [(&str, &str); 6] = [
+    ("media", "media:read"),
+    ("audio", "audio:read"),
+    ("system", "system:read"),
+    ("file", "file:read"),
+    ("http", "http:read"),
+    ("cmd", "cmd:read"),
+];
+
+struct WindowGrant {
+    widget_key: String,
+    scopes: BTreeSet<String>,
+}
+
+/// Windows of HTML widgets and the scopes they were opened with. Other
+/// windows show the app's own pages and are not limited.
+static WINDOW_GRANTS: Mutex<BTreeMap<String, WindowGrant>> = Mutex::new(BTreeMap::new());
+
+pub fn is_sandboxed(manifest: &WidgetManifest) -> bool {
+    matches!(manifest.widget_type, WidgetType::Html | WidgetType::Url)
+}
+
+fn declared_scopes(manifest: &WidgetManifest) -> BTreeSet<String> {
+    manifest
+        .permissions
+        .iter()
+        .flatten()
+        .filter(|scope| PERMISSION_SCOPES.contains(&scope.as_str()))
+        .cloned()
+        .collect()
+}
+
+fn consents(app: &AppHandle) -> anyhow::Result<Map<String, Value>> {
+    let store = get_or_create_store(app)?;
+    Ok(store
+        .get(CONSENTS_KEY)
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default())
+}
+
+/// `Some(granted)` when the user answered for exactly these scopes.
+fn consent(app: &AppHandle, widget_key: &str, scopes: &BTreeSet<String>) -> Option<bool> {
+    let consents = consents(app)
+        .map_err(|e| eprintln!("Error reading widget permissions: {}", e))
+        .ok()?;
+    let consent = consents.get(widget_key)?;
+    let answered: BTreeSet<String> = consent
+        .get("scopes")?
+        .as_array()?
+        .iter()
+        .filter_map(|s| s.as_str().map(str::to_string))
+        .collect();
+    if &answered != scopes {
+        return None;
+    }
+    consent.get("granted").and_then(Value::as_bool)
+}
+
+fn set_consent(
+    app: &AppHandle,
+    widget_key: &str,
+    scopes: &BTreeSet<String>,
+    granted: bool,
+) -> anyhow::Result<()> {
+    // Answers in two windows at once must not drop each other
+    persist::with_lock(&store_path(app)?, || {
+        let mut consents = consents(app)?;
+        consents.insert(
+            widget_key.to_string(),
+            json!({ "scopes": scopes, "granted": granted }),
+        );
+        write_to_store(
+            app,
+            vec![KVPair {
+                key: CONSENTS_KEY.to_string(),
+                value: Value::Object(consents),
+            }],
+        )
+    })
+}
+
+/// A widget installed again under the same key is asked again.
+pub fn forget_consent(app: &AppHandle, widget_key: &str) -> anyhow::Result<()> {
+    persist::with_lock(&store_path(app)?, || {
+        let mut consents = consents(app)?;
+        if consents.remove(widget_key).is_none() {
+            return Ok(());
+        }
+        write_to_store(
+            app,
+            vec![KVPair {
+                key: CONSENTS_KEY.to_string(),
+                value: Value::Object(consents),
+            }],
+        )
+    })
+}
+
+/// Scopes the widget may use right now. JSON widgets are not limited and get
+/// every scope.
+pub fn granted_scopes(app: &AppHandle, manifest: &WidgetManifest) -> BTreeSet<String> {
+    if !is_sandboxed(manifest) {
+        return PERMISSION_SCOPES.iter().map(|s| s.to_string()).collect();
+    }
+    let scopes = declared_scopes(manifest);
+    match consent(app, &manifest.key, &scopes) {
+        Some(true) => scopes,
+        _ => BTreeSet::new(),
+    }
+}
+
+/// For requests that name a widget rather than come from its window, like the
+/// local API.
+pub fn widget_allows(app: &AppHandle, widget_key: &str, scope: &str) -> bool {
+    ProviderContext::for_widget(app, Some(widget_key))
+        .manifest
+        .is_some_and(|manifest| granted_scopes(app, &manifest).contains(scope))
+}
+
+/// Asks once for the scopes an HTML or URL widget declares, again when they
+/// change. Resolves to the scopes the widget may use.
+pub async fn ensure_consent(app: &AppHandle, manifest: &WidgetManifest) -> BTreeSet<String> {
+    let scopes = declared_scopes(manifest);
+    if !is_sandboxed(manifest) || scopes.is_empty() {
+        return granted_scopes(app, manifest);
+    }
+    if let Some(granted) = consent(app, &manifest.key, &scopes) {
+        return if granted { scopes } else { BTreeSet::new() };
+    }
+
+    let label = manifest.label.as_deref().unwrap_or(&manifest.key);
+    let wanted: Vec<String> = SCOPE_COMMANDS
+        .iter()
+        .filter(|(scope, ..)| scopes.contains(*scope))
+        .map(|(_, description, _)| format!("• {}", description))
+        .collect();
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    app.dialog()
+        .message(format!("Allow {} to:\n\n{}", label, wanted.join("\n")))
+        .title("Widget permissions")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancel)
+        .show(move |allowed| {
+            let _ = sender.send(allowed);
+        });
+    let granted = receiver.await.unwrap_or(false);
+    if let Err(e) = set_consent(app, &manifest.key, &scopes, granted) {
+        eprintln!("Error saving widget permissions: {}", e);
+    }
+    if granted {
+        scopes
+    } else {
+        BTreeSet::new()
+    }
+}
+
+/// Records what the window of `manifest` may do, before it is created.
+pub fn register_window(label: &str, manifest: &WidgetManifest, scopes: BTreeSet<String>) {
+    let mut grants = WINDOW_GRANTS.lock().unwrap();
+    if !is_sandboxed(manifest) {
+        grants.remove(label);
+        return;
+    }
+    grants.insert(
+        label.to_string(),
+        WindowGrant {
+            widget_key: manifest.key.clone(),
+            scopes,
+        },
+    );
+}
+
+fn template_scopes(template: &str) -> impl Iterator<Item = &'static str> {
+    let namespaces = template::namespaces(template);
+    NAMESPACE_SCOPES
+        .into_iter()
+        .filter(move |(namespace, _)| namespaces.contains(*namespace))
+        .map(|(_, scope)| scope)
+}
+
+/// Whether the window `label` may call `command` with `args`. Runs before
+/// every app command.
+pub fn check_command(label: &str, command: &str, args: &Value) -> Result<(), String> {
+    let grants = WINDOW_GRANTS.lock().unwrap();
+    let Some(grant) = grants.get(label) else {
+        return Ok(());
+    };
+    let denied = |scope: Option<&str>| {
+        let message = match scope {
+            Some(scope) => format!(
+                "Widget {} needs the `{}` permission to call {}",
+                grant.widget_key, scope, command
+            ),
+            None => format!("Widgets can not call {}", command),
+        };
+        eprintln!("{}", message);
+        Err(message)
+    };
+
+    if BASE_COMMANDS.contains(&command) {
+        return Ok(());
+    }
+    if OWN_KEY_COMMANDS.contains(&command) {
+        return match args.get("widgetKey").and_then(Value::as_str) {
+            Some(key) if key == grant.widget_key => Ok(()),
+            _ => denied(None),
+        };
+    }
+    if TEMPLATE_COMMANDS.contains(&command) {
+        // Variables of another widget, like its `http` sources, are not
+        // the caller's to read
+        let own_key = match args.get("widgetKey") {
+            None | Some(Value::Null) => true,
+            Some(key) => key.as_str() == Some(grant.widget_key.as_str()),
+        };
+        if !own_key {
+            return denied(None);
+        }
+        let template = args.get("template").and_then(Value::as_str).unwrap_or("");
+        return match template_scopes(template).find(|scope| !grant.scopes.contains(*scope)) {
+            Some(scope) => denied(Some(scope)),
+            None => Ok(()),
+        };
+    }
+    let Some((scope, ..)) = SCOPE_COMMANDS
+        .iter()
+        .find(|(_, _, commands)| commands.contains(&command))
+    else {
+        return denied(None);
+    };
+    if !grant.scopes.contains(*scope) {
+        return denied(Some(scope));
+    }
+    // The rest of the store belongs to the app, like command approvals
+    if *scope == "store" {
+        let prefix = format!("{}:", grant.widget_key);
+        let own_keys = args
+            .get("pairs")
+            .and_then(Value::as_array)
+            .is_some_and(|pairs| {
+                pairs.iter().all(|pair| {
+                    pair.get("key")
+                        .and_then(Value::as_str)
+                        .is_some_and(|key| key.starts_with(&prefix))
+                })
+            });
+        if !own_keys {
+            let message = format!("Widgets can only write store keys starting with `{prefix}`");
+            eprintln!("{}", message);
+            return Err(message);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct WidgetPermission {
+    pub scope: String,
+    pub description: String,
+    pub granted: bool,
+}
+
+/// Scopes a widget declares and whether it may use them.
+#[tauri::command]
+pub fn list_widget_permissions(
+    app: AppHandle,
+    widget_key: String,
+) -> Result<Vec<WidgetPermission>, String> {
+    let manifest = ProviderContext::for_widget(&app, Some(&widget_key))
+        .manifest
+        .ok_or_else(|| format!("Widget {} not found", widget_key))?;
+    let granted = granted_scopes(&app, &manifest);
+    Ok(SCOPE_COMMANDS
+        .iter()
+        .filter(|(scope, ..)| declared_scopes(&manifest).contains(*scope))
+        .map(|(scope, description, _)| WidgetPermission {
+            scope: scope.to_string(),
+            description: description.to_string(),
+            granted: granted.contains(*scope),
+        })
+        .collect())
+}
+
+/// Grants or revokes every scope the widget declares. Open windows of the
+/// widget follow right away.
+#[tauri::command]
+pub fn set_widget_permissions(
+    app: AppHandle,
+    window: WebviewWindow,
+    widget_key: String,
+    granted: bool,
+) -> Result<(), String> {
+    if window.label() != "main" {
+        return Err("Permissions can only be changed from the main window".to_string());
+    }
+    let manifest = ProviderContext::for_widget(&app, Some(&widget_key))
+        .manifest
+        .ok_or_else(|| format!("Widget {} not found", widget_key))?;
+    let scopes = declared_scopes(&manifest);
+    set_consent(&app, &widget_key, &scopes, granted).map_err(|e| e.to_string())?;
+
+    let labels: Vec<String> = app
+        .webview_windows()
+        .into_keys()
+        .filter(|label| widget_key_for(label) == Some(widget_key.as_str()))
+        .collect();
+    for label in labels {
+        let scopes = if granted {
+            scopes.clone()
+        } else {
+            BTreeSet::new()
+        };
+        register_window(&label, &manifest, scopes);
+    }
+    Ok(())
+}
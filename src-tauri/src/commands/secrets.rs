@@ -0,0 +1,244 @@
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+};
+use tauri::{AppHandle, WebviewWindow};
+
+use crate::{
+    commands::vault,
+    get_custom_server_port,
+    manifest::WidgetManifest,
+    providers::ProviderContext,
+    template::{self, Segment},
+};
+
+/// Only these windows may write secrets, widgets can not see or change them.
+const SECRET_WINDOWS: [&str; 2] = ["main", "creator"];
+
+/// API tokens read from the keyring, so requests do not each go to it
+static API_TOKENS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+fn secret_name(widget_key: &str, name: &str) -> String {
+    format!("widget-secret:{widget_key}:{name}")
+}
+
+pub fn get_widget_secret(
+    app: &AppHandle,
+    widget_key: &str,
+    name: &str,
+) -> Result<Option<String>, String> {
+    vault::get(app, &secret_name(widget_key, name))
+}
+
+/// Replaces `{{secret:<name>}}` in `text` with what `secret` finds for the
+/// name, usually `get_widget_secret`. Other variables are kept as written.
+pub fn fill_secrets(
+    text: &str,
+    secret: impl Fn(&str) -> Result<Option<String>, String>,
+) -> Result<String, String> {
+    let mut filled = String::new();
+    for segment in template::parse(text) {
+        match segment {
+            Segment::Variable {
+                namespace: "secret",
+                argument: Some(name),
+            } => match secret(name)? {
+                Some(secret) => filled.push_str(&secret),
+                None => return Err(format!("Secret {} is not set", name)),
+            },
+            Segment::Variable {
+                namespace,
+                argument: Some(argument),
+            } => filled.push_str(&format!("{{{{{namespace}:{argument}}}}}")),
+            Segment::Variable {
+                namespace,
+                argument: None,
+            } => filled.push_str(&format!("{{{{{namespace}}}}}")),
+            Segment::Expression(_) => {
+                return Err("Only {{secret:<name>}} can be used here".to_string())
+            }
+            Segment::Text(text) => filled.push_str(text),
+        }
+    }
+    Ok(filled)
+}
+
+/// Names of the secrets the manifest's http sources use.
+pub fn secret_names(manifest: &WidgetManifest) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    for source in manifest.http_sources.iter().flatten() {
+        let texts =
+            std::iter::once(&source.url).chain(source.headers.iter().flat_map(|h| h.values()));
+        for text in texts {
+            for segment in template::parse(text) {
+                if let Segment::Variable {
+                    namespace: "secret",
+                    argument: Some(name),
+                } = segment
+                {
+                    names.insert(name.to_string());
+                }
+            }
+        }
+    }
+    names
+}
+
+fn api_token_name(widget_key: &str) -> String {
+    format!("widget-api-token:{widget_key}")
+}
+
+/// The token a widget's pages send to the local API, `None` until one is
+/// created in the app.
+pub fn widget_api_token(app: &AppHandle, widget_key: &str) -> Result<Option<String>, String> {
+    if let Some(token) = API_TOKENS.lock().unwrap().get(widget_key) {
+        return Ok(Some(token.clone()));
+    }
+    let token = vault::get(app, &api_token_name(widget_key))?;
+    if let Some(token) = &token {
+        API_TOKENS
+            .lock()
+            .unwrap()
+            .insert(widget_key.to_string(), token.clone());
+    }
+    Ok(token)
+}
+
+/// The widget an API token belongs to. Tokens are `<key>.<random hex>`.
+pub fn widget_for_api_token(app: &AppHandle, token: &str) -> Option<String> {
+    let (widget_key, _) = token.rsplit_once('.')?;
+    let expected = widget_api_token(app, widget_key).ok()??;
+    // Compares every byte, so timing does not tell how much matched
+    let matches = expected.len() == token.len()
+        && expected
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    matches.then(|| widget_key.to_string())
+}
+
+fn create_api_token(app: &AppHandle, widget_key: &str) -> Result<String, String> {
+    let random: [u8; 16] = rand::random();
+    let hex: String = random.iter().map(|b| format!("{:02x}", b)).collect();
+    let token = format!("{widget_key}.{hex}");
+    vault::set(app, &api_token_name(widget_key), &token)?;
+    API_TOKENS
+        .lock()
+        .unwrap()
+        .insert(widget_key.to_string(), token.clone());
+    Ok(token)
+}
+
+pub fn delete_widget_secrets(app: &AppHandle, manifest: &WidgetManifest) {
+    API_TOKENS.lock().unwrap().remove(&manifest.key);
+    if let Err(e) = vault::delete(app, &api_token_name(&manifest.key)) {
+        eprintln!("Error deleting API token of {}: {}", manifest.key, e);
+    }
+    for name in secret_names(manifest) {
+        if let Err(e) = vault::delete(app, &secret_name(&manifest.key, &name)) {
+            eprintln!("Error deleting secret {}: {}", name, e);
+        }
+    }
+}
+
+fn ensure_secret_window(window: &WebviewWindow) -> Result<(), String> {
+    if !SECRET_WINDOWS.contains(&window.label()) {
+        return Err("Secrets can only be changed from the app".to_string());
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct WidgetSecretInfo {
+    pub name: String,
+    pub set: bool,
+}
+
+/// Secrets a widget uses and whether they have a value. Values are never
+/// returned.
+#[tauri::command]
+pub fn list_widget_secrets(
+    app: AppHandle,
+    widget_key: String,
+) -> Result<Vec<WidgetSecretInfo>, String> {
+    let manifest = ProviderContext::for_widget(&app, Some(&widget_key))
+        .manifest
+        .ok_or_else(|| format!("Widget {} not found", widget_key))?;
+    secret_names(&manifest)
+        .into_iter()
+        .map(|name| {
+            Ok(WidgetSecretInfo {
+                set: get_widget_secret(&app, &widget_key, &name)?.is_some(),
+                name,
+            })
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn set_widget_secret(
+    app: AppHandle,
+    window: WebviewWindow,
+    widget_key: String,
+    name: String,
+    value: String,
+) -> Result<(), String> {
+    ensure_secret_window(&window)?;
+    vault::set(&app, &secret_name(&widget_key, &name), &value)
+}
+
+#[tauri::command]
+pub fn delete_widget_secret(
+    app: AppHandle,
+    window: WebviewWindow,
+    widget_key: String,
+    name: String,
+) -> Result<(), String> {
+    ensure_secret_window(&window)?;
+    vault::delete(&app, &secret_name(&widget_key, &name))
+}
+
+/// Where pages that can not use IPC reach the local API, and the token
+/// they send.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WidgetApiAccess {
+    /// Changes when the app restarts, the server port is picked at startup
+    pub base_url: String,
+    pub token: String,
+}
+
+fn api_access(token: String) -> WidgetApiAccess {
+    WidgetApiAccess {
+        base_url: format!("http://localhost:{}/api/v1", get_custom_server_port()),
+        token,
+    }
+}
+
+/// The widget's API access, creating its token when it has none yet.
+#[tauri::command]
+pub fn get_widget_api_token(
+    app: AppHandle,
+    window: WebviewWindow,
+    widget_key: String,
+) -> Result<WidgetApiAccess, String> {
+    ensure_secret_window(&window)?;
+    let token = match widget_api_token(&app, &widget_key)? {
+        Some(token) => token,
+        None => create_api_token(&app, &widget_key)?,
+    };
+    Ok(api_access(token))
+}
+
+/// Replaces the widget's API token, pages using the old one lose access.
+#[tauri::command]
+pub fn reset_widget_api_token(
+    app: AppHandle,
+    window: WebviewWindow,
+    widget_key: String,
+) -> Result<WidgetApiAccess, String> {
+    ensure_secret_window(&window)?;
+    create_api_token(&app, &widget_key).map(api_access)
+}
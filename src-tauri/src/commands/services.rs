@@ -1,12 +1,19 @@
 use serde::Serialize;
-use serde_json::{json, Value};
-use std::fs;
+use serde_json::Value;
+use std::{fs, path::Path};
 use tauri::{Emitter, Manager};
 
 #[cfg(target_os = "windows")]
 use window_vibrancy::apply_mica;
 
-use crate::commands::utils::{compare_if_no_thumb, copy_dir_all};
+use crate::{
+    commands::{
+        history::{record_revision, RevisionSource},
+        utils::{compare_if_no_thumb, copy_dir_all, decode_path_arg},
+    },
+    manifest::{ManifestError, WidgetManifest, WidgetType},
+    persist,
+};
 
 #[tauri::command]
 pub async fn copy_custom_assets(app: tauri::AppHandle, key: String, path: String) {
@@ -161,7 +168,7 @@ pub async fn get_all_widgets(app: tauri::AppHandle) -> Result<Vec<WidgetWithMeta
                         return Err(err.to_string());
                     }
                 }
-                let entries = fs::read_dir(path).unwrap();
+                let entries = fs::read_dir(path).map_err(|e| e.to_string())?;
                 for entry in entries {
                     let entry = match entry {
                         Ok(e) => e,
@@ -179,23 +186,14 @@ pub async fn get_all_widgets(app: tauri::AppHandle) -> Result<Vec<WidgetWithMeta
                         continue;
                     }
 
-                    let content = match fs::read_to_string(&manifest_path) {
-                        Ok(c) => c,
-                        Err(_) => continue,
-                    };
-                    let mut manifest_json: serde_json::Value = match serde_json::from_str(&content)
-                    {
-                        Ok(m) => m,
-                        Err(_) => continue,
-                    };
-                    if let Some(obj) = manifest_json.as_object_mut() {
-                        obj.remove("elements");
-                        obj.remove("dimensions");
-                        obj.remove("position");
-                        obj.remove("customFields");
-                        obj.remove("customAssets");
-                        obj.remove("theme");
-                    }
+                    let manifest_json =
+                        match WidgetManifest::load(&manifest_path).and_then(|m| m.lite()) {
+                            Ok(m) => m,
+                            Err(e) => {
+                                eprintln!("Skipping {}: {}", manifest_path.display(), e);
+                                continue;
+                            }
+                        };
 
                     let metadata = match fs::metadata(&manifest_path) {
                         Ok(m) => m,
@@ -234,17 +232,18 @@ pub async fn update_manifest_value(
     value: Value,
     path: String,
 ) -> Result<String, String> {
-    let clean_path = serde_json::from_str::<String>(&path).unwrap();
-    let config_content = fs::read_to_string(&clean_path).unwrap();
-
-    let mut config: Value = serde_json::from_str(&config_content).map_err(|e| e.to_string())?;
-
-    let key = config
-        .get("key")
-        .and_then(Value::as_str)
-        .unwrap()
-        .to_string();
-    let label = format!("widget-{}", key);
+    let clean_path = decode_path_arg(&path)?;
+    let manifest_path = Path::new(&clean_path);
+    // Write the updated JSON back to the file
+    let (previous, config, json_string) = persist::with_lock(manifest_path, || {
+        let previous = WidgetManifest::load(manifest_path)?;
+        let mut config = previous.clone();
+        config.set_field(&field, value.clone())?;
+        let json_string = config.save(manifest_path)?;
+        Ok::<_, ManifestError>((previous, config, json_string))
+    })
+    .map_err(|e| e.to_string())?;
+    let label = config.window_label();
 
     if field == "alwaysOnTop" {
         if let (Some(value), Some(window)) = (value.as_bool(), app.get_webview_window(&label)) {
@@ -257,32 +256,27 @@ pub async fn update_manifest_value(
         }
     }
 
-    if field == "pinned" {
-        let widget_type = config
-            .get("widgetType")
-            .and_then(Value::as_str)
-            .unwrap()
-            .to_string();
-        if widget_type == "url" {
-            if let (Some(value), Some(window)) = (value.as_bool(), app.get_webview_window(&label)) {
-                if let Err(err) = window.set_decorations(!value) {
-                    eprintln!("Error setting decorations: {}", err);
-                };
+    if field == "pinned" && config.widget_type == WidgetType::Url {
+        if let (Some(value), Some(window)) = (value.as_bool(), app.get_webview_window(&label)) {
+            if let Err(err) = window.set_decorations(!value) {
+                eprintln!("Error setting decorations: {}", err);
             };
-        }
-    }
-
-    if let Value::Object(ref mut map) = config {
-        map.insert(field, json!(value));
+        };
     }
 
-    // Write the updated JSON back to the file
-    if let Ok(json_string) = serde_json::to_string_pretty(&config) {
-        fs::write(&clean_path, &json_string).map_err(|e| e.to_string())?;
-        app.emit_to(label, "update-manifest", 1)
-            .map_err(|e| e.to_string())?;
-        return Ok(json_string);
+    if let Err(e) = record_revision(
+        &app,
+        manifest_path,
+        Some(&previous),
+        &config,
+        RevisionSource::Update,
+        None,
+    )
+    .await
+    {
+        eprintln!("Error recording manifest revision: {}", e);
     }
-
-    Ok("".to_string())
+    app.emit_to(label, "update-manifest", 1)
+        .map_err(|e| e.to_string())?;
+    Ok(json_string)
 }
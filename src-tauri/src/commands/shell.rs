@@ -0,0 +1,222 @@
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+use tauri::{AppHandle, WebviewWindow};
+
+use crate::{
+    commands::store::{get_or_create_store, store_path, write_to_store, KVPair},
+    manifest::ShellCommand,
+    persist,
+    providers::ProviderContext,
+};
+
+/// Widget key -> command id -> fingerprint of the approved command
+const APPROVED_COMMANDS_KEY: &str = "approvedShellCommands";
+
+/// Bare names are looked up on `PATH`, relative paths are resolved inside
+/// the widget folder `dir` and may not leave it.
+pub fn resolve_program(dir: &Path, program: &str) -> Result<PathBuf, String> {
+    let path = Path::new(program);
+    if path.is_absolute() || path.components().count() == 1 {
+        return Ok(path.to_path_buf());
+    }
+    if path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(format!("{} is outside the widget folder", program));
+    }
+    Ok(dir.join(path))
+}
+
+/// Hash of what would run, so editing a command in the manifest, or a
+/// script shipped in the widget folder `dir`, needs a new approval. That
+/// includes scripts passed as arguments, like `python status.py`.
+pub fn command_fingerprint(dir: &Path, command: &ShellCommand) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(json!([command.command, command.args]).to_string());
+    if let Ok(program) = resolve_program(dir, &command.command) {
+        if program.starts_with(dir) {
+            match fs::read(&program) {
+                Ok(contents) => hasher.update(Sha256::digest(contents)),
+                Err(_) => hasher.update(b"missing"),
+            }
+        }
+    }
+    for arg in &command.args {
+        if let Some(path) = widget_file(dir, arg) {
+            hasher.update(arg);
+            match fs::read(&path) {
+                Ok(contents) => hasher.update(Sha256::digest(contents)),
+                Err(_) => hasher.update(b"missing"),
+            }
+        }
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// The file `arg` names when it is one inside `dir`, which commands run in.
+fn widget_file(dir: &Path, arg: &str) -> Option<PathBuf> {
+    let path = dir.join(arg).canonicalize().ok()?;
+    (path.is_file() && path.starts_with(dir.canonicalize().ok()?)).then_some(path)
+}
+
+fn approvals(app: &AppHandle) -> anyhow::Result<Map<String, Value>> {
+    let store = get_or_create_store(app)?;
+    Ok(store
+        .get(APPROVED_COMMANDS_KEY)
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default())
+}
+
+pub fn is_approved(app: &AppHandle, widget_key: &str, dir: &Path, command: &ShellCommand) -> bool {
+    approvals(app)
+        .map_err(|e| eprintln!("Error reading command approvals: {}", e))
+        .ok()
+        .and_then(|approvals| {
+            approvals
+                .get(widget_key)?
+                .get(&command.id)?
+                .as_str()
+                .map(|fingerprint| fingerprint == command_fingerprint(dir, command))
+        })
+        .unwrap_or(false)
+}
+
+fn set_approval(
+    app: &AppHandle,
+    widget_key: &str,
+    id: &str,
+    fingerprint: Option<String>,
+) -> anyhow::Result<()> {
+    // Read and written under one lock, or a concurrent approval is lost
+    persist::with_lock(&store_path(app)?, || {
+        let mut approvals = approvals(app)?;
+        let widget = approvals
+            .entry(widget_key.to_string())
+            .or_insert_with(|| json!({}));
+        if !widget.is_object() {
+            *widget = json!({});
+        }
+        let commands = widget.as_object_mut().expect("checked above");
+        match fingerprint {
+            Some(fingerprint) => {
+                commands.insert(id.to_string(), json!(fingerprint));
+            }
+            None => {
+                commands.remove(id);
+            }
+        }
+        if commands.is_empty() {
+            approvals.remove(widget_key);
+        }
+        write_to_store(
+            app,
+            vec![KVPair {
+                key: APPROVED_COMMANDS_KEY.to_string(),
+                value: Value::Object(approvals),
+            }],
+        )
+    })
+}
+
+/// Commands a widget declares and the folder they run in.
+fn widget_commands(
+    app: &AppHandle,
+    widget_key: &str,
+) -> Result<(PathBuf, Vec<ShellCommand>), String> {
+    let context = ProviderContext::for_widget(app, Some(widget_key));
+    let (Some(manifest), Some(dir)) = (
+        context.manifest,
+        context.manifest_path.as_deref().and_then(Path::parent),
+    ) else {
+        return Err(format!("Widget {} not found", widget_key));
+    };
+    Ok((
+        dir.to_path_buf(),
+        manifest.shell_commands.unwrap_or_default(),
+    ))
+}
+
+/// Approvals are a user decision, widgets must not be able to grant them to
+/// themselves.
+fn ensure_main_window(window: &WebviewWindow) -> Result<(), String> {
+    if window.label() != "main" {
+        return Err("Shell commands can only be approved from the main window".to_string());
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShellCommandInfo {
+    #[serde(flatten)]
+    pub command: ShellCommand,
+    pub approved: bool,
+}
+
+/// Commands a widget declares, for the approval prompt.
+#[tauri::command]
+pub fn list_shell_commands(
+    app: AppHandle,
+    widget_key: String,
+) -> Result<Vec<ShellCommandInfo>, String> {
+    let (dir, commands) = widget_commands(&app, &widget_key)?;
+    Ok(commands
+        .into_iter()
+        .map(|command| ShellCommandInfo {
+            approved: is_approved(&app, &widget_key, &dir, &command),
+            command,
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub fn approve_shell_command(
+    app: AppHandle,
+    window: WebviewWindow,
+    widget_key: String,
+    id: String,
+) -> Result<(), String> {
+    ensure_main_window(&window)?;
+    let (dir, commands) = widget_commands(&app, &widget_key)?;
+    let command = commands
+        .into_iter()
+        .find(|c| c.id == id)
+        .ok_or_else(|| format!("Widget {} has no command {}", widget_key, id))?;
+    let fingerprint = command_fingerprint(&dir, &command);
+    set_approval(&app, &widget_key, &id, Some(fingerprint)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn revoke_shell_command(
+    app: AppHandle,
+    window: WebviewWindow,
+    widget_key: String,
+    id: String,
+) -> Result<(), String> {
+    ensure_main_window(&window)?;
+    set_approval(&app, &widget_key, &id, None).map_err(|e| e.to_string())
+}
+
+/// Drops every approval of a deleted widget.
+pub fn forget_approvals(app: &AppHandle, widget_key: &str) -> anyhow::Result<()> {
+    persist::with_lock(&store_path(app)?, || {
+        let mut approvals = approvals(app)?;
+        if approvals.remove(widget_key).is_none() {
+            return Ok(());
+        }
+        write_to_store(
+            app,
+            vec![KVPair {
+                key: APPROVED_COMMANDS_KEY.to_string(),
+                value: Value::Object(approvals),
+            }],
+        )
+    })
+}
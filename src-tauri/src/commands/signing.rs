@@ -0,0 +1,218 @@
+use anyhow::anyhow;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tauri::AppHandle;
+
+use crate::commands::{
+    package::WidgetPackage,
+    store::{get_or_create_store, write_to_store, KVPair},
+    vault,
+};
+
+pub const SIGNATURE_ENTRY: &str = "signature.json";
+
+const KEYRING_SIGNING_KEY: &str = "widget-signing-key";
+const TRUSTED_PUBLISHERS_KEY: &str = "trustedPublishers";
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PackageSignature {
+    algorithm: String,
+    public_key: String,
+    signature: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustedPublisher {
+    pub name: String,
+    pub public_key: String,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageTrust {
+    /// Signature is valid and the key is in the trust store.
+    Verified,
+    /// Signature is valid but the key is not in the trust store.
+    Untrusted,
+    Unsigned,
+    /// The package was modified after signing, or the signature is malformed.
+    Tampered,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageVerification {
+    pub status: PackageTrust,
+    pub public_key: Option<String>,
+    pub publisher: Option<String>,
+}
+
+/// Loads this install's publisher key from the keyring, creating it on first use.
+fn get_or_create_signing_key(app: &AppHandle) -> anyhow::Result<SigningKey> {
+    match vault::get(app, KEYRING_SIGNING_KEY).map_err(anyhow::Error::msg)? {
+        Some(encoded) => {
+            let bytes: [u8; 32] = BASE64_STANDARD
+                .decode(encoded)?
+                .try_into()
+                .map_err(|_| anyhow!("Stored signing key has an invalid length"))?;
+            Ok(SigningKey::from_bytes(&bytes))
+        }
+        None => {
+            let key = SigningKey::generate(&mut rand::rngs::OsRng);
+            vault::set(
+                app,
+                KEYRING_SIGNING_KEY,
+                &BASE64_STANDARD.encode(key.to_bytes()),
+            )
+            .map_err(anyhow::Error::msg)?;
+            Ok(key)
+        }
+    }
+}
+
+/// Text that gets signed: one `<sha256>  <entry>` line per archive entry.
+fn package_digest(package: &WidgetPackage) -> Vec<u8> {
+    package
+        .entries
+        .iter()
+        .filter(|(name, _)| name.as_str() != SIGNATURE_ENTRY)
+        .map(|(name, bytes)| format!("{:x}  {}\n", Sha256::digest(bytes), name))
+        .collect::<String>()
+        .into_bytes()
+}
+
+pub fn sign_package(app: &AppHandle, package: &mut WidgetPackage) -> anyhow::Result<()> {
+    let key = get_or_create_signing_key(app)?;
+    let signature = key.sign(&package_digest(package));
+    let signature = PackageSignature {
+        algorithm: "ed25519".into(),
+        public_key: BASE64_STANDARD.encode(key.verifying_key().to_bytes()),
+        signature: BASE64_STANDARD.encode(signature.to_bytes()),
+    };
+    package.entries.insert(
+        SIGNATURE_ENTRY.into(),
+        serde_json::to_vec_pretty(&signature)?,
+    );
+    Ok(())
+}
+
+fn check_signature(package: &WidgetPackage, signature: &PackageSignature) -> anyhow::Result<()> {
+    if signature.algorithm != "ed25519" {
+        anyhow::bail!("Unsupported signature algorithm {}", signature.algorithm);
+    }
+    let public_key: [u8; 32] = BASE64_STANDARD
+        .decode(&signature.public_key)?
+        .try_into()
+        .map_err(|_| anyhow!("Invalid public key"))?;
+    let signature_bytes: [u8; 64] = BASE64_STANDARD
+        .decode(&signature.signature)?
+        .try_into()
+        .map_err(|_| anyhow!("Invalid signature"))?;
+    VerifyingKey::from_bytes(&public_key)?.verify(
+        &package_digest(package),
+        &Signature::from_bytes(&signature_bytes),
+    )?;
+    Ok(())
+}
+
+pub fn get_trusted_publishers(app: &AppHandle) -> anyhow::Result<Vec<TrustedPublisher>> {
+    let store = get_or_create_store(app)?;
+    Ok(store
+        .get(TRUSTED_PUBLISHERS_KEY)
+        .cloned()
+        .map(serde_json::from_value)
+        .transpose()?
+        .unwrap_or_default())
+}
+
+fn set_trusted_publishers(
+    app: &AppHandle,
+    publishers: Vec<TrustedPublisher>,
+) -> anyhow::Result<()> {
+    write_to_store(
+        app,
+        vec![KVPair {
+            key: TRUSTED_PUBLISHERS_KEY.to_string(),
+            value: json!(publishers),
+        }],
+    )
+}
+
+pub fn verify_package(app: &AppHandle, package: &WidgetPackage) -> PackageVerification {
+    let Some(bytes) = package.entries.get(SIGNATURE_ENTRY) else {
+        return PackageVerification {
+            status: PackageTrust::Unsigned,
+            public_key: None,
+            publisher: None,
+        };
+    };
+    let Ok(signature) = serde_json::from_slice::<PackageSignature>(bytes) else {
+        return PackageVerification {
+            status: PackageTrust::Tampered,
+            public_key: None,
+            publisher: None,
+        };
+    };
+    if let Err(e) = check_signature(package, &signature) {
+        eprintln!("Package signature check failed: {}", e);
+        return PackageVerification {
+            status: PackageTrust::Tampered,
+            public_key: Some(signature.public_key),
+            publisher: None,
+        };
+    }
+
+    let publisher = get_trusted_publishers(app)
+        .unwrap_or_default()
+        .into_iter()
+        .find(|p| p.public_key == signature.public_key)
+        .map(|p| p.name);
+    PackageVerification {
+        status: if publisher.is_some() {
+            PackageTrust::Verified
+        } else {
+            PackageTrust::Untrusted
+        },
+        public_key: Some(signature.public_key),
+        publisher,
+    }
+}
+
+#[tauri::command]
+pub fn get_publisher_key(app: AppHandle) -> Result<String, String> {
+    let key = get_or_create_signing_key(&app).map_err(|e| e.to_string())?;
+    Ok(BASE64_STANDARD.encode(key.verifying_key().to_bytes()))
+}
+
+#[tauri::command]
+pub fn list_trusted_publishers(app: AppHandle) -> Result<Vec<TrustedPublisher>, String> {
+    get_trusted_publishers(&app).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn trust_publisher(app: AppHandle, name: String, public_key: String) -> Result<(), String> {
+    let decoded = BASE64_STANDARD
+        .decode(&public_key)
+        .map_err(|e| e.to_string())?;
+    let bytes: [u8; 32] = decoded
+        .try_into()
+        .map_err(|_| "Invalid public key".to_string())?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| e.to_string())?;
+
+    let mut publishers = get_trusted_publishers(&app).map_err(|e| e.to_string())?;
+    publishers.retain(|p| p.public_key != public_key);
+    publishers.push(TrustedPublisher { name, public_key });
+    set_trusted_publishers(&app, publishers).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn untrust_publisher(app: AppHandle, public_key: String) -> Result<(), String> {
+    let mut publishers = get_trusted_publishers(&app).map_err(|e| e.to_string())?;
+    publishers.retain(|p| p.public_key != public_key);
+    set_trusted_publishers(&app, publishers).map_err(|e| e.to_string())
+}
@@ -1,18 +1,26 @@
 use serde_json::Value;
-use std::fs;
+use std::path::PathBuf;
 use tauri::AppHandle;
 use tauri::Manager;
 
-pub fn get_or_create_store(app_handle: &AppHandle) -> anyhow::Result<serde_json::Value> {
-    let store_path = app_handle
+use crate::persist;
+
+pub fn store_path(app_handle: &AppHandle) -> anyhow::Result<PathBuf> {
+    Ok(app_handle
         .path()
-        .resolve("store.json", tauri::path::BaseDirectory::AppData)?;
+        .resolve("store.json", tauri::path::BaseDirectory::AppData)?)
+}
 
-    if !store_path.exists() {
-        fs::write(&store_path, "{}")?;
-    }
-    let contents = fs::read_to_string(&store_path)?;
-    Ok(serde_json::from_str::<serde_json::Value>(&contents)?)
+pub fn get_or_create_store(app_handle: &AppHandle) -> anyhow::Result<serde_json::Value> {
+    let store_path = store_path(app_handle)?;
+
+    persist::with_lock(&store_path, || {
+        if !store_path.exists() {
+            persist::write(&store_path, "{}")?;
+        }
+        let contents = persist::read(&store_path)?;
+        Ok(serde_json::from_str::<serde_json::Value>(&contents)?)
+    })
 }
 
 #[derive(serde::Deserialize)]
@@ -22,18 +30,19 @@ pub struct KVPair {
 }
 
 pub fn write_to_store(app_handle: &AppHandle, pairs: Vec<KVPair>) -> anyhow::Result<()> {
-    let store_path = app_handle
-        .path()
-        .resolve("store.json", tauri::path::BaseDirectory::AppData)?;
+    let store_path = store_path(app_handle)?;
 
-    let mut store = get_or_create_store(app_handle)?;
-    for pair in pairs {
-        store[pair.key] = pair.value;
-    }
+    // Held across the read so concurrent writers do not drop each other's keys
+    persist::with_lock(&store_path, || {
+        let mut store = get_or_create_store(app_handle)?;
+        for pair in pairs {
+            store[pair.key] = pair.value;
+        }
 
-    let contents = serde_json::to_string(&store)?;
-    fs::write(store_path, contents)?;
-    Ok(())
+        let contents = serde_json::to_string(&store)?;
+        persist::write(&store_path, contents)?;
+        Ok(())
+    })
 }
 
 #[tauri::command]
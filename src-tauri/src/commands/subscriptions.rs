@@ -0,0 +1,146 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Mutex;
+
+use crate::providers::{DataProvider, ProviderContext, ProviderRegistry, Snapshot};
+
+/// How often the poller checks providers that do not emit their own events.
+/// Snapshots are only fetched again when their refresh interval is up.
+const POLL_TICK: Duration = Duration::from_secs(1);
+
+/// Which windows use which provider namespace. A provider runs while at least
+/// one window is subscribed to it. The lock is held while providers start and
+/// stop so a subscribe can not race the last unsubscribe.
+#[derive(Default)]
+pub struct Subscriptions {
+    windows: HashMap<String, HashSet<String>>,
+    polling: HashSet<String>,
+}
+
+async fn start_provider(
+    app: &AppHandle,
+    subscriptions: &mut Subscriptions,
+    provider: Arc<dyn DataProvider>,
+) -> Result<(), String> {
+    let namespace = provider.namespace();
+    app.state::<ProviderRegistry>()
+        .start(app, namespace)
+        .await?;
+    if !provider.emits_events() && subscriptions.polling.insert(namespace.to_string()) {
+        tauri::async_runtime::spawn(poll_provider(app.clone(), provider));
+    }
+    Ok(())
+}
+
+/// Emits `<namespace>_updated` with the snapshot to subscribed windows when it
+/// changes, until nobody is subscribed.
+async fn poll_provider(app: AppHandle, provider: Arc<dyn DataProvider>) {
+    let namespace = provider.namespace();
+    let event = format!("{}_updated", namespace);
+    let mut last_sent: HashMap<String, Snapshot> = HashMap::new();
+    loop {
+        let labels = {
+            let state = app.state::<Mutex<Subscriptions>>();
+            let mut subscriptions = state.lock().await;
+            let labels: Vec<String> = subscriptions
+                .windows
+                .get(namespace)
+                .map(|w| w.iter().cloned().collect())
+                .unwrap_or_default();
+            if labels.is_empty() {
+                subscriptions.polling.remove(namespace);
+                return;
+            }
+            labels
+        };
+        last_sent.retain(|label, _| labels.contains(label));
+
+        let registry = app.state::<ProviderRegistry>();
+        for label in labels {
+            let context = ProviderContext::for_window(&app, &label);
+            let Some(snapshot) = registry.snapshot(&app, namespace, &context).await else {
+                continue;
+            };
+            if last_sent.get(&label) != Some(&snapshot) {
+                let _ = app.emit_to(&label, &event, &snapshot);
+                last_sent.insert(label, snapshot);
+            }
+        }
+        tokio::time::sleep(POLL_TICK).await;
+    }
+}
+
+pub async fn subscribe(app: &AppHandle, label: &str, namespace: &str) -> Result<(), String> {
+    let provider = app
+        .state::<ProviderRegistry>()
+        .get(namespace)
+        .ok_or_else(|| format!("Unknown data provider: {}", namespace))?;
+    let state = app.state::<Mutex<Subscriptions>>();
+    let mut subscriptions = state.lock().await;
+    let windows = subscriptions
+        .windows
+        .entry(namespace.to_string())
+        .or_default();
+    let is_first = windows.is_empty();
+    windows.insert(label.to_string());
+    if is_first {
+        if let Err(e) = start_provider(app, &mut subscriptions, provider).await {
+            subscriptions.windows.remove(namespace);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+pub async fn unsubscribe(app: &AppHandle, label: &str, namespace: &str) {
+    let state = app.state::<Mutex<Subscriptions>>();
+    let mut subscriptions = state.lock().await;
+    let Some(windows) = subscriptions.windows.get_mut(namespace) else {
+        return;
+    };
+    // The poller stops by itself once nobody is subscribed
+    if windows.remove(label) && windows.is_empty() {
+        app.state::<ProviderRegistry>().stop(app, namespace).await;
+    }
+}
+
+/// Drops every subscription of a closed or destroyed window.
+pub async fn unsubscribe_window(app: &AppHandle, label: &str) {
+    let namespaces: Vec<String> = {
+        let state = app.state::<Mutex<Subscriptions>>();
+        let subscriptions = state.lock().await;
+        subscriptions
+            .windows
+            .iter()
+            .filter(|(_, windows)| windows.contains(label))
+            .map(|(namespace, _)| namespace.clone())
+            .collect()
+    };
+    for namespace in namespaces {
+        unsubscribe(app, label, &namespace).await;
+    }
+}
+
+/// Window label -> provider namespaces it is subscribed to, for debugging.
+#[tauri::command]
+pub async fn list_subscriptions(app: AppHandle) -> BTreeMap<String, Vec<String>> {
+    let state = app.state::<Mutex<Subscriptions>>();
+    let subscriptions = state.lock().await;
+    let mut by_window: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (namespace, windows) in &subscriptions.windows {
+        for label in windows {
+            by_window
+                .entry(label.clone())
+                .or_default()
+                .push(namespace.clone());
+        }
+    }
+    for namespaces in by_window.values_mut() {
+        namespaces.sort();
+    }
+    by_window
+}
@@ -68,8 +68,29 @@ fn get_network_speeds() -> Vec<serde_json::Value> {
         .collect()
 }
 
+/// Whether a battery is discharging, i.e. the machine is unplugged.
+pub fn is_on_battery() -> bool {
+    let state = SysInfoState::default();
+    let sysinfo = state.sysinfo.lock().unwrap();
+    sysinfo
+        .batteries()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|b| serde_json::to_value(b).ok())
+        .any(|b| {
+            b.get("state")
+                .and_then(Value::as_str)
+                .is_some_and(|s| s.eq_ignore_ascii_case("discharging"))
+        })
+}
+
 #[tauri::command]
 pub async fn get_system_info(has_network: Option<bool>) -> Result<Value, ()> {
+    Ok(read_system_info(has_network))
+}
+
+/// Blocks for `sysinfo::MINIMUM_CPU_UPDATE_INTERVAL` to measure CPU usage.
+pub fn read_system_info(has_network: Option<bool>) -> Value {
     let state = SysInfoState::default();
     let mut sysinfo = state.sysinfo.lock().unwrap();
     std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
@@ -92,7 +113,7 @@ pub async fn get_system_info(has_network: Option<bool>) -> Result<Value, ()> {
         vec![]
     };
 
-    let info = serde_json::json!({
+    serde_json::json!({
         "total_memory": sysinfo.total_memory(),
         "used_memory": sysinfo.used_memory(),
         "total_swap": sysinfo.total_swap(),
@@ -111,6 +132,5 @@ pub async fn get_system_info(has_network: Option<bool>) -> Result<Value, ()> {
             "brand": cpu_brand,
         }),
         "networks": networks,
-    });
-    Ok(info)
+    })
 }
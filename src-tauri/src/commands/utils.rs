@@ -3,7 +3,6 @@ use base64::{prelude::BASE64_STANDARD, Engine};
 use chrono::Utc;
 use image::GenericImageView;
 use serde::Serialize;
-use serde_json::{json, Value};
 use sqlx::prelude::FromRow;
 use sqlx::types::chrono::DateTime;
 use sqlx::{Pool, Sqlite};
@@ -30,14 +29,22 @@ use windows_icons::get_icon_by_path;
 
 use crate::commands::chat::MediaQueryRequest;
 use crate::commands::media::{MediaInfo, MediaState};
+use crate::commands::monitors::{
+    anchor_for_position, clamp_to_nearest_monitor, current_monitors, fits, remember_position,
+    snap_position, Rect, SnapSettings,
+};
+use crate::commands::store::get_or_create_store;
 use crate::db::DatabaseState;
+use crate::manifest::{Dimensions, Position, WidgetManifest, WidgetType};
+use crate::persist;
 
 static NO_THUMB_BYTES: &'static [u8] = include_bytes!("no-thumb.png");
 
-#[derive(serde::Deserialize)]
-struct ManifestKey {
-    key: Option<String>,
+/// Manifest paths are passed from the frontend as JSON encoded strings.
+pub fn decode_path_arg(path: &str) -> Result<String, String> {
+    serde_json::from_str::<String>(path).map_err(|e| format!("Invalid path {path}: {e}"))
 }
+
 pub fn get_existing_keys(
     app: &tauri::AppHandle,
     current_folder: String,
@@ -65,64 +72,43 @@ pub fn get_existing_keys(
                 continue;
             }
 
-            let Ok(contents) = fs::read_to_string(&manifest_path) else {
-                continue;
-            };
-
-            let Ok(manifest) = serde_json::from_str::<ManifestKey>(&contents) else {
+            let Ok(manifest) = WidgetManifest::load(&manifest_path) else {
                 continue;
             };
-            if let Some(key) = manifest.key {
-                existing_keys.insert(key, None);
-            }
+            existing_keys.insert(manifest.key, None);
         }
     }
 
     existing_keys
 }
 
+/// Returns `base` or the first `base-<n>` that is not taken yet.
+pub fn unique_key(base: &str, existing_keys: &HashMap<String, Option<()>>) -> String {
+    if !existing_keys.contains_key(base) {
+        return base.to_string();
+    }
+    let mut n = 2;
+    loop {
+        let candidate = format!("{base}-{n}");
+        if !existing_keys.contains_key(&candidate) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+/// Returns `position` if the window fits on a monitor, otherwise the closest
+/// position that does.
 pub fn ensure_window_position_bounds(
-    webview: &tauri::WebviewWindow,
+    app: &AppHandle,
     position: PhysicalPosition<i32>,
     size: PhysicalSize<u32>,
 ) -> PhysicalPosition<i32> {
-    let monitors = webview.available_monitors().unwrap();
-
-    let win_x = position.x;
-    let win_y = position.y;
-    let win_right = win_x + size.width as i32;
-    let win_bottom = win_y + size.height as i32;
-
-    let mut in_bounds = false;
-
-    for monitor in &monitors {
-        let monitor_size = monitor.size();
-        let monitor_position = monitor.position();
-
-        if win_x >= monitor_position.x
-            && win_y >= monitor_position.y
-            && win_right <= monitor_position.x + monitor_size.width as i32
-            && win_bottom <= monitor_position.y + monitor_size.height as i32
-        {
-            in_bounds = true;
-            break;
-        }
-    }
-
-    if in_bounds {
+    let monitors = current_monitors(app);
+    if monitors.iter().any(|m| fits(m, position, size)) {
         return position;
     }
-
-    if let Some(primary) = webview.primary_monitor().unwrap_or_default() {
-        let mon_pos = primary.position();
-
-        let new_x = mon_pos.x + 30 as i32;
-        let new_y = mon_pos.y + 30 as i32;
-
-        PhysicalPosition { x: new_x, y: new_y }
-    } else {
-        PhysicalPosition { x: 30, y: 30 }
-    }
+    clamp_to_nearest_monitor(&monitors, position, size).unwrap_or(PhysicalPosition { x: 30, y: 30 })
 }
 
 pub fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
@@ -145,49 +131,100 @@ fn save_window_state(window: &tauri::WebviewWindow, config_path: String) {
         window.inner_size(),
         window.scale_factor(),
     ) {
-        // Try to read the existing config file
-        let config_content = match fs::read_to_string(&config_path) {
-            Ok(content) => content,
-            Err(_) => String::from("{}"),
-        };
+        let config_path = Path::new(&config_path);
+        // Locked so a manifest update between the load and the save is not lost
+        persist::with_lock(config_path, || {
+            let mut config = match WidgetManifest::load(config_path) {
+                Ok(config) => config,
+                Err(e) => {
+                    eprintln!(
+                        "Not saving window state for {}: {}",
+                        config_path.display(),
+                        e
+                    );
+                    return;
+                }
+            };
 
-        // Parse the existing JSON
-        let mut config: Value = match serde_json::from_str(&config_content) {
-            Ok(json) => json,
-            Err(_) => json!({}),
-        };
-        let widget_type = config
-            .get("widgetType")
-            .and_then(Value::as_str)
-            .unwrap_or("json")
-            .to_string();
-
-        // Update only the window position and size fields
-        if let Value::Object(ref mut map) = config {
-            map.insert(
-                String::from("position"),
-                json!({
-                    "x": position.x,
-                    "y": position.y
-                }),
-            );
-            let is_json = widget_type == "json";
+            // Update only the window position and size fields
+            config.position = Some(Position {
+                x: Some(position.x as f64),
+                y: Some(position.y as f64),
+            });
+            remember_position(window.app_handle(), &mut config, position);
+            if let Some(point) = config.anchor.as_ref().map(|a| a.point) {
+                if let Some(anchor) =
+                    anchor_for_position(window.app_handle(), point, position, size)
+                {
+                    config.anchor = Some(anchor);
+                }
+            }
+            let is_json = config.widget_type == WidgetType::Json;
             let logical_size = size.to_logical::<u32>(scale_factor);
+            config.dimensions = Some(Dimensions {
+                width: (if is_json {
+                    logical_size.width
+                } else {
+                    size.width
+                }) as f64,
+                height: (if is_json {
+                    logical_size.height
+                } else {
+                    size.height
+                }) as f64,
+            });
+
+            // Not a revision, history only keeps changes to the design
+            if let Err(e) = config.save(config_path) {
+                eprintln!("Error saving window state: {}", e);
+            }
+        });
+    }
+}
 
-            map.insert(
-                String::from("dimensions"),
-                json!({
-                    "width": if is_json { logical_size.width } else { size.width },
-                    "height": if is_json { logical_size.height } else { size.height }
-                }),
-            );
-        }
+fn snap_settings(app: &AppHandle) -> SnapSettings {
+    let store = get_or_create_store(app).unwrap_or_default();
+    SnapSettings {
+        grid: store.get("snapGrid").and_then(|v| v.as_u64()).unwrap_or(0) as u32,
+        edges: store
+            .get("snapToWidgets")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
+        threshold: store
+            .get("snapThreshold")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(10) as u32,
+    }
+}
 
-        // Write the updated JSON back to the file
-        if let Ok(json_string) = serde_json::to_string_pretty(&config) {
-            let _ = fs::write(&config_path, json_string);
-        }
+/// Snaps a widget that was just dragged, returns whether it was moved.
+fn snap_window(window: &tauri::WebviewWindow) -> bool {
+    let app = window.app_handle();
+    let settings = snap_settings(app);
+    if !settings.edges && settings.grid == 0 {
+        return false;
     }
+    let (Ok(position), Ok(size)) = (window.outer_position(), window.outer_size()) else {
+        return false;
+    };
+    let others: Vec<Rect> = app
+        .webview_windows()
+        .into_iter()
+        .filter(|(label, w)| {
+            label.starts_with("widget-")
+                && label.as_str() != window.label()
+                && w.is_visible().unwrap_or(false)
+        })
+        .filter_map(|(_, w)| {
+            Some(Rect {
+                position: w.outer_position().ok()?,
+                size: w.outer_size().ok()?,
+            })
+        })
+        .collect();
+
+    let snapped = snap_position(app, Rect { position, size }, &others, &settings);
+    snapped != position && window.set_position(snapped).is_ok()
 }
 
 pub fn attach_window_events(new_window: tauri::WebviewWindow, clean_path: String) {
@@ -220,7 +257,8 @@ pub fn attach_window_events(new_window: tauri::WebviewWindow, clean_path: String
                         }
                     };
 
-                    if should_save {
+                    // Snapping moves the window again, which saves it
+                    if should_save && !snap_window(&window_clone) {
                         save_window_state(&window_clone, path);
                     }
                 });
@@ -0,0 +1,233 @@
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tauri::{AppHandle, Emitter, Manager, WebviewWindow};
+use tokio::sync::Mutex;
+
+use crate::{
+    expression::Value,
+    providers::{
+        is_preview, widget_key_for, ProviderContext, ProviderInfo, ProviderRegistry, Snapshot,
+    },
+    template::{self, format_date},
+};
+
+const PUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+static NEXT_SUBSCRIPTION_ID: AtomicU64 = AtomicU64::new(1);
+
+struct TemplateSubscription {
+    label: String,
+    template: String,
+    widget_key: Option<String>,
+    preview: bool,
+    last: Option<String>,
+}
+
+/// Templates windows asked to be kept up to date. The data behind them is
+/// cached by the provider registry.
+#[derive(Default)]
+pub struct VariableState {
+    subscriptions: HashMap<String, TemplateSubscription>,
+    pushing: bool,
+}
+
+#[derive(Serialize, Clone)]
+pub struct TemplateUpdate {
+    pub id: String,
+    pub text: String,
+}
+
+/// `date`, `time`, `datetime` and `custom` are built in, every other namespace
+/// comes from a snapshot fetched before rendering. A missing snapshot renders
+/// as `Loading...`, a missing key is null and renders as `NA`.
+fn resolve(
+    context: &ProviderContext,
+    snapshots: &HashMap<&str, Option<Snapshot>>,
+    namespace: &str,
+    argument: Option<&str>,
+) -> Option<Value> {
+    let now = chrono::Local::now();
+    match namespace {
+        "date" => Some(Value::Text(format_date(
+            &now,
+            argument.unwrap_or("yyyy-MM-dd"),
+        ))),
+        "time" => Some(Value::Text(format_date(
+            &now,
+            argument.unwrap_or("hh:mm aa"),
+        ))),
+        "datetime" => Some(Value::Text(format_date(
+            &now,
+            argument.unwrap_or("eeee, MMMM d yyyy, h:mm aa"),
+        ))),
+        "custom" => Some(
+            argument
+                .and_then(|key| context.custom_field(key))
+                .map_or(Value::Null, |value| Value::Text(value.to_string())),
+        ),
+        _ => {
+            let snapshot = snapshots.get(namespace)?.as_ref()?;
+            Some(
+                argument
+                    .and_then(|key| snapshot.get(key))
+                    .map_or(Value::Null, |value| value.clone().into()),
+            )
+        }
+    }
+}
+
+/// Renders `template`, with `custom` and `weather` variables read from the
+/// manifest of `widget_key`, or from its draft for a `preview`.
+pub async fn render(
+    app: &AppHandle,
+    template: &str,
+    widget_key: Option<&str>,
+    preview: bool,
+) -> String {
+    let namespaces = template::namespaces(template);
+    let needs_widget = namespaces
+        .iter()
+        .any(|ns| !matches!(ns.as_str(), "date" | "time" | "datetime"));
+    let context = if needs_widget {
+        ProviderContext::lookup(app, widget_key, preview)
+    } else {
+        ProviderContext::default()
+    };
+
+    let registry = app.state::<ProviderRegistry>();
+    let mut snapshots = HashMap::new();
+    for namespace in &namespaces {
+        if registry.get(namespace).is_some() {
+            let snapshot = registry.snapshot(app, namespace, &context).await;
+            snapshots.insert(namespace.as_str(), snapshot);
+        }
+    }
+    template::render(template, |namespace, argument| {
+        resolve(&context, &snapshots, namespace, argument)
+    })
+}
+
+/// Re-renders subscribed templates and pushes the ones that changed, until
+/// there are none left.
+async fn push_updates(app: AppHandle) {
+    let state = app.state::<Mutex<VariableState>>();
+    loop {
+        tokio::time::sleep(PUSH_INTERVAL).await;
+        let subscriptions: Vec<(String, String, String, Option<String>, bool)> = {
+            let mut state = state.lock().await;
+            if state.subscriptions.is_empty() {
+                state.pushing = false;
+                return;
+            }
+            state
+                .subscriptions
+                .iter()
+                .map(|(id, s)| {
+                    (
+                        id.clone(),
+                        s.label.clone(),
+                        s.template.clone(),
+                        s.widget_key.clone(),
+                        s.preview,
+                    )
+                })
+                .collect()
+        };
+
+        for (id, label, template, widget_key, preview) in subscriptions {
+            let text = render(&app, &template, widget_key.as_deref(), preview).await;
+            let changed = match state.lock().await.subscriptions.get_mut(&id) {
+                Some(subscription) if subscription.last.as_ref() != Some(&text) => {
+                    subscription.last = Some(text.clone());
+                    true
+                }
+                _ => false,
+            };
+            if changed {
+                let _ = app.emit_to(label, "template-updated", TemplateUpdate { id, text });
+            }
+        }
+    }
+}
+
+/// Drops the template subscriptions of a closed window.
+pub async fn unsubscribe_window_templates(app: &AppHandle, label: &str) {
+    let state = app.state::<Mutex<VariableState>>();
+    state
+        .lock()
+        .await
+        .subscriptions
+        .retain(|_, s| s.label != label);
+}
+
+/// `widget_key`, defaulting to the widget of window `label`, and whether it
+/// is that widget's preview.
+fn calling_widget(label: &str, widget_key: Option<String>) -> (Option<String>, bool) {
+    let own_key = widget_key_for(label);
+    let widget_key = widget_key.or_else(|| own_key.map(str::to_string));
+    let preview = is_preview(label) && widget_key.as_deref() == own_key;
+    (widget_key, preview)
+}
+
+/// Renders a template once. `widget_key` defaults to the calling widget.
+#[tauri::command]
+pub async fn render_template(
+    app: AppHandle,
+    window: WebviewWindow,
+    template: String,
+    widget_key: Option<String>,
+) -> Result<String, String> {
+    let (widget_key, preview) = calling_widget(window.label(), widget_key);
+    Ok(render(&app, &template, widget_key.as_deref(), preview).await)
+}
+
+/// Renders a template and keeps pushing `template-updated` events to the
+/// calling window whenever the result changes.
+#[tauri::command]
+pub async fn subscribe_template(
+    app: AppHandle,
+    window: WebviewWindow,
+    template: String,
+    widget_key: Option<String>,
+) -> Result<TemplateUpdate, String> {
+    let label = window.label().to_string();
+    let (widget_key, preview) = calling_widget(&label, widget_key);
+    let text = render(&app, &template, widget_key.as_deref(), preview).await;
+    let id = NEXT_SUBSCRIPTION_ID
+        .fetch_add(1, Ordering::Relaxed)
+        .to_string();
+
+    let state = app.state::<Mutex<VariableState>>();
+    let mut state = state.lock().await;
+    state.subscriptions.insert(
+        id.clone(),
+        TemplateSubscription {
+            label,
+            template,
+            widget_key,
+            preview,
+            last: Some(text.clone()),
+        },
+    );
+    if !state.pushing {
+        state.pushing = true;
+        tauri::async_runtime::spawn(push_updates(app.clone()));
+    }
+    Ok(TemplateUpdate { id, text })
+}
+
+#[tauri::command]
+pub async fn unsubscribe_template(app: AppHandle, id: String) {
+    let state = app.state::<Mutex<VariableState>>();
+    state.lock().await.subscriptions.remove(&id);
+}
+
+/// Variable namespaces backed by data providers, with their keys.
+#[tauri::command]
+pub fn list_variables(registry: tauri::State<'_, ProviderRegistry>) -> Vec<ProviderInfo> {
+    registry.list()
+}
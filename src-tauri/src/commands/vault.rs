@@ -0,0 +1,214 @@
+//! Keyring access for the app. No window has the keyring plugin's
+//! permissions, so webviews only reach what the commands here hand out.
+
+use serde_json::{json, Value};
+use tauri::{AppHandle, WebviewWindow};
+use tauri_plugin_keyring::KeyringExt;
+
+use crate::{
+    commands::store::{get_or_create_store, store_path, write_to_store, KVPair},
+    persist,
+    providers::widget_key_for,
+};
+
+/// The app's own secrets: the publisher key, widget secrets and API tokens
+const APP_SERVICE: &str = "delta-widgets-app";
+/// AI model keys, which earlier versions also kept the app's secrets under
+const MODEL_SERVICE: &str = "delta-widgets";
+/// Windows that configure AI models
+const MODEL_WINDOWS: [&str; 3] = ["main", "creator", "assistant"];
+/// Widget key -> names of the passwords the widget saved, so they can be
+/// removed with it
+const WIDGET_PASSWORDS_KEY: &str = "widgetPasswords";
+
+fn get_password(app: &AppHandle, service: &str, name: &str) -> Result<Option<String>, String> {
+    app.keyring()
+        .get_password(service, name)
+        .map_err(|e| e.to_string())
+}
+
+fn delete_password(app: &AppHandle, service: &str, name: &str) -> Result<(), String> {
+    if get_password(app, service, name)?.is_none() {
+        return Ok(());
+    }
+    app.keyring()
+        .delete_password(service, name)
+        .map_err(|e| e.to_string())
+}
+
+/// Reads one of the app's secrets, moving it over from the shared service
+/// it was kept under before.
+pub fn get(app: &AppHandle, name: &str) -> Result<Option<String>, String> {
+    if let Some(value) = get_password(app, APP_SERVICE, name)? {
+        return Ok(Some(value));
+    }
+    let Some(value) = get_password(app, MODEL_SERVICE, name)? else {
+        return Ok(None);
+    };
+    set(app, name, &value)?;
+    if let Err(e) = delete_password(app, MODEL_SERVICE, name) {
+        eprintln!("Error removing the old keyring entry {}: {}", name, e);
+    }
+    Ok(Some(value))
+}
+
+pub fn set(app: &AppHandle, name: &str, value: &str) -> Result<(), String> {
+    app.keyring()
+        .set_password(APP_SERVICE, name, value)
+        .map_err(|e| e.to_string())
+}
+
+pub fn delete(app: &AppHandle, name: &str) -> Result<(), String> {
+    delete_password(app, APP_SERVICE, name)?;
+    delete_password(app, MODEL_SERVICE, name)
+}
+
+fn model_key_name(window: &WebviewWindow, id: &str) -> Result<String, String> {
+    if !MODEL_WINDOWS.contains(&window.label()) {
+        return Err("Model keys can only be used from the app".to_string());
+    }
+    Ok(format!("model-key-{id}"))
+}
+
+#[tauri::command]
+pub fn get_model_key(
+    app: AppHandle,
+    window: WebviewWindow,
+    id: String,
+) -> Result<Option<String>, String> {
+    get_password(&app, MODEL_SERVICE, &model_key_name(&window, &id)?)
+}
+
+#[tauri::command]
+pub fn set_model_key(
+    app: AppHandle,
+    window: WebviewWindow,
+    id: String,
+    key: String,
+) -> Result<(), String> {
+    app.keyring()
+        .set_password(MODEL_SERVICE, &model_key_name(&window, &id)?, &key)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_model_key(app: AppHandle, window: WebviewWindow, id: String) -> Result<(), String> {
+    delete_password(&app, MODEL_SERVICE, &model_key_name(&window, &id)?)
+}
+
+fn widget_password_entry(widget_key: &str, name: &str) -> String {
+    format!("widget-keyring:{widget_key}:{name}")
+}
+
+/// Key of the widget whose window calls and the keyring entry of its
+/// password, widgets can not name another widget's entries.
+fn widget_password_name(window: &WebviewWindow, name: &str) -> Result<(String, String), String> {
+    let widget_key = widget_key_for(window.label())
+        .ok_or_else(|| "Only widgets have their own passwords".to_string())?;
+    Ok((
+        widget_key.to_string(),
+        widget_password_entry(widget_key, name),
+    ))
+}
+
+fn widget_passwords(app: &AppHandle, widget_key: &str) -> Result<Vec<String>, String> {
+    let store = get_or_create_store(app).map_err(|e| e.to_string())?;
+    Ok(store
+        .get(WIDGET_PASSWORDS_KEY)
+        .and_then(|passwords| passwords.get(widget_key))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|name| name.as_str().map(str::to_string))
+        .collect())
+}
+
+fn set_widget_passwords(
+    app: &AppHandle,
+    widget_key: &str,
+    names: Vec<String>,
+) -> Result<(), String> {
+    let store = get_or_create_store(app).map_err(|e| e.to_string())?;
+    let mut passwords = store
+        .get(WIDGET_PASSWORDS_KEY)
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+    if names.is_empty() {
+        passwords.remove(widget_key);
+    } else {
+        passwords.insert(widget_key.to_string(), json!(names));
+    }
+    write_to_store(
+        app,
+        vec![KVPair {
+            key: WIDGET_PASSWORDS_KEY.to_string(),
+            value: Value::Object(passwords),
+        }],
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Changes the names saved for the widget, under the store's lock so two
+/// windows saving at once both keep theirs.
+fn update_widget_passwords(
+    app: &AppHandle,
+    widget_key: &str,
+    update: impl FnOnce(&mut Vec<String>),
+) -> Result<(), String> {
+    let store_path = store_path(app).map_err(|e| e.to_string())?;
+    persist::with_lock(&store_path, || {
+        let mut names = widget_passwords(app, widget_key)?;
+        let previous = names.clone();
+        update(&mut names);
+        if names == previous {
+            return Ok(());
+        }
+        set_widget_passwords(app, widget_key, names)
+    })
+}
+
+#[tauri::command]
+pub fn get_widget_password(
+    app: AppHandle,
+    window: WebviewWindow,
+    name: String,
+) -> Result<Option<String>, String> {
+    let (_, entry) = widget_password_name(&window, &name)?;
+    get_password(&app, APP_SERVICE, &entry)
+}
+
+#[tauri::command]
+pub fn set_widget_password(
+    app: AppHandle,
+    window: WebviewWindow,
+    name: String,
+    password: String,
+) -> Result<(), String> {
+    let (widget_key, entry) = widget_password_name(&window, &name)?;
+    set(&app, &entry, &password)?;
+    update_widget_passwords(&app, &widget_key, |names| {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    })
+}
+
+#[tauri::command]
+pub fn delete_widget_password(
+    app: AppHandle,
+    window: WebviewWindow,
+    name: String,
+) -> Result<(), String> {
+    let (widget_key, entry) = widget_password_name(&window, &name)?;
+    delete_password(&app, APP_SERVICE, &entry)?;
+    update_widget_passwords(&app, &widget_key, |names| names.retain(|n| *n != name))
+}
+
+/// Removes the passwords a deleted widget saved.
+pub fn delete_widget_passwords(app: &AppHandle, widget_key: &str) -> Result<(), String> {
+    for name in widget_passwords(app, widget_key)? {
+        delete_password(app, APP_SERVICE, &widget_password_entry(widget_key, &name))?;
+    }
+    update_widget_passwords(app, widget_key, Vec::clear)
+}
@@ -1,20 +1,38 @@
 use serde_json::{json, Value};
-use std::{collections::HashMap, fs};
-use tauri::{Emitter, Manager, PhysicalPosition, PhysicalSize, State};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use tauri::{Emitter, LogicalSize, Manager, PhysicalPosition, PhysicalSize, Size};
 
 use crate::{
     commands::{
-        audio::{stop_capture as stop_audio_capture, AudioState},
-        media::{stop_media_listener, MediaState},
+        history::{record_revision, RevisionSource},
+        monitors::{
+            anchored_position, current_monitors, manifest_size, physical_size_at, restore_position,
+            set_position,
+        },
+        package::resolve_manifest_file,
+        permissions,
         services::copy_custom_assets_dir,
+        subscriptions::{subscribe, unsubscribe_window},
         utils::{
-            attach_window_events, ensure_window_position_bounds, get_existing_keys,
-            get_wallpaper_preview,
+            attach_window_events, copy_dir_all, decode_path_arg, ensure_window_position_bounds,
+            get_existing_keys, get_wallpaper_preview, unique_key,
         },
     },
     get_custom_server_port,
+    manifest::{
+        validate_manifest_value, ManifestError, ManifestIssue, Position, WidgetManifest, WidgetType,
+    },
+    persist,
+    plugins::localhost,
 };
 
+/// Physical pixels a duplicated widget is moved from the original.
+const DUPLICATE_OFFSET: i32 = 30;
+
 #[tauri::command]
 pub async fn create_creator_window(
     app: tauri::AppHandle,
@@ -81,114 +99,58 @@ pub async fn create_creator_window(
     });
 }
 
-#[derive(serde::Deserialize, PartialEq)]
-#[serde(rename_all = "lowercase")]
-pub enum WidgetType {
-    Json,
-    Url,
-    Html,
-}
-#[derive(serde::Deserialize, PartialEq)]
-#[serde(rename_all = "camelCase")]
-struct WidgetManifest {
-    key: Option<String>,
-    label: Option<String>,
-    dimensions: Option<PhysicalSize<f64>>,
-    position: Option<PhysicalPosition<Option<f64>>>,
-    url: Option<String>,
-    file: Option<String>,
-    visible: Option<bool>,
-    always_on_top: Option<bool>,
-    pinned: Option<bool>,
-    #[serde(default = "default_widget_type")]
-    widget_type: WidgetType,
-}
-
-fn default_widget_type() -> WidgetType {
-    WidgetType::Json
-}
-
-async fn clear_window_listeners_on_close(
-    label: &String,
-    audio_state: State<'_, std::sync::Mutex<AudioState>>,
-    media_state: State<'_, tokio::sync::Mutex<MediaState>>,
+#[tauri::command]
+pub async fn create_widget_window(
+    app: tauri::AppHandle,
+    path: String,
+    is_preview: Option<bool>,
 ) -> Result<(), String> {
-    {
-        let mut state = audio_state.lock().map_err(|e| e.to_string())?;
-        if state.listening_windows.len() == 1 && state.listening_windows.contains(label) {
-            drop(state);
-            stop_audio_capture(label, &audio_state);
-        } else {
-            state.listening_windows.remove(label);
-        };
-    }
-    {
-        let mut state = media_state.lock().await;
-        if state.listening_windows.len() == 1 && state.listening_windows.contains(label) {
-            drop(state);
-            let _ = stop_media_listener(label, &media_state).await;
+    let clean_path = decode_path_arg(&path)?;
+    let manifest = WidgetManifest::load(Path::new(&clean_path)).map_err(|e| e.to_string())?;
+    let label = format!(
+        "widget-{}{}",
+        if is_preview.unwrap_or(false) {
+            "preview-"
         } else {
-            state.listening_windows.remove(label);
-        };
-    }
-    Ok(())
-}
+            ""
+        },
+        manifest.key
+    );
+    let scopes = permissions::ensure_consent(&app, &manifest).await;
+    permissions::register_window(&label, &manifest, scopes);
 
-#[tauri::command]
-pub async fn create_widget_window(app: tauri::AppHandle, path: String, is_preview: Option<bool>) {
-    let clean_path = serde_json::from_str::<String>(&path).unwrap();
-    let manifest_content = fs::read_to_string(clean_path.as_str()).expect("Cannot read manifest");
-    let manifest: WidgetManifest =
-        serde_json::from_str(&manifest_content).expect("invalid manifest");
+    let window_size = manifest_size(&manifest).unwrap_or(match manifest.widget_type {
+        WidgetType::Url => Size::Physical(PhysicalSize::new(520, 840)),
+        WidgetType::Html => Size::Physical(PhysicalSize::new(600, 400)),
+        WidgetType::Json => Size::Logical(LogicalSize::new(600.0, 400.0)),
+    });
+    let position = manifest
+        .anchor
+        .as_ref()
+        .and_then(|anchor| anchored_position(&app, anchor, window_size))
+        .unwrap_or_else(|| restore_position(&app, &manifest, window_size));
 
+    let requires = manifest.requires.clone().unwrap_or_default();
     let title = manifest.label.unwrap_or_else(|| "Widget".to_string());
-    let manifest_key = manifest.key.unwrap_or_else(|| "widget".to_string());
-
-    let physical_size = manifest
-        .dimensions
-        .map(|d| PhysicalSize {
-            height: d.height as u32,
-            width: d.width as u32,
-        })
-        .unwrap_or(PhysicalSize {
-            width: if manifest.widget_type == WidgetType::Url {
-                520
-            } else {
-                600
-            },
-            height: if manifest.widget_type == WidgetType::Url {
-                840
-            } else {
-                400
-            },
-        });
+    let manifest_key = manifest.key;
 
     let url = match manifest.widget_type {
         WidgetType::Url => manifest.url.unwrap_or_else(|| "".to_string()),
         WidgetType::Html => {
             let html_folder = manifest.file.unwrap_or_else(|| "index.html".to_string());
-            let _ = copy_custom_assets_dir(app.clone(), manifest_key.clone(), html_folder.clone())
-                .await
-                .unwrap_or_default();
-            let port = get_custom_server_port();
-            format!(
-                "http://localhost:{}/{}/index.html",
-                port,
-                manifest_key.clone(),
-            )
+            if manifest.dev == Some(true) {
+                localhost::set_dev_folder(&manifest_key, Some(PathBuf::from(&html_folder)));
+            } else {
+                localhost::set_dev_folder(&manifest_key, None);
+                let _ =
+                    copy_custom_assets_dir(app.clone(), manifest_key.clone(), html_folder.clone())
+                        .await
+                        .unwrap_or_default();
+            }
+            localhost::widget_url(get_custom_server_port(), &manifest_key, "index.html")
         }
         _ => "widget-index.html".into(),
     };
-    let label = format!(
-        "widget-{}{}",
-        if is_preview.unwrap_or(false) {
-            "preview-"
-        } else {
-            ""
-        },
-        manifest_key
-    );
-
     let mut window_builder =
         tauri::WebviewWindowBuilder::new(&app, label.clone(), tauri::WebviewUrl::App(url.into()));
     window_builder = window_builder.title(&title).visible(false);
@@ -221,32 +183,18 @@ pub async fn create_widget_window(app: tauri::AppHandle, path: String, is_previe
         }
     }
 
-    let new_window = window_builder.build().unwrap();
-
-    let position = manifest
-        .position
-        .map(|p| {
-            ensure_window_position_bounds(
-                &new_window,
-                PhysicalPosition {
-                    x: p.x.unwrap_or(30 as f64) as i32,
-                    y: p.y.unwrap_or(30 as f64) as i32,
-                },
-                physical_size,
-            )
-        })
-        .unwrap_or(PhysicalPosition { x: 30, y: 30 });
+    let new_window = window_builder.build().map_err(|e| e.to_string())?;
 
     new_window.set_position(position).unwrap();
 
-    if manifest.widget_type == WidgetType::Json {
-        new_window
-            .set_size(physical_size.to_logical::<u32>(1.0))
-            .unwrap();
-    } else if manifest.widget_type == WidgetType::Url || manifest.widget_type == WidgetType::Html {
-        new_window.set_size(physical_size).unwrap();
-    }
+    new_window.set_size(window_size).unwrap();
     new_window.show().unwrap();
+    // Providers the widget reads from, tracked per window like template subscriptions
+    for namespace in requires {
+        if let Err(e) = subscribe(&app, &label, &namespace).await {
+            eprintln!("Error starting {} for {}: {}", namespace, label, e);
+        }
+    }
     if !is_preview.unwrap_or(false) {
         new_window.set_skip_taskbar(true).unwrap();
         new_window.set_maximizable(false).unwrap();
@@ -267,119 +215,105 @@ pub async fn create_widget_window(app: tauri::AppHandle, path: String, is_previe
         }
         attach_window_events(new_window.clone(), clean_path);
     } else {
-        let label = label.clone();
         let app = app.clone();
         let fired = std::sync::Arc::new(std::sync::Once::new());
 
         new_window.on_window_event(move |event| {
             match event {
                 tauri::WindowEvent::CloseRequested { .. } | tauri::WindowEvent::Destroyed => {
-                    let label = label.clone();
-                    let app = app.clone();
                     let clean_path = clean_path.clone();
                     fired.call_once(|| {
-                        tauri::async_runtime::spawn(async move {
-                            let audio_state = app.state::<std::sync::Mutex<AudioState>>();
-                            let media_state = app.state::<tokio::sync::Mutex<MediaState>>();
-                            let _ =
-                                clear_window_listeners_on_close(&label, audio_state, media_state)
-                                    .await;
-                            let _ = app.emit_to("creator", "widget-close", clean_path);
-                        });
+                        let _ = app.emit_to("creator", "widget-close", clean_path);
                     });
                 }
                 _ => {}
             };
         });
     }
+    Ok(())
 }
 
 #[tauri::command]
-pub async fn close_widget_window(
-    app: tauri::AppHandle,
-    media_state: State<'_, tokio::sync::Mutex<MediaState>>,
-    audio_state: State<'_, std::sync::Mutex<AudioState>>,
-    label: String,
-) -> Result<(), String> {
+pub async fn close_widget_window(app: tauri::AppHandle, label: String) -> Result<(), String> {
     if let Some(window) = app.get_webview_window(&label) {
-        clear_window_listeners_on_close(&label, audio_state, media_state).await?;
+        unsubscribe_window(&app, &label).await;
         window.close().map_err(|e| e.to_string())?;
     }
     Ok(())
 }
 
 #[tauri::command]
-pub async fn publish_widget(app: tauri::AppHandle, path: String) -> Result<String, String> {
-    let clean_path = serde_json::from_str::<String>(&path).unwrap();
+pub async fn publish_widget(
+    app: tauri::AppHandle,
+    path: String,
+    revision_name: Option<String>,
+) -> Result<String, String> {
+    let clean_path = decode_path_arg(&path)?;
     let widgets_path = app
         .path()
         .resolve("widgets", tauri::path::BaseDirectory::AppData)
-        .unwrap();
+        .map_err(|e| e.to_string())?;
     if !widgets_path.exists() {
         if let Err(err) = fs::create_dir_all(&widgets_path) {
             eprintln!("Error creating widgets directory: {}", err);
             return Err(err.to_string());
         }
     }
-    let config_content = fs::read_to_string(&clean_path).unwrap();
-
-    // Parse the existing JSON
-    let mut config: Value = match serde_json::from_str(&config_content) {
-        Ok(json) => json,
-        Err(_) => json!({}),
-    };
-    let key = config
-        .get("key")
-        .and_then(Value::as_str)
-        .unwrap()
-        .to_string();
-    let published_time = std::time::UNIX_EPOCH.elapsed().unwrap().as_millis();
-
-    // Update published status and timestamp
-    if let Value::Object(ref mut map) = config {
-        map.insert(String::from("published"), json!(true));
-
-        map.insert(String::from("publishedAt"), json!(published_time));
-    }
-
-    // Write the updated JSON back to the file
-    if let Ok(json_string) = serde_json::to_string_pretty(&config) {
-        let _ = fs::write(&clean_path, json_string);
-    }
-    let manifest_path = widgets_path.join(&key);
-    if !manifest_path.exists() {
-        fs::create_dir_all(&manifest_path).unwrap();
-    } else {
-        if let Value::Object(ref mut map) = config {
-            let old_config_content =
-                fs::read_to_string(&manifest_path.join("manifest.json")).unwrap();
-            let old_config: Value = match serde_json::from_str(&old_config_content) {
-                Ok(json) => json,
-                Err(_) => json!({}),
-            };
-            map.insert(
-                String::from("visible"),
-                old_config.get("visible").unwrap_or(&json!(false)).clone(),
-            );
-            let dimensions = map.get("dimensions");
-            if dimensions.is_none() {
-                map.insert(
-                    String::from("dimensions"),
-                    old_config.get("dimensions").unwrap_or(&json!({})).clone(),
-                );
+    let draft_path = Path::new(&clean_path);
+    let mut config = persist::with_lock(draft_path, || {
+        let mut config = WidgetManifest::load(draft_path)?;
+        let published_time = std::time::UNIX_EPOCH.elapsed().unwrap().as_millis();
+
+        // Update published status and timestamp
+        config.published = Some(true);
+        config.published_at = Some(json!(published_time));
+
+        // Write the updated manifest back to the draft
+        config.save(draft_path)?;
+        Ok::<_, ManifestError>(config)
+    })
+    .map_err(|e| e.to_string())?;
+
+    let manifest_path = widgets_path.join(&config.key);
+    let published_manifest = manifest_path.join("manifest.json");
+    // Locked so window state saved while publishing is kept
+    let previous = persist::with_lock(&published_manifest, || {
+        let mut previous = None;
+        if !manifest_path.exists() {
+            fs::create_dir_all(&manifest_path).map_err(|e| e.to_string())?;
+        } else {
+            // Keep the window state of the already published widget
+            let old_config = WidgetManifest::load(&published_manifest).ok();
+            previous = old_config.clone();
+            config.visible = Some(old_config.as_ref().and_then(|c| c.visible).unwrap_or(false));
+            if config.dimensions.is_none() {
+                config.dimensions = old_config.as_ref().and_then(|c| c.dimensions.clone());
             }
-            map.insert(
-                String::from("position"),
-                old_config
-                    .get("position")
-                    .unwrap_or(&json!({"x": 30, "y": 30}))
-                    .clone(),
-            );
+            config.monitor_positions = old_config
+                .as_ref()
+                .and_then(|c| c.monitor_positions.clone());
+            config.position = Some(old_config.and_then(|c| c.position).unwrap_or(Position {
+                x: Some(30.0),
+                y: Some(30.0),
+            }));
         }
-    }
-    // Copy the widget to the published directory
-    if let Ok(json_string) = serde_json::to_string_pretty(&config) {
-        let _ = fs::write(&manifest_path.join("manifest.json"), json_string);
+        // Copy the widget to the published directory
+        config
+            .save(&published_manifest)
+            .map_err(|e| e.to_string())?;
+        Ok::<_, String>(previous)
+    })?;
+    if let Err(e) = record_revision(
+        &app,
+        &published_manifest,
+        previous.as_ref(),
+        &config,
+        RevisionSource::Publish,
+        Some(revision_name.unwrap_or_else(|| "Published".to_string())),
+    )
+    .await
+    {
+        eprintln!("Error recording manifest revision: {}", e);
     }
 
     manifest_path
@@ -388,6 +322,114 @@ pub async fn publish_widget(app: tauri::AppHandle, path: String) -> Result<Strin
         .map_err(|_| String::from("Error stringifying path"))
 }
 
+fn duplicate_widget_files(
+    app: &tauri::AppHandle,
+    manifest: &mut WidgetManifest,
+    original_key: &str,
+    source_dir: &Path,
+    widget_dir: &Path,
+) -> anyhow::Result<()> {
+    copy_dir_all(source_dir, widget_dir)?;
+
+    // Give the copy its own assets so editing one widget never touches the other
+    let cache_dir = app.path().app_cache_dir()?;
+    let assets_dir = cache_dir.join("assets");
+    let mut renamed = HashMap::new();
+    for asset_key in manifest.file_asset_keys() {
+        let new_key = format!("{}-{}", manifest.key, asset_key);
+        match fs::copy(assets_dir.join(&asset_key), assets_dir.join(&new_key)) {
+            Ok(_) => {
+                renamed.insert(asset_key, new_key);
+            }
+            Err(e) => eprintln!("Error copying asset {}: {}", asset_key, e),
+        }
+    }
+    manifest.rename_asset_keys(&renamed);
+
+    if manifest.widget_type == WidgetType::Html {
+        // Sources living inside the widget folder were copied along with it
+        if let Some(relative) = manifest
+            .file
+            .as_ref()
+            .and_then(|f| Path::new(f).strip_prefix(source_dir).ok())
+        {
+            manifest.file = Some(widget_dir.join(relative).to_string_lossy().to_string());
+        }
+        let cached_files = cache_dir.join("files").join(original_key);
+        let html_source = if cached_files.is_dir() {
+            Some(cached_files)
+        } else {
+            manifest.file.as_ref().map(PathBuf::from)
+        };
+        if let Some(html_source) = html_source.filter(|p| p.is_dir()) {
+            copy_dir_all(html_source, cache_dir.join("files").join(&manifest.key))?;
+        }
+    }
+
+    if let Some(position) = &manifest.position {
+        let position = PhysicalPosition {
+            x: position.x.unwrap_or(30.0) as i32 + DUPLICATE_OFFSET,
+            y: position.y.unwrap_or(30.0) as i32 + DUPLICATE_OFFSET,
+        };
+        let size = manifest_size(manifest).map_or(PhysicalSize::new(0, 0), |size| {
+            physical_size_at(&current_monitors(app), position, size)
+        });
+        let offset = ensure_window_position_bounds(app, position, size);
+        // Next to the original, not on its remembered or anchored spot
+        set_position(app, manifest, offset, None);
+    }
+
+    manifest.save(&widget_dir.join("manifest.json"))?;
+    Ok(())
+}
+
+/// Copies a published widget or a draft next to the original under a fresh
+/// key and returns the new manifest path.
+#[tauri::command]
+pub async fn duplicate_widget(app: tauri::AppHandle, path: String) -> Result<String, String> {
+    let manifest_path = resolve_manifest_file(&path);
+    let mut manifest = WidgetManifest::load(&manifest_path).map_err(|e| e.to_string())?;
+    let source_dir = manifest_path
+        .parent()
+        .ok_or("Invalid widget path")?
+        .to_path_buf();
+    let parent_dir = source_dir.parent().ok_or("Invalid widget path")?;
+
+    let mut existing_keys = get_existing_keys(&app, String::new());
+    for entry in fs::read_dir(parent_dir)
+        .map_err(|e| e.to_string())?
+        .flatten()
+    {
+        existing_keys.insert(entry.file_name().to_string_lossy().to_string(), None);
+    }
+
+    let original_key = manifest.key.clone();
+    let original_label = manifest
+        .label
+        .clone()
+        .unwrap_or_else(|| original_key.clone());
+    manifest.key = unique_key(&original_key, &existing_keys);
+    manifest.label = Some(format!("{} (copy)", original_label));
+    manifest.description = Some(format!("Copy of {}", original_label));
+    manifest.visible = Some(false);
+    if manifest.published_at.is_some() {
+        manifest.published_at = Some(json!(chrono::Utc::now().timestamp_millis()));
+    }
+
+    let widget_dir = parent_dir.join(&manifest.key);
+    if let Err(e) =
+        duplicate_widget_files(&app, &mut manifest, &original_key, &source_dir, &widget_dir)
+    {
+        let _ = fs::remove_dir_all(&widget_dir);
+        return Err(e.to_string());
+    }
+
+    Ok(widget_dir
+        .join("manifest.json")
+        .to_string_lossy()
+        .to_string())
+}
+
 #[tauri::command]
 pub async fn open_devtools(app: tauri::AppHandle, label: String) {
     #[allow(unused)]
@@ -404,3 +446,8 @@ pub fn get_existing_keys_cmd(
 ) -> HashMap<String, Option<()>> {
     get_existing_keys(&app, current_folder)
 }
+
+#[tauri::command]
+pub fn validate_manifest(manifest: Value) -> Vec<ManifestIssue> {
+    validate_manifest_value(&manifest)
+}
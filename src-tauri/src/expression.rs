@@ -0,0 +1,790 @@
+//! Expressions inside `{{ }}`: variables, literals, arithmetic, comparisons,
+//! `cond ? a : b` and a `| filter(args)` pipeline, e.g.
+//! `{{system:memory_used_bytes | bytes}}` or
+//! `{{media:playing ? "Playing" : "Paused"}}`.
+//!
+//! A `namespace:key` variable is written without spaces, its key may contain
+//! `.` and `-`, or be quoted. Put spaces around `-` when subtracting.
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use std::{collections::BTreeSet, fmt, fmt::Write};
+
+use crate::template::{format_duration, human_storage_size};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A key the namespace does not have
+    Null,
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+impl fmt::Display for Value {
+    /// Missing values print as `NA`, like the frontend, and whole numbers
+    /// without `.0`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => f.write_str("NA"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Number(n) if n.fract() == 0.0 && n.is_finite() => write!(f, "{}", *n as i64),
+            Self::Number(n) => write!(f, "{n}"),
+            Self::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        match self {
+            Self::Null => false,
+            Self::Bool(b) => *b,
+            Self::Number(n) => *n != 0.0 && !n.is_nan(),
+            Self::Text(text) => !text.is_empty(),
+        }
+    }
+
+    /// Text counts when it parses, since providers often report numbers as
+    /// text.
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Number(n) => Some(*n),
+            Self::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+            Self::Text(text) => text.trim().parse().ok(),
+            Self::Null => None,
+        }
+    }
+
+    fn number(&self, filter: &str) -> Result<f64, Error> {
+        self.as_number()
+            .ok_or_else(|| Error::Invalid(format!("`{}` needs a number, got `{}`", filter, self)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Variable {
+        namespace: String,
+        key: Option<String>,
+    },
+    /// The current time in milliseconds
+    Now,
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Filter {
+        input: Box<Expr>,
+        name: String,
+        args: Vec<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A variable whose data is not there yet
+    Unresolved,
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Ident(String),
+    Variable(String, String),
+    Symbol(&'static str),
+}
+
+/// Two character symbols first, so `||` is not read as two `|`
+const SYMBOLS: [&str; 20] = [
+    "||", "&&", "==", "!=", "<=", ">=", "|", "!", "<", ">", "+", "-", "*", "/", "%", "?", ":", "(",
+    ")", ",",
+];
+
+fn read_quoted(chars: &[char], i: &mut usize) -> Result<String, String> {
+    let quote = chars[*i];
+    let mut text = String::new();
+    *i += 1;
+    while *i < chars.len() {
+        match chars[*i] {
+            '\\' if *i + 1 < chars.len() => {
+                text.push(chars[*i + 1]);
+                *i += 2;
+            }
+            c if c == quote => {
+                *i += 1;
+                return Ok(text);
+            }
+            c => {
+                text.push(c);
+                *i += 1;
+            }
+        }
+    }
+    Err("Unclosed string".to_string())
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let is_key = |c: char| is_word(c) || c == '.' || c == '-';
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' || c == '\'' {
+            tokens.push(Token::Text(read_quoted(&chars, &mut i)?));
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit))
+        {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            let number = number
+                .parse()
+                .map_err(|_| format!("Invalid number `{}`", number))?;
+            tokens.push(Token::Number(number));
+        } else if is_word(c) {
+            let start = i;
+            while i < chars.len() && is_word(chars[i]) {
+                i += 1;
+            }
+            let name: String = chars[start..i].iter().collect();
+            // `namespace:key` only without spaces, `a ? b : c` needs them
+            let key_start = chars.get(i + 1).copied();
+            if chars.get(i) == Some(&':')
+                && key_start.is_some_and(|c| is_key(c) || c == '"' || c == '\'')
+            {
+                i += 1;
+                let key = if chars[i] == '"' || chars[i] == '\'' {
+                    read_quoted(&chars, &mut i)?
+                } else {
+                    let start = i;
+                    while i < chars.len() && is_key(chars[i]) {
+                        i += 1;
+                    }
+                    chars[start..i].iter().collect()
+                };
+                tokens.push(Token::Variable(name, key));
+            } else {
+                tokens.push(Token::Ident(name));
+            }
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = SYMBOLS
+                .iter()
+                .find(|s| rest.starts_with(**s))
+                .ok_or_else(|| format!("Unexpected `{}`", c))?;
+            i += symbol.chars().count();
+            tokens.push(Token::Symbol(symbol));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Deepest nesting of parentheses, operators, conditionals and filters.
+/// Parsing and evaluating recurse once per level, so a long `1+1+...` or
+/// `((((...` in a manifest must not be able to overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    /// One level deeper, undone with `leave` once the level is parsed. A
+    /// failed parse is thrown away, so errors do not need to undo it.
+    fn enter(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(format!(
+                "Expression is nested deeper than {} levels",
+                MAX_DEPTH
+            ));
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self, levels: usize) {
+        self.depth -= levels;
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        if !self.eat(symbol) {
+            return Err(format!("Expected `{}`", symbol));
+        }
+        Ok(())
+    }
+
+    fn pipeline(&mut self) -> Result<Expr, String> {
+        let mut expr = self.conditional()?;
+        let mut filters = 0;
+        while self.eat("|") {
+            // Each filter wraps everything before it
+            self.enter()?;
+            filters += 1;
+            let Some(Token::Ident(name)) = self.next() else {
+                return Err("Expected a filter name after `|`".to_string());
+            };
+            let mut args = vec![];
+            if self.eat("(") && !self.eat(")") {
+                loop {
+                    args.push(self.pipeline()?);
+                    if self.eat(")") {
+                        break;
+                    }
+                    self.expect(",")?;
+                }
+            }
+            expr = Expr::Filter {
+                input: Box::new(expr),
+                name,
+                args,
+            };
+        }
+        self.leave(filters);
+        Ok(expr)
+    }
+
+    fn conditional(&mut self) -> Result<Expr, String> {
+        let condition = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        self.enter()?;
+        let then = self.conditional()?;
+        self.expect(":")?;
+        let otherwise = self.conditional()?;
+        self.leave(1);
+        Ok(Expr::Conditional(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    /// Operators by precedence, lowest first.
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        const LEVELS: [&[(&str, BinaryOp)]; 5] = [
+            &[("||", BinaryOp::Or)],
+            &[("&&", BinaryOp::And)],
+            &[
+                ("==", BinaryOp::Equal),
+                ("!=", BinaryOp::NotEqual),
+                ("<=", BinaryOp::LessEqual),
+                (">=", BinaryOp::GreaterEqual),
+                ("<", BinaryOp::Less),
+                (">", BinaryOp::Greater),
+            ],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Subtract)],
+            &[
+                ("*", BinaryOp::Multiply),
+                ("/", BinaryOp::Divide),
+                ("%", BinaryOp::Remainder),
+            ],
+        ];
+        let Some(operators) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        let mut chained = 0;
+        'outer: loop {
+            for (symbol, op) in operators.iter() {
+                if self.eat(symbol) {
+                    // `a + b + c` is `(a + b) + c`, one level per operator
+                    self.enter()?;
+                    chained += 1;
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(*op, Box::new(left), Box::new(right));
+                    continue 'outer;
+                }
+            }
+            self.leave(chained);
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let op = if self.eat("-") {
+            UnaryOp::Negate
+        } else if self.eat("!") {
+            UnaryOp::Not
+        } else {
+            return self.primary();
+        };
+        self.enter()?;
+        let expr = self.unary()?;
+        self.leave(1);
+        Ok(Expr::Unary(op, Box::new(expr)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Literal(Value::Number(n))),
+            Some(Token::Text(text)) => Ok(Expr::Literal(Value::Text(text))),
+            Some(Token::Variable(namespace, key)) => Ok(Expr::Variable {
+                namespace,
+                key: Some(key),
+            }),
+            Some(Token::Ident(name)) => Ok(match name.as_str() {
+                "true" => Expr::Literal(Value::Bool(true)),
+                "false" => Expr::Literal(Value::Bool(false)),
+                "null" => Expr::Literal(Value::Null),
+                "now" => Expr::Now,
+                _ => Expr::Variable {
+                    namespace: name,
+                    key: None,
+                },
+            }),
+            Some(Token::Symbol("(")) => {
+                self.enter()?;
+                let expr = self.pipeline()?;
+                self.expect(")")?;
+                self.leave(1);
+                Ok(expr)
+            }
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+pub fn parse(source: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+        depth: 0,
+    };
+    let expr = parser.pipeline()?;
+    if parser.position < parser.tokens.len() {
+        return Err(format!("Unexpected {:?}", parser.tokens[parser.position]));
+    }
+    Ok(expr)
+}
+
+/// `value` as a local date: numbers are Unix time, in milliseconds when they
+/// are too big for seconds, text is RFC 3339 or `YYYY-MM-DD[ HH:MM:SS]`.
+fn to_date(value: &Value) -> Option<DateTime<Local>> {
+    if let Some(n) = value.as_number() {
+        let millis = if n.abs() < 1e11 { n * 1000.0 } else { n };
+        return Local.timestamp_millis_opt(millis as i64).single();
+    }
+    let Value::Text(text) = value else {
+        return None;
+    };
+    let text = text.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Some(date.with_timezone(&Local));
+    }
+    let naive = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S"))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })?;
+    Local.from_local_datetime(&naive).earliest()
+}
+
+/// Most decimals `round` shows, far past what an `f64` holds
+const MAX_DECIMALS: usize = 20;
+
+const FILTERS: [&str; 12] = [
+    "default", "bytes", "duration", "round", "floor", "ceil", "abs", "upper", "lower", "trim",
+    "truncate", "date",
+];
+
+fn apply_filter(name: &str, input: Value, args: &[Value]) -> Result<Value, Error> {
+    if !FILTERS.contains(&name) {
+        return Err(Error::Invalid(format!("Unknown filter `{}`", name)));
+    }
+    let arg = |i: usize| args.get(i);
+    let count = |i: usize, default: f64| -> Result<usize, Error> {
+        let n = arg(i).map_or(Ok(default), |v| v.number(name))?;
+        Ok(n.max(0.0) as usize)
+    };
+    Ok(match name {
+        "default" => match input {
+            Value::Null => arg(0).cloned().unwrap_or(Value::Text(String::new())),
+            Value::Text(text) if text.is_empty() => {
+                arg(0).cloned().unwrap_or(Value::Text(String::new()))
+            }
+            value => value,
+        },
+        // Null stays null through the other filters, so `default` can follow
+        _ if input == Value::Null => Value::Null,
+        "bytes" => Value::Text(human_storage_size(input.number(name)?)),
+        "duration" => {
+            let n = input.number(name)?;
+            let millis = match arg(0).map(ToString::to_string).as_deref() {
+                None | Some("ms") => n,
+                Some("s") => n * 1000.0,
+                Some(unit) => {
+                    return Err(Error::Invalid(format!(
+                        "`duration` unit must be \"ms\" or \"s\", got `{}`",
+                        unit
+                    )))
+                }
+            };
+            Value::Text(format_duration(millis.max(0.0) as u64))
+        }
+        "round" => {
+            let decimals = count(0, 0.0)?;
+            if decimals > MAX_DECIMALS {
+                return Err(Error::Invalid(format!(
+                    "`round` takes at most {} decimals, got {}",
+                    MAX_DECIMALS, decimals
+                )));
+            }
+            Value::Text(format!("{:.*}", decimals, input.number(name)?))
+        }
+        "floor" => Value::Number(input.number(name)?.floor()),
+        "ceil" => Value::Number(input.number(name)?.ceil()),
+        "abs" => Value::Number(input.number(name)?.abs()),
+        "upper" => Value::Text(input.to_string().to_uppercase()),
+        "lower" => Value::Text(input.to_string().to_lowercase()),
+        "trim" => Value::Text(input.to_string().trim().to_string()),
+        "truncate" => {
+            let max = count(0, 20.0)?;
+            let text = input.to_string();
+            if text.chars().count() > max {
+                Value::Text(text.chars().take(max).collect::<String>() + "…")
+            } else {
+                Value::Text(text)
+            }
+        }
+        "date" => {
+            let date = to_date(&input)
+                .ok_or_else(|| Error::Invalid(format!("`{}` is not a date", input)))?;
+            let format = arg(0).map_or("%Y-%m-%d %H:%M".to_string(), ToString::to_string);
+            let mut out = String::new();
+            write!(out, "{}", date.format(&format))
+                .map_err(|_| Error::Invalid(format!("Invalid date format `{}`", format)))?;
+            Value::Text(out)
+        }
+        _ => unreachable!("checked against FILTERS"),
+    })
+}
+
+fn compare(op: BinaryOp, left: &Value, right: &Value) -> bool {
+    if *left == Value::Null || *right == Value::Null {
+        return match op {
+            BinaryOp::Equal => left == right,
+            BinaryOp::NotEqual => left != right,
+            _ => false,
+        };
+    }
+    let ordering = match (left.as_number(), right.as_number()) {
+        (Some(a), Some(b)) => a.partial_cmp(&b),
+        _ => Some(left.to_string().cmp(&right.to_string())),
+    };
+    let Some(ordering) = ordering else {
+        return op == BinaryOp::NotEqual;
+    };
+    match op {
+        BinaryOp::Equal => ordering.is_eq(),
+        BinaryOp::NotEqual => ordering.is_ne(),
+        BinaryOp::Less => ordering.is_lt(),
+        BinaryOp::LessEqual => ordering.is_le(),
+        BinaryOp::Greater => ordering.is_gt(),
+        _ => ordering.is_ge(),
+    }
+}
+
+fn arithmetic(op: BinaryOp, left: Value, right: Value) -> Result<Value, Error> {
+    if left == Value::Null || right == Value::Null {
+        return Ok(Value::Null);
+    }
+    let (a, b) = match (left.as_number(), right.as_number()) {
+        (Some(a), Some(b)) => (a, b),
+        // `+` joins text
+        _ if op == BinaryOp::Add => return Ok(Value::Text(format!("{}{}", left, right))),
+        (None, _) => return Err(Error::Invalid(format!("`{}` is not a number", left))),
+        (_, None) => return Err(Error::Invalid(format!("`{}` is not a number", right))),
+    };
+    Ok(Value::Number(match op {
+        BinaryOp::Add => a + b,
+        BinaryOp::Subtract => a - b,
+        BinaryOp::Multiply => a * b,
+        _ if b == 0.0 => return Err(Error::Invalid("Division by zero".to_string())),
+        BinaryOp::Divide => a / b,
+        _ => a % b,
+    }))
+}
+
+impl Expr {
+    /// Namespaces of the variables used, so only their data has to be
+    /// fetched.
+    pub fn namespaces<'a>(&'a self, out: &mut BTreeSet<&'a str>) {
+        match self {
+            Self::Variable { namespace, .. } => {
+                out.insert(namespace);
+            }
+            Self::Literal(_) | Self::Now => {}
+            Self::Unary(_, expr) => expr.namespaces(out),
+            Self::Binary(_, left, right) => {
+                left.namespaces(out);
+                right.namespaces(out);
+            }
+            Self::Conditional(condition, then, otherwise) => {
+                condition.namespaces(out);
+                then.namespaces(out);
+                otherwise.namespaces(out);
+            }
+            Self::Filter { input, args, .. } => {
+                input.namespaces(out);
+                args.iter().for_each(|arg| arg.namespaces(out));
+            }
+        }
+    }
+
+    /// `resolve` returns `None` for variables whose data is not loaded yet.
+    pub fn evaluate(
+        &self,
+        resolve: &mut impl FnMut(&str, Option<&str>) -> Option<Value>,
+    ) -> Result<Value, Error> {
+        match self {
+            Self::Literal(value) => Ok(value.clone()),
+            Self::Variable { namespace, key } => {
+                resolve(namespace, key.as_deref()).ok_or(Error::Unresolved)
+            }
+            Self::Now => Ok(Value::Number(Local::now().timestamp_millis() as f64)),
+            Self::Unary(UnaryOp::Not, expr) => Ok(Value::Bool(!expr.evaluate(resolve)?.truthy())),
+            Self::Unary(UnaryOp::Negate, expr) => match expr.evaluate(resolve)? {
+                Value::Null => Ok(Value::Null),
+                value => Ok(Value::Number(-value.number("-")?)),
+            },
+            Self::Binary(BinaryOp::And, left, right) => Ok(Value::Bool(
+                left.evaluate(resolve)?.truthy() && right.evaluate(resolve)?.truthy(),
+            )),
+            Self::Binary(BinaryOp::Or, left, right) => Ok(Value::Bool(
+                left.evaluate(resolve)?.truthy() || right.evaluate(resolve)?.truthy(),
+            )),
+            Self::Binary(op, left, right) => {
+                let (left, right) = (left.evaluate(resolve)?, right.evaluate(resolve)?);
+                match op {
+                    BinaryOp::Add
+                    | BinaryOp::Subtract
+                    | BinaryOp::Multiply
+                    | BinaryOp::Divide
+                    | BinaryOp::Remainder => arithmetic(*op, left, right),
+                    _ => Ok(Value::Bool(compare(*op, &left, &right))),
+                }
+            }
+            Self::Conditional(condition, then, otherwise) => {
+                if condition.evaluate(resolve)?.truthy() {
+                    then.evaluate(resolve)
+                } else {
+                    otherwise.evaluate(resolve)
+                }
+            }
+            Self::Filter { input, name, args } => {
+                let input = input.evaluate(resolve)?;
+                let args = args
+                    .iter()
+                    .map(|arg| arg.evaluate(resolve))
+                    .collect::<Result<Vec<_>, _>>()?;
+                apply_filter(name, input, &args)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Evaluates `source` with `system:cpu` set to 42.5 and `media:playing`
+    /// to true, anything else is not loaded yet.
+    fn eval(source: &str) -> Result<Value, Error> {
+        parse(source).map_err(Error::Invalid)?.evaluate(
+            &mut |namespace, key| match (namespace, key) {
+                ("system", Some("cpu")) => Some(Value::Number(42.5)),
+                ("media", Some("playing")) => Some(Value::Bool(true)),
+                ("custom", Some("missing")) => Some(Value::Null),
+                _ => None,
+            },
+        )
+    }
+
+    fn text(source: &str) -> String {
+        eval(source).unwrap().to_string()
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            tokenize(r#"system:cpu.total >= .5 || "a \"b\"""#).unwrap(),
+            vec![
+                Token::Variable("system".to_string(), "cpu.total".to_string()),
+                Token::Symbol(">="),
+                Token::Number(0.5),
+                Token::Symbol("||"),
+                Token::Text("a \"b\"".to_string()),
+            ]
+        );
+        assert_eq!(
+            tokenize("custom:'my key'").unwrap(),
+            vec![Token::Variable("custom".to_string(), "my key".to_string())]
+        );
+        // With spaces `:` belongs to a conditional
+        assert_eq!(
+            tokenize("a ? b : c").unwrap(),
+            vec![
+                Token::Ident("a".to_string()),
+                Token::Symbol("?"),
+                Token::Ident("b".to_string()),
+                Token::Symbol(":"),
+                Token::Ident("c".to_string()),
+            ]
+        );
+        assert!(tokenize("\"open").is_err());
+        assert!(tokenize("1.2.3").is_err());
+        assert!(tokenize("a # b").is_err());
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(text("1 + 2 * 3"), "7");
+        assert_eq!(text("(1 + 2) * 3"), "9");
+        assert_eq!(text("10 - 4 - 3"), "3");
+        assert_eq!(text("-2 * 3"), "-6");
+        assert_eq!(text("1 + 1 == 2 && !false"), "true");
+        assert_eq!(text("7 % 4"), "3");
+        assert_eq!(text("true ? 1 : false ? 2 : 3"), "1");
+        assert_eq!(text("false ? 1 : false ? 2 : 3"), "3");
+    }
+
+    #[test]
+    fn parse_errors() {
+        for source in ["", "1 +", "(1", "1 2", "a ? b", "1 | ", "x | round(1"] {
+            assert!(parse(source).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(text("system:cpu * 2"), "85");
+        assert_eq!(text(r#"media:playing ? "Playing" : "Paused""#), "Playing");
+        assert_eq!(eval("system:gpu + 1"), Err(Error::Unresolved));
+        assert_eq!(eval("custom:missing + 1"), Ok(Value::Null));
+        assert_eq!(text("custom:missing == null"), "true");
+        assert_eq!(text("\"a\" + 1"), "a1");
+        assert_eq!(text("\"2\" * 3"), "6");
+        assert!(matches!(eval("1 / 0"), Err(Error::Invalid(_))));
+        assert!(matches!(eval("\"a\" * 2"), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn namespaces() {
+        let expr = parse("system:cpu > 1 ? media:title : weather | default(custom:x)").unwrap();
+        let mut used = BTreeSet::new();
+        expr.namespaces(&mut used);
+        assert_eq!(
+            used.into_iter().collect::<Vec<_>>(),
+            ["custom", "media", "system", "weather"]
+        );
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(text("system:cpu | round"), "42");
+        assert_eq!(text("system:cpu | round(2)"), "42.50");
+        assert_eq!(text("system:cpu | floor"), "42");
+        assert_eq!(text("system:cpu | ceil"), "43");
+        assert_eq!(text("-3 | abs"), "3");
+        assert_eq!(text("1536 | bytes"), "1.5 KiB");
+        assert_eq!(text("61000 | duration"), "01:01");
+        assert_eq!(text("61 | duration(\"s\")"), "01:01");
+        assert_eq!(text("\" Hi \" | trim | upper"), "HI");
+        assert_eq!(text("\"Hello\" | lower | truncate(2)"), "he…");
+        assert_eq!(text("\"2024-01-02\" | date(\"%d.%m.%Y\")"), "02.01.2024");
+        assert_eq!(text("custom:missing | round | default(\"none\")"), "none");
+        assert_eq!(text("\"\" | default(1)"), "1");
+        assert!(matches!(eval("1 | shout"), Err(Error::Invalid(_))));
+        assert!(matches!(eval("\"a\" | round"), Err(Error::Invalid(_))));
+        assert!(matches!(
+            eval("1 | duration(\"h\")"),
+            Err(Error::Invalid(_))
+        ));
+        assert!(matches!(eval("\"soon\" | date"), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn round_decimals_are_limited() {
+        assert_eq!(text("1 | round(20)"), format!("1.{}", "0".repeat(20)));
+        assert!(matches!(eval("1 | round(21)"), Err(Error::Invalid(_))));
+        assert!(matches!(eval("1 | round(70000)"), Err(Error::Invalid(_))));
+        assert_eq!(text("1.5 | round(-3)"), "2");
+    }
+
+    #[test]
+    fn depth_limit() {
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(text(&nested(MAX_DEPTH)), "1");
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+
+        let chain = |count: usize| vec!["1"; count + 1].join("+");
+        assert_eq!(text(&chain(MAX_DEPTH)), (MAX_DEPTH + 1).to_string());
+        assert!(parse(&chain(MAX_DEPTH + 1)).is_err());
+
+        assert!(parse(&format!("{}1", "-".repeat(MAX_DEPTH + 1))).is_err());
+        assert!(parse(&format!("1{}", " | abs".repeat(MAX_DEPTH + 1))).is_err());
+        let conditionals =
+            |count: usize| format!("{}1{}", "1 ? ".repeat(count), " : 1".repeat(count));
+        assert_eq!(text(&conditionals(MAX_DEPTH)), "1");
+        assert!(parse(&conditionals(MAX_DEPTH + 1)).is_err());
+        // Levels are given back once parsed, siblings do not add up
+        let siblings = format!("{} + {}", nested(MAX_DEPTH - 1), nested(MAX_DEPTH - 1));
+        assert_eq!(text(&siblings), "2");
+    }
+}
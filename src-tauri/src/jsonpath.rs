@@ -0,0 +1,223 @@
+//! The commonly used subset of JSONPath: `$`, `.name`, `['name']`, `[0]`,
+//! `[-1]`, `[start:end]`, `*` and `..` recursive descent.
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Child(String),
+    Index(i64),
+    Slice(Option<i64>, Option<i64>),
+    Wildcard,
+    /// The node and everything below it, for `..`
+    Descendants,
+}
+
+fn parse_index(s: &str, path: &str) -> Result<i64, String> {
+    s.trim()
+        .parse()
+        .map_err(|_| format!("Invalid index `{}` in {}", s, path))
+}
+
+fn parse(path: &str) -> Result<Vec<Step>, String> {
+    let rest = path
+        .trim()
+        .strip_prefix('$')
+        .ok_or_else(|| format!("JSONPath must start with `$`: {}", path))?;
+    let chars: Vec<char> = rest.chars().collect();
+    let mut steps = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '.' => {
+                i += 1;
+                if chars.get(i) == Some(&'.') {
+                    steps.push(Step::Descendants);
+                    i += 1;
+                    if chars.get(i) == Some(&'[') {
+                        continue;
+                    }
+                }
+                let start = i;
+                while i < chars.len() && chars[i] != '.' && chars[i] != '[' {
+                    i += 1;
+                }
+                let name: String = chars[start..i].iter().collect();
+                match name.as_str() {
+                    "" => return Err(format!("Missing name after `.` in {}", path)),
+                    "*" => steps.push(Step::Wildcard),
+                    _ => steps.push(Step::Child(name)),
+                }
+            }
+            '[' => {
+                let close = chars[i..]
+                    .iter()
+                    .position(|&c| c == ']')
+                    .map(|p| i + p)
+                    .ok_or_else(|| format!("Unclosed `[` in {}", path))?;
+                let inner: String = chars[i + 1..close].iter().collect();
+                let inner = inner.trim();
+                let quoted = inner.len() >= 2
+                    && ((inner.starts_with('\'') && inner.ends_with('\''))
+                        || (inner.starts_with('"') && inner.ends_with('"')));
+                if quoted {
+                    steps.push(Step::Child(inner[1..inner.len() - 1].to_string()));
+                } else if inner == "*" {
+                    steps.push(Step::Wildcard);
+                } else if let Some((start, end)) = inner.split_once(':') {
+                    let bound = |s: &str| {
+                        (!s.trim().is_empty())
+                            .then(|| parse_index(s, path))
+                            .transpose()
+                    };
+                    steps.push(Step::Slice(bound(start)?, bound(end)?));
+                } else {
+                    steps.push(Step::Index(parse_index(inner, path)?));
+                }
+                i = close + 1;
+            }
+            c => return Err(format!("Unexpected `{}` in {}", c, path)),
+        }
+    }
+    Ok(steps)
+}
+
+/// Python style index, negative counts from the end.
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+fn descendants<'a>(value: &'a Value, out: &mut Vec<&'a Value>) {
+    out.push(value);
+    match value {
+        Value::Object(map) => map.values().for_each(|v| descendants(v, out)),
+        Value::Array(items) => items.iter().for_each(|v| descendants(v, out)),
+        _ => {}
+    }
+}
+
+fn apply<'a>(step: &Step, value: &'a Value, out: &mut Vec<&'a Value>) {
+    match (step, value) {
+        (Step::Child(name), Value::Object(map)) => out.extend(map.get(name)),
+        (Step::Index(index), Value::Array(items)) => {
+            out.extend(resolve_index(*index, items.len()).map(|i| &items[i]))
+        }
+        (Step::Slice(start, end), Value::Array(items)) => {
+            let len = items.len() as i64;
+            let clamp = |i: i64| (if i < 0 { len + i } else { i }).clamp(0, len) as usize;
+            let start = clamp(start.unwrap_or(0));
+            let end = clamp(end.unwrap_or(len));
+            if start < end {
+                out.extend(&items[start..end]);
+            }
+        }
+        (Step::Wildcard, Value::Object(map)) => out.extend(map.values()),
+        (Step::Wildcard, Value::Array(items)) => out.extend(items),
+        (Step::Descendants, _) => descendants(value, out),
+        _ => {}
+    }
+}
+
+/// Every value `path` matches in `value`, in document order.
+pub fn select<'a>(value: &'a Value, path: &str) -> Result<Vec<&'a Value>, String> {
+    let mut nodes = vec![value];
+    for step in parse(path)? {
+        let mut next = vec![];
+        for node in nodes {
+            apply(&step, node, &mut next);
+        }
+        nodes = next;
+    }
+    Ok(nodes)
+}
+
+/// Like `select`, but a single match is returned as is and several as an
+/// array. `None` when nothing matches.
+pub fn select_one(value: &Value, path: &str) -> Result<Option<Value>, String> {
+    let matches = select(value, path)?;
+    Ok(match matches.as_slice() {
+        [] => None,
+        [single] => Some((*single).clone()),
+        many => Some(Value::Array(many.iter().map(|v| (*v).clone()).collect())),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample() -> Value {
+        json!({
+            "store": {
+                "bike": {"price": 20},
+                "books": [
+                    {"title": "A", "price": 8},
+                    {"title": "B", "price": 12},
+                    {"title": "C", "price": 5}
+                ]
+            },
+            "odd key": true
+        })
+    }
+
+    fn titles(path: &str) -> Vec<Value> {
+        select(&sample(), path)
+            .unwrap()
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn children_and_indexes() {
+        assert_eq!(titles("$.store.books[0].title"), [json!("A")]);
+        assert_eq!(titles("$['store']['books'][-1].title"), [json!("C")]);
+        assert_eq!(titles("$[\"odd key\"]"), [json!(true)]);
+        assert_eq!(titles("$"), [sample()]);
+        assert!(titles("$.store.books[3]").is_empty());
+        assert!(titles("$.store.missing.title").is_empty());
+    }
+
+    #[test]
+    fn slices_and_wildcards() {
+        assert_eq!(titles("$.store.books[1:].title"), [json!("B"), json!("C")]);
+        assert_eq!(titles("$.store.books[:-2].title"), [json!("A")]);
+        assert!(titles("$.store.books[2:1]").is_empty());
+        assert_eq!(
+            titles("$.store.books[*].title"),
+            [json!("A"), json!("B"), json!("C")]
+        );
+        assert_eq!(titles("$.store.bike.*"), [json!(20)]);
+    }
+
+    #[test]
+    fn recursive_descent() {
+        assert_eq!(
+            titles("$..price"),
+            [json!(20), json!(8), json!(12), json!(5)]
+        );
+        assert_eq!(titles("$..books[1].title"), [json!("B")]);
+    }
+
+    #[test]
+    fn select_one_wraps_several_matches() {
+        assert_eq!(
+            select_one(&sample(), "$.store.bike.price"),
+            Ok(Some(json!(20)))
+        );
+        assert_eq!(
+            select_one(&sample(), "$.store.books[:2].price"),
+            Ok(Some(json!([8, 12])))
+        );
+        assert_eq!(select_one(&sample(), "$.nothing"), Ok(None));
+    }
+
+    #[test]
+    fn invalid_paths() {
+        for path in ["store.books", "$.", "$.store[0", "$[x]", "$[1:y]", "$store"] {
+            assert!(select(&sample(), path).is_err(), "{}", path);
+        }
+    }
+}
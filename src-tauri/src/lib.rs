@@ -1,13 +1,22 @@
 mod commands;
 mod db;
+pub mod expression;
+pub mod jsonpath;
+pub mod manifest;
 pub mod migration;
 pub mod migrations;
+mod persist;
 mod plugins;
+mod providers;
 mod setup;
+pub mod template;
 
-use commands::{analytics, audio, chat, media, migrate, services, store, system, widget};
+use commands::{
+    analytics, audio, chat, cleanup, history, layouts, media, migrate, package, permissions,
+    secrets, services, shell, signing, store, subscriptions, system, variables, vault, widget,
+};
 use log::LevelFilter;
-use plugins::localhost;
+use plugins::{api, localhost};
 use setup::init::init_app;
 use std::{env, sync::OnceLock};
 use tauri::Manager;
@@ -25,6 +34,83 @@ pub fn get_custom_server_port() -> u16 {
 pub fn run() {
     let port = portpicker::pick_unused_port().expect("failed to find unused port");
 
+    let handler = tauri::generate_handler![
+        media::get_media,
+        media::start_media_listener_cmd,
+        media::stop_media_listener_cmd,
+        media::media_action,
+        media::get_media_metadata,
+        services::get_all_widgets,
+        services::copy_custom_assets,
+        services::copy_custom_assets_dir,
+        services::apply_blur_theme,
+        services::create_url_thumbnail,
+        services::update_manifest_value,
+        widget::create_creator_window,
+        widget::create_widget_window,
+        widget::close_widget_window,
+        widget::publish_widget,
+        widget::open_devtools,
+        widget::get_existing_keys_cmd,
+        widget::validate_manifest,
+        widget::duplicate_widget,
+        package::export_widget,
+        package::import_widget,
+        package::verify_widget_package,
+        signing::get_publisher_key,
+        signing::list_trusted_publishers,
+        signing::trust_publisher,
+        signing::untrust_publisher,
+        history::list_manifest_revisions,
+        history::diff_manifest_revisions,
+        history::restore_manifest_revision,
+        cleanup::delete_widget,
+        cleanup::unpublish_widget,
+        cleanup::gc_cache,
+        layouts::list_layouts,
+        layouts::save_layout,
+        layouts::apply_layout,
+        layouts::rename_layout,
+        layouts::delete_layout,
+        system::get_system_info,
+        subscriptions::list_subscriptions,
+        variables::render_template,
+        variables::subscribe_template,
+        variables::unsubscribe_template,
+        variables::list_variables,
+        shell::list_shell_commands,
+        shell::approve_shell_command,
+        shell::revoke_shell_command,
+        secrets::list_widget_secrets,
+        secrets::set_widget_secret,
+        secrets::delete_widget_secret,
+        secrets::get_widget_api_token,
+        secrets::reset_widget_api_token,
+        vault::get_model_key,
+        vault::set_model_key,
+        vault::delete_model_key,
+        vault::get_widget_password,
+        vault::set_widget_password,
+        vault::delete_widget_password,
+        analytics::track_analytics_event,
+        store::write_to_store_cmd,
+        migrate::migrate,
+        audio::start_audio_capture,
+        audio::stop_audio_capture,
+        audio::restart_audio_capture,
+        audio::get_current_device_cmd,
+        chat::create_chat,
+        chat::delete_chat,
+        chat::get_all_chats,
+        chat::load_chat,
+        chat::upsert_message,
+        chat::update_chat_name,
+        chat::update_chat_widget_keys,
+        chat::get_chat_by_id,
+        chat::query_media_history,
+        chat::create_assistant_window,
+    ];
+
     tauri::Builder::default()
         .plugin(
             tauri_plugin_log::Builder::default()
@@ -61,48 +147,39 @@ pub fn run() {
         .plugin(localhost::Builder::new(port).build())
         .manage(std::sync::Mutex::new(audio::AudioState::new()))
         .manage(tokio::sync::Mutex::new(media::MediaState::new()))
-        .invoke_handler(tauri::generate_handler![
-            media::get_media,
-            media::start_media_listener_cmd,
-            media::stop_media_listener_cmd,
-            media::media_action,
-            media::get_media_metadata,
-            services::get_all_widgets,
-            services::copy_custom_assets,
-            services::copy_custom_assets_dir,
-            services::apply_blur_theme,
-            services::create_url_thumbnail,
-            services::update_manifest_value,
-            widget::create_creator_window,
-            widget::create_widget_window,
-            widget::close_widget_window,
-            widget::publish_widget,
-            widget::open_devtools,
-            widget::get_existing_keys_cmd,
-            system::get_system_info,
-            analytics::track_analytics_event,
-            store::write_to_store_cmd,
-            migrate::migrate,
-            audio::start_audio_capture,
-            audio::stop_audio_capture,
-            audio::restart_audio_capture,
-            audio::get_current_device_cmd,
-            chat::create_chat,
-            chat::delete_chat,
-            chat::get_all_chats,
-            chat::load_chat,
-            chat::upsert_message,
-            chat::update_chat_name,
-            chat::update_chat_widget_keys,
-            chat::get_chat_by_id,
-            chat::query_media_history,
-            chat::create_assistant_window,
-        ])
+        .manage(tokio::sync::Mutex::new(
+            subscriptions::Subscriptions::default(),
+        ))
+        .manage(tokio::sync::Mutex::new(variables::VariableState::default()))
+        .manage(providers::ProviderRegistry::default())
+        .invoke_handler(move |invoke| {
+            // HTML widgets only reach the commands their permissions allow
+            let label = invoke.message.webview().label().to_string();
+            let args = match invoke.message.payload() {
+                tauri::ipc::InvokeBody::Json(args) => args.clone(),
+                _ => serde_json::Value::Null,
+            };
+            if let Err(e) = permissions::check_command(&label, invoke.message.command(), &args) {
+                invoke.resolver.reject(e);
+                return true;
+            }
+            handler(invoke)
+        })
         .setup(move |app| {
             CUSTOM_SERVER_PORT
                 .set(port)
                 .expect("Failed to set global port");
             init_app(&app)?;
+            api::init(app.handle());
+            // HTML widgets are served from `http://<token>.localhost:<port>`, the
+            // commands they reach are limited by `permissions::check_command`
+            app.add_capability(
+                tauri::ipc::CapabilityBuilder::new("html-widget")
+                    .window("widget-*")
+                    .remote(format!("http://*.localhost:{port}"))
+                    .permission("core:default")
+                    .permission("log:default"),
+            )?;
             Ok(())
         })
         .on_window_event(|window, event| match event {
@@ -113,6 +190,15 @@ pub fn run() {
                     api.prevent_close();
                 }
             }
+            tauri::WindowEvent::Destroyed => {
+                // Also covers windows that went away without close_widget_window
+                let app = window.app_handle().clone();
+                let label = window.label().to_string();
+                tauri::async_runtime::spawn(async move {
+                    subscriptions::unsubscribe_window(&app, &label).await;
+                    variables::unsubscribe_window_templates(&app, &label).await;
+                });
+            }
             _ => {}
         })
         .build(tauri::generate_context!())
@@ -0,0 +1,1186 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+};
+
+use crate::persist;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WidgetType {
+    #[default]
+    Json,
+    Url,
+    Html,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeMode {
+    Light,
+    Dark,
+    System,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Dimensions {
+    #[serde(serialize_with = "serialize_number")]
+    pub width: f64,
+    #[serde(serialize_with = "serialize_number")]
+    pub height: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Position {
+    #[serde(serialize_with = "serialize_optional_number")]
+    pub x: Option<f64>,
+    #[serde(serialize_with = "serialize_optional_number")]
+    pub y: Option<f64>,
+}
+
+/// Keeps whole numbers as integers on disk, the way the frontend writes them.
+fn serialize_number<S: serde::Serializer>(n: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        serializer.serialize_i64(*n as i64)
+    } else {
+        serializer.serialize_f64(*n)
+    }
+}
+
+fn serialize_optional_number<S: serde::Serializer>(
+    n: &Option<f64>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match n {
+        Some(n) => serialize_number(n, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnchorPoint {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+/// Places the widget relative to a point of a monitor's work area instead of
+/// at an absolute position. Offsets are logical pixels from that point to the
+/// same point of the window.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Anchor {
+    pub point: AnchorPoint,
+    #[serde(default, serialize_with = "serialize_number")]
+    pub offset_x: f64,
+    #[serde(default, serialize_with = "serialize_number")]
+    pub offset_y: f64,
+    /// Index into the monitors sorted by position, the first one if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitor: Option<usize>,
+}
+
+/// Geometry of a connected monitor, in physical pixels.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MonitorInfo {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale: f64,
+}
+
+/// Where the widget was placed while a given set of monitors was connected.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MonitorPosition {
+    #[serde(serialize_with = "serialize_number")]
+    pub x: f64,
+    #[serde(serialize_with = "serialize_number")]
+    pub y: f64,
+    pub monitors: Vec<MonitorInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleMatch {
+    #[default]
+    All,
+    Any,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ScheduleRule {
+    /// Local time window as `HH:MM`, wrapping past midnight when `end` is
+    /// before `start`.
+    Time {
+        start: String,
+        end: String,
+    },
+    Weekdays {
+        days: Vec<Weekday>,
+    },
+    Battery {
+        #[serde(rename = "onBattery")]
+        on_battery: bool,
+    },
+    Media {
+        playing: bool,
+    },
+}
+
+/// When a visible widget should actually be on screen.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Schedule {
+    #[serde(rename = "match", default)]
+    pub match_mode: ScheduleMatch,
+    pub rules: Vec<ScheduleRule>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub mode: ThemeMode,
+    pub color: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CustomField {
+    pub key: String,
+    pub label: String,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AssetKind {
+    File,
+    Url,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CustomAsset {
+    pub kind: AssetKind,
+    pub path: String,
+    pub key: String,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub asset_type: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WidgetElement {
+    #[serde(rename = "type")]
+    pub element_type: String,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub styles: Option<Map<String, Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Map<String, Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<WidgetElement>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Typed view of a widget's `manifest.json`.
+///
+/// Fields the backend does not know about are kept in `extra` so that a
+/// read-modify-write never drops data written by the frontend.
+/// What HTML and URL widgets can ask for in `permissions`. JSON widgets are
+/// built by the app and are not limited.
+pub const PERMISSION_SCOPES: [&str; 9] = [
+    "media:read",
+    "media:control",
+    "audio:read",
+    "system:read",
+    "store",
+    "keyring",
+    "file:read",
+    "http:read",
+    "cmd:read",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WidgetManifest {
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub widget_type: WidgetType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<Dimensions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<Anchor>,
+    /// Positions per monitor configuration, keyed by its fingerprint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitor_positions: Option<BTreeMap<String, MonitorPosition>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visible: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub always_on_top: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell_commands: Option<Vec<ShellCommand>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_files: Option<Vec<DataFile>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_sources: Option<Vec<HttpSource>>,
+    /// Scopes an HTML or URL widget asks for, see `PERMISSION_SCOPES`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Vec<String>>,
+    /// Hosts an HTML widget may reach through the `/proxy` endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_hosts: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Serves an HTML widget straight from `file` and reloads it on changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dev: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elements: Option<Vec<WidgetElement>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_fields: Option<BTreeMap<String, CustomField>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_assets: Option<Vec<CustomAsset>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<Theme>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published_at: Option<Value>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A single validation problem, addressed by a path such as
+/// `$.elements[0].children[2].id`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ManifestIssue {
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error("invalid manifest: {}", format_issues(.0))]
+    Invalid(Vec<ManifestIssue>),
+}
+
+fn format_issues(issues: &[ManifestIssue]) -> String {
+    issues
+        .iter()
+        .map(|i| format!("{}: {}", i.path, i.message))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl WidgetManifest {
+    pub fn from_value(mut value: Value) -> Result<Self, ManifestError> {
+        // Older versions of `publish_widget` could write `"dimensions": {}`.
+        if let Some(obj) = value.as_object_mut() {
+            if obj
+                .get("dimensions")
+                .and_then(Value::as_object)
+                .is_some_and(Map::is_empty)
+            {
+                obj.remove("dimensions");
+            }
+        }
+        let issues = validate_manifest_value(&value);
+        if !issues.is_empty() {
+            return Err(ManifestError::Invalid(issues));
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, ManifestError> {
+        Self::from_value(serde_json::from_str(contents)?)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ManifestError> {
+        Self::parse(&persist::read(path)?)
+    }
+
+    /// Writes the manifest as pretty JSON and returns what was written.
+    pub fn save(&self, path: &Path) -> Result<String, ManifestError> {
+        let contents = serde_json::to_string_pretty(self)?;
+        persist::write(path, &contents)?;
+        Ok(contents)
+    }
+
+    pub fn to_value(&self) -> Result<Value, ManifestError> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    /// Sets a top level field by its JSON name, re-validating the result so
+    /// a bad value never reaches disk.
+    pub fn set_field(&mut self, field: &str, value: Value) -> Result<(), ManifestError> {
+        let mut json = self.to_value()?;
+        if let Value::Object(ref mut map) = json {
+            map.insert(field.to_string(), value);
+        }
+        *self = Self::from_value(json)?;
+        Ok(())
+    }
+
+    /// Manifest without the heavy design fields, as listed in the main window.
+    pub fn lite(&self) -> Result<Value, ManifestError> {
+        let mut json = self.to_value()?;
+        if let Some(obj) = json.as_object_mut() {
+            for field in [
+                "elements",
+                "dimensions",
+                "position",
+                "monitorPositions",
+                "customFields",
+                "customAssets",
+                "theme",
+            ] {
+                obj.remove(field);
+            }
+        }
+        Ok(json)
+    }
+
+    pub fn window_label(&self) -> String {
+        format!("widget-{}", self.key)
+    }
+
+    /// Keys of the files this widget uses from AppCache `assets/`, both
+    /// custom assets and images picked in the creator.
+    pub fn file_asset_keys(&self) -> Vec<String> {
+        fn walk(elements: &[WidgetElement], keys: &mut Vec<String>) {
+            for element in elements {
+                let image = element
+                    .data
+                    .as_ref()
+                    .and_then(|d| d.get("imageData"))
+                    .and_then(Value::as_object);
+                if let Some(image) = image {
+                    if image.get("kind").and_then(Value::as_str) == Some("file") {
+                        if let Some(key) = image.get("key").and_then(Value::as_str) {
+                            keys.push(key.to_string());
+                        }
+                    }
+                }
+                if let Some(children) = &element.children {
+                    walk(children, keys);
+                }
+            }
+        }
+
+        let mut keys: Vec<String> = self
+            .custom_assets
+            .iter()
+            .flatten()
+            .filter(|a| a.kind == AssetKind::File)
+            .map(|a| a.key.clone())
+            .collect();
+        if let Some(elements) = &self.elements {
+            walk(elements, &mut keys);
+        }
+        keys.retain(|k| !k.is_empty() && !k.contains(['/', '\\']) && k != "..");
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    /// Points file asset references at new keys, see `file_asset_keys`.
+    pub fn rename_asset_keys(&mut self, renamed: &HashMap<String, String>) {
+        fn walk(elements: &mut [WidgetElement], renamed: &HashMap<String, String>) {
+            for element in elements {
+                let image = element
+                    .data
+                    .as_mut()
+                    .and_then(|d| d.get_mut("imageData"))
+                    .and_then(Value::as_object_mut);
+                if let Some(image) = image {
+                    if image.get("kind").and_then(Value::as_str) == Some("file") {
+                        let new_key = image
+                            .get("key")
+                            .and_then(Value::as_str)
+                            .and_then(|key| renamed.get(key));
+                        if let Some(new_key) = new_key {
+                            image.insert("key".into(), Value::String(new_key.clone()));
+                        }
+                    }
+                }
+                if let Some(children) = &mut element.children {
+                    walk(children, renamed);
+                }
+            }
+        }
+
+        for asset in self.custom_assets.iter_mut().flatten() {
+            if asset.kind == AssetKind::File {
+                if let Some(new_key) = renamed.get(&asset.key) {
+                    asset.key = new_key.clone();
+                }
+            }
+        }
+        if let Some(elements) = &mut self.elements {
+            walk(elements, renamed);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommandOutput {
+    #[default]
+    Text,
+    Json,
+    KeyValue,
+}
+
+/// A program the `cmd` provider runs on an interval, once the user approved
+/// it for this widget. Results are `{{cmd:<id>.<field>}}` variables.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ShellCommand {
+    pub id: String,
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Seconds between runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<u64>,
+    /// Seconds before the process is killed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub output: CommandOutput,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DataFileFormat {
+    #[default]
+    Text,
+    Json,
+    Lines,
+    Csv,
+}
+
+/// A local file the `file` provider reads, and reads again when it changes.
+/// Results are `{{file:<id>.<field>}}` variables.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DataFile {
+    pub id: String,
+    /// Relative to the widget folder, or absolute
+    pub path: String,
+    #[serde(default)]
+    pub format: DataFileFormat,
+    /// Field name -> JSONPath, for `json`. Without it every value is a field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub select: Option<BTreeMap<String, String>>,
+    /// How many lines from the end to keep, for `lines`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lines: Option<usize>,
+    /// Row for `csv`, negative counts from the end
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub row: Option<i64>,
+    /// Column names, or indexes without a header, for `csv`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub columns: Option<Vec<String>>,
+    /// Whether the first `csv` row names the columns, the default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<char>,
+}
+
+/// An endpoint the `http` provider polls. Results are
+/// `{{http:<id>.<field>}}` variables.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpSource {
+    pub id: String,
+    /// May contain `{{secret:<name>}}`, like header values
+    pub url: String,
+    /// Values may contain `{{secret:<name>}}`, read from the keyring
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<BTreeMap<String, String>>,
+    /// Seconds between requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<u64>,
+    /// Seconds before a request is given up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// Field name -> JSONPath. Without it every value of a JSON response is a
+    /// field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub select: Option<BTreeMap<String, String>>,
+}
+
+/// `HH:MM` -> minutes since midnight.
+pub fn minutes_of_day(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+struct Validator {
+    issues: Vec<ManifestIssue>,
+}
+
+impl Validator {
+    fn push(&mut self, path: &str, message: impl Into<String>) {
+        self.issues.push(ManifestIssue {
+            path: path.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn expect_string(
+        &mut self,
+        obj: &Map<String, Value>,
+        parent: &str,
+        field: &str,
+        required: bool,
+    ) {
+        match obj.get(field) {
+            Some(Value::String(_)) => {}
+            None | Some(Value::Null) if !required => {}
+            None | Some(Value::Null) => self.push(&format!("{parent}.{field}"), "is required"),
+            Some(_) => self.push(&format!("{parent}.{field}"), "must be a string"),
+        }
+    }
+
+    fn expect_bool(&mut self, obj: &Map<String, Value>, parent: &str, field: &str) {
+        match obj.get(field) {
+            None | Some(Value::Null) | Some(Value::Bool(_)) => {}
+            Some(_) => self.push(&format!("{parent}.{field}"), "must be a boolean"),
+        }
+    }
+
+    fn expect_one_of(
+        &mut self,
+        obj: &Map<String, Value>,
+        parent: &str,
+        field: &str,
+        allowed: &[&str],
+        required: bool,
+    ) {
+        let path = format!("{parent}.{field}");
+        match obj.get(field) {
+            Some(Value::String(s)) if allowed.contains(&s.as_str()) => {}
+            None | Some(Value::Null) if !required => {}
+            None | Some(Value::Null) => self.push(&path, "is required"),
+            Some(_) => self.push(&path, format!("must be one of: {}", allowed.join(", "))),
+        }
+    }
+
+    fn key(&mut self, obj: &Map<String, Value>) {
+        match obj.get("key") {
+            Some(Value::String(key)) => {
+                if key.trim().is_empty() {
+                    self.push("$.key", "must not be empty");
+                } else if key == "." || key == ".." || key.contains(['/', '\\', ':']) {
+                    self.push("$.key", "must not contain path separators");
+                }
+            }
+            Some(_) => self.push("$.key", "must be a string"),
+            None => self.push("$.key", "is required"),
+        }
+    }
+
+    fn dimensions(&mut self, value: &Value) {
+        let Some(obj) = value.as_object() else {
+            self.push("$.dimensions", "must be an object");
+            return;
+        };
+        for field in ["width", "height"] {
+            let path = format!("$.dimensions.{field}");
+            match obj.get(field).map(Value::as_f64) {
+                Some(Some(n)) if n >= 0.0 => {}
+                Some(Some(_)) => self.push(&path, "must not be negative"),
+                Some(None) => self.push(&path, "must be a number"),
+                None => self.push(&path, "is required"),
+            }
+        }
+    }
+
+    fn position(&mut self, value: &Value) {
+        let Some(obj) = value.as_object() else {
+            self.push("$.position", "must be an object");
+            return;
+        };
+        for field in ["x", "y"] {
+            match obj.get(field) {
+                None | Some(Value::Null) | Some(Value::Number(_)) => {}
+                Some(_) => self.push(&format!("$.position.{field}"), "must be a number"),
+            }
+        }
+    }
+
+    fn anchor(&mut self, value: &Value) {
+        let Some(obj) = value.as_object() else {
+            self.push("$.anchor", "must be an object");
+            return;
+        };
+        self.expect_one_of(
+            obj,
+            "$.anchor",
+            "point",
+            &[
+                "top-left",
+                "top",
+                "top-right",
+                "left",
+                "center",
+                "right",
+                "bottom-left",
+                "bottom",
+                "bottom-right",
+            ],
+            true,
+        );
+        for field in ["offsetX", "offsetY"] {
+            match obj.get(field) {
+                None | Some(Value::Number(_)) => {}
+                Some(_) => self.push(&format!("$.anchor.{field}"), "must be a number"),
+            }
+        }
+        match obj.get("monitor") {
+            None | Some(Value::Null) => {}
+            Some(value) if value.as_u64().is_some() => {}
+            Some(_) => self.push("$.anchor.monitor", "must be a non-negative integer"),
+        }
+    }
+
+    fn monitor_positions(&mut self, value: &Value) {
+        let Some(entries) = value.as_object() else {
+            self.push("$.monitorPositions", "must be an object");
+            return;
+        };
+        for (fingerprint, entry) in entries {
+            let path = format!("$.monitorPositions.{fingerprint}");
+            let Some(obj) = entry.as_object() else {
+                self.push(&path, "must be an object");
+                continue;
+            };
+            self.expect_numbers(obj, &path, &["x", "y"]);
+            let Some(monitors) = obj.get("monitors").and_then(Value::as_array) else {
+                self.push(&format!("{path}.monitors"), "must be an array");
+                continue;
+            };
+            for (i, monitor) in monitors.iter().enumerate() {
+                let path = format!("{path}.monitors[{i}]");
+                match monitor.as_object() {
+                    Some(obj) => {
+                        self.expect_numbers(obj, &path, &["x", "y", "width", "height", "scale"])
+                    }
+                    None => self.push(&path, "must be an object"),
+                }
+            }
+        }
+    }
+
+    fn requires(&mut self, value: &Value) {
+        let Some(providers) = value.as_array() else {
+            self.push("$.requires", "must be an array");
+            return;
+        };
+        // Which namespaces exist is up to the provider registry
+        for (i, provider) in providers.iter().enumerate() {
+            if provider.as_str().is_none_or(str::is_empty) {
+                self.push(&format!("$.requires[{i}]"), "must be a non-empty string");
+            }
+        }
+    }
+
+    /// Ids of `shellCommands`, `dataFiles` and `httpSources` become variable names, so they
+    /// are kept to characters that read well in `{{ns:<id>.<field>}}`.
+    fn source_id(&mut self, obj: &Map<String, Value>, path: &str, ids: &mut HashSet<String>) {
+        match obj.get("id").and_then(Value::as_str) {
+            Some(id)
+                if id.is_empty()
+                    || !id
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') =>
+            {
+                self.push(&format!("{path}.id"), "must be letters, digits, `_` or `-`")
+            }
+            Some(id) => {
+                if !ids.insert(id.to_string()) {
+                    self.push(&format!("{path}.id"), format!("duplicate id `{id}`"));
+                }
+            }
+            None => self.expect_string(obj, path, "id", true),
+        }
+    }
+
+    fn json_select(&mut self, obj: &Map<String, Value>, path: &str) {
+        match obj.get("select") {
+            None | Some(Value::Null) => {}
+            Some(Value::Object(fields)) => {
+                for (field, selector) in fields {
+                    if !selector.as_str().is_some_and(|s| s.starts_with('$')) {
+                        self.push(
+                            &format!("{path}.select.{field}"),
+                            "must be a JSONPath starting with `$`",
+                        );
+                    }
+                }
+            }
+            Some(_) => self.push(&format!("{path}.select"), "must be an object"),
+        }
+    }
+
+    fn seconds(&mut self, obj: &Map<String, Value>, path: &str, fields: &[&str]) {
+        for field in fields {
+            match obj.get(*field) {
+                None | Some(Value::Null) => {}
+                Some(n) if n.as_u64().is_some_and(|n| n > 0) => {}
+                Some(_) => self.push(
+                    &format!("{path}.{field}"),
+                    "must be a positive whole number of seconds",
+                ),
+            }
+        }
+    }
+
+    fn http_sources(&mut self, value: &Value) {
+        let Some(sources) = value.as_array() else {
+            self.push("$.httpSources", "must be an array");
+            return;
+        };
+        let mut ids = HashSet::new();
+        for (i, source) in sources.iter().enumerate() {
+            let path = format!("$.httpSources[{i}]");
+            let Some(obj) = source.as_object() else {
+                self.push(&path, "must be an object");
+                continue;
+            };
+            self.source_id(obj, &path, &mut ids);
+            match obj.get("url").and_then(Value::as_str) {
+                Some(url) if !(url.starts_with("http://") || url.starts_with("https://")) => {
+                    self.push(&format!("{path}.url"), "must be an http or https URL")
+                }
+                Some(_) => {}
+                None => self.expect_string(obj, &path, "url", true),
+            }
+            match obj.get("headers") {
+                None | Some(Value::Null) => {}
+                Some(Value::Object(headers)) => {
+                    for (name, value) in headers {
+                        if !value.is_string() {
+                            self.push(&format!("{path}.headers.{name}"), "must be a string");
+                        }
+                    }
+                }
+                Some(_) => self.push(&format!("{path}.headers"), "must be an object"),
+            }
+            self.seconds(obj, &path, &["interval", "timeout"]);
+            self.json_select(obj, &path);
+        }
+    }
+
+    fn permissions(&mut self, value: &Value) {
+        let Some(scopes) = value.as_array() else {
+            self.push("$.permissions", "must be an array");
+            return;
+        };
+        for (i, scope) in scopes.iter().enumerate() {
+            if !scope
+                .as_str()
+                .is_some_and(|s| PERMISSION_SCOPES.contains(&s))
+            {
+                self.push(
+                    &format!("$.permissions[{i}]"),
+                    format!("must be one of {}", PERMISSION_SCOPES.join(", ")),
+                );
+            }
+        }
+    }
+
+    /// `api.example.com`, or `*.example.com` for its subdomains.
+    fn proxy_hosts(&mut self, value: &Value) {
+        let Some(hosts) = value.as_array() else {
+            self.push("$.proxyHosts", "must be an array");
+            return;
+        };
+        for (i, host) in hosts.iter().enumerate() {
+            let valid = host.as_str().is_some_and(|host| {
+                let name = host.strip_prefix("*.").unwrap_or(host);
+                !name.is_empty()
+                    && name.split('.').all(|label| {
+                        !label.is_empty()
+                            && label
+                                .chars()
+                                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+                    })
+            });
+            if !valid {
+                self.push(
+                    &format!("$.proxyHosts[{i}]"),
+                    "must be a lowercase host name like `api.example.com` or `*.example.com`",
+                );
+            }
+        }
+    }
+
+    fn data_files(&mut self, value: &Value) {
+        let Some(files) = value.as_array() else {
+            self.push("$.dataFiles", "must be an array");
+            return;
+        };
+        let mut ids = HashSet::new();
+        for (i, file) in files.iter().enumerate() {
+            let path = format!("$.dataFiles[{i}]");
+            let Some(obj) = file.as_object() else {
+                self.push(&path, "must be an object");
+                continue;
+            };
+            self.source_id(obj, &path, &mut ids);
+            match obj.get("path").and_then(Value::as_str) {
+                Some("") => self.push(&format!("{path}.path"), "must not be empty"),
+                _ => self.expect_string(obj, &path, "path", true),
+            }
+            self.expect_one_of(
+                obj,
+                &path,
+                "format",
+                &["text", "json", "lines", "csv"],
+                false,
+            );
+            self.json_select(obj, &path);
+            match obj.get("lines") {
+                None | Some(Value::Null) => {}
+                Some(n) if n.as_u64().is_some_and(|n| n > 0) => {}
+                Some(_) => self.push(&format!("{path}.lines"), "must be a positive whole number"),
+            }
+            match obj.get("row") {
+                None | Some(Value::Null) => {}
+                Some(n) if n.is_i64() => {}
+                Some(_) => self.push(&format!("{path}.row"), "must be a whole number"),
+            }
+            match obj.get("columns") {
+                None | Some(Value::Null) => {}
+                Some(Value::Array(columns)) if columns.iter().all(Value::is_string) => {}
+                Some(_) => self.push(&format!("{path}.columns"), "must be an array of strings"),
+            }
+            self.expect_bool(obj, &path, "header");
+            match obj.get("delimiter") {
+                None | Some(Value::Null) => {}
+                Some(Value::String(d)) if d.chars().count() == 1 => {}
+                Some(_) => self.push(&format!("{path}.delimiter"), "must be a single character"),
+            }
+        }
+    }
+
+    fn shell_commands(&mut self, value: &Value) {
+        let Some(commands) = value.as_array() else {
+            self.push("$.shellCommands", "must be an array");
+            return;
+        };
+        let mut ids = HashSet::new();
+        for (i, command) in commands.iter().enumerate() {
+            let path = format!("$.shellCommands[{i}]");
+            let Some(obj) = command.as_object() else {
+                self.push(&path, "must be an object");
+                continue;
+            };
+            self.source_id(obj, &path, &mut ids);
+            match obj.get("command").and_then(Value::as_str) {
+                Some("") => self.push(&format!("{path}.command"), "must not be empty"),
+                _ => self.expect_string(obj, &path, "command", true),
+            }
+            match obj.get("args") {
+                None | Some(Value::Null) => {}
+                Some(Value::Array(args)) if args.iter().all(Value::is_string) => {}
+                Some(_) => self.push(&format!("{path}.args"), "must be an array of strings"),
+            }
+            self.seconds(obj, &path, &["interval", "timeout"]);
+            self.expect_one_of(obj, &path, "output", &["text", "json", "key-value"], false);
+        }
+    }
+
+    fn schedule(&mut self, value: &Value) {
+        let Some(obj) = value.as_object() else {
+            self.push("$.schedule", "must be an object");
+            return;
+        };
+        self.expect_one_of(obj, "$.schedule", "match", &["all", "any"], false);
+        let Some(rules) = obj.get("rules").and_then(Value::as_array) else {
+            self.push("$.schedule.rules", "must be an array");
+            return;
+        };
+        for (i, rule) in rules.iter().enumerate() {
+            let path = format!("$.schedule.rules[{i}]");
+            let Some(obj) = rule.as_object() else {
+                self.push(&path, "must be an object");
+                continue;
+            };
+            match obj.get("type").and_then(Value::as_str) {
+                Some("time") => {
+                    for field in ["start", "end"] {
+                        let time = obj.get(field).and_then(Value::as_str);
+                        if time.and_then(minutes_of_day).is_none() {
+                            self.push(&format!("{path}.{field}"), "must be a time as HH:MM");
+                        }
+                    }
+                }
+                Some("weekdays") => {
+                    let days = obj.get("days").and_then(Value::as_array);
+                    let valid = days.is_some_and(|days| {
+                        days.iter().all(|d| {
+                            matches!(
+                                d.as_str(),
+                                Some("mon" | "tue" | "wed" | "thu" | "fri" | "sat" | "sun")
+                            )
+                        })
+                    });
+                    if !valid {
+                        self.push(
+                            &format!("{path}.days"),
+                            "must be an array of mon, tue, wed, thu, fri, sat, sun",
+                        );
+                    }
+                }
+                Some("battery") => self.expect_required_bool(obj, &path, "onBattery"),
+                Some("media") => self.expect_required_bool(obj, &path, "playing"),
+                _ => self.push(
+                    &format!("{path}.type"),
+                    "must be one of: time, weekdays, battery, media",
+                ),
+            }
+        }
+    }
+
+    fn expect_required_bool(&mut self, obj: &Map<String, Value>, parent: &str, field: &str) {
+        match obj.get(field) {
+            Some(Value::Bool(_)) => {}
+            Some(_) => self.push(&format!("{parent}.{field}"), "must be a boolean"),
+            None => self.push(&format!("{parent}.{field}"), "is required"),
+        }
+    }
+
+    fn expect_numbers(&mut self, obj: &Map<String, Value>, parent: &str, fields: &[&str]) {
+        for field in fields {
+            match obj.get(*field) {
+                Some(Value::Number(_)) => {}
+                Some(_) => self.push(&format!("{parent}.{field}"), "must be a number"),
+                None => self.push(&format!("{parent}.{field}"), "is required"),
+            }
+        }
+    }
+
+    fn elements(&mut self, value: &Value, path: &str, ids: &mut HashSet<String>) {
+        let Some(elements) = value.as_array() else {
+            self.push(path, "must be an array");
+            return;
+        };
+        for (i, element) in elements.iter().enumerate() {
+            let path = format!("{path}[{i}]");
+            let Some(obj) = element.as_object() else {
+                self.push(&path, "must be an object");
+                continue;
+            };
+            self.expect_string(obj, &path, "type", true);
+            match obj.get("id") {
+                Some(Value::String(id)) if id.is_empty() => {
+                    self.push(&format!("{path}.id"), "must not be empty")
+                }
+                Some(Value::String(id)) => {
+                    if !ids.insert(id.clone()) {
+                        self.push(
+                            &format!("{path}.id"),
+                            format!("duplicate element id `{id}`"),
+                        );
+                    }
+                }
+                Some(_) => self.push(&format!("{path}.id"), "must be a string"),
+                None => self.push(&format!("{path}.id"), "is required"),
+            }
+            self.expect_string(obj, &path, "label", false);
+            for field in ["styles", "data"] {
+                match obj.get(field) {
+                    None | Some(Value::Object(_)) => {}
+                    Some(_) => self.push(&format!("{path}.{field}"), "must be an object"),
+                }
+            }
+            if let Some(children) = obj.get("children") {
+                self.elements(children, &format!("{path}.children"), ids);
+            }
+        }
+    }
+
+    fn custom_fields(&mut self, value: &Value) {
+        let Some(fields) = value.as_object() else {
+            self.push("$.customFields", "must be an object");
+            return;
+        };
+        for (id, field) in fields {
+            let path = format!("$.customFields.{id}");
+            let Some(obj) = field.as_object() else {
+                self.push(&path, "must be an object");
+                continue;
+            };
+            for name in ["key", "label", "value"] {
+                self.expect_string(obj, &path, name, true);
+            }
+            self.expect_string(obj, &path, "description", false);
+        }
+    }
+
+    fn custom_assets(&mut self, value: &Value) {
+        let Some(assets) = value.as_array() else {
+            self.push("$.customAssets", "must be an array");
+            return;
+        };
+        for (i, asset) in assets.iter().enumerate() {
+            let path = format!("$.customAssets[{i}]");
+            let Some(obj) = asset.as_object() else {
+                self.push(&path, "must be an object");
+                continue;
+            };
+            self.expect_one_of(obj, &path, "kind", &["file", "url"], true);
+            self.expect_string(obj, &path, "path", true);
+            match obj.get("key") {
+                Some(Value::String(key)) if key.is_empty() || key.contains(['/', '\\']) => {
+                    self.push(&format!("{path}.key"), "must be a non-empty file name")
+                }
+                _ => self.expect_string(obj, &path, "key", true),
+            }
+            self.expect_string(obj, &path, "type", false);
+        }
+    }
+
+    fn theme(&mut self, value: &Value) {
+        if value.is_null() {
+            return;
+        }
+        let Some(obj) = value.as_object() else {
+            self.push("$.theme", "must be an object or null");
+            return;
+        };
+        self.expect_one_of(obj, "$.theme", "mode", &["light", "dark", "system"], true);
+        self.expect_string(obj, "$.theme", "color", true);
+    }
+}
+
+/// Checks a raw manifest and returns every problem found, rather than
+/// stopping at the first one like `serde` would.
+pub fn validate_manifest_value(value: &Value) -> Vec<ManifestIssue> {
+    let mut v = Validator { issues: vec![] };
+    let Some(obj) = value.as_object() else {
+        v.push("$", "manifest must be a JSON object");
+        return v.issues;
+    };
+
+    v.key(obj);
+    v.expect_string(obj, "$", "label", false);
+    v.expect_string(obj, "$", "description", false);
+    v.expect_one_of(obj, "$", "widgetType", &["json", "url", "html"], false);
+    for field in ["visible", "alwaysOnTop", "pinned", "published", "dev"] {
+        v.expect_bool(obj, "$", field);
+    }
+    match obj.get("publishedAt") {
+        None | Some(Value::Null) | Some(Value::Number(_)) | Some(Value::String(_)) => {}
+        Some(_) => v.push("$.publishedAt", "must be a number or a string"),
+    }
+
+    match obj.get("widgetType").and_then(Value::as_str) {
+        Some("url") => v.expect_string(obj, "$", "url", true),
+        Some("html") => v.expect_string(obj, "$", "file", true),
+        _ => {
+            v.expect_string(obj, "$", "url", false);
+            v.expect_string(obj, "$", "file", false);
+        }
+    }
+
+    let field = |name: &str| obj.get(name).filter(|v| !v.is_null());
+    if let Some(dimensions) = field("dimensions") {
+        v.dimensions(dimensions);
+    }
+    if let Some(position) = field("position") {
+        v.position(position);
+    }
+    if let Some(anchor) = field("anchor") {
+        v.anchor(anchor);
+    }
+    if let Some(monitor_positions) = field("monitorPositions") {
+        v.monitor_positions(monitor_positions);
+    }
+    if let Some(requires) = field("requires") {
+        v.requires(requires);
+    }
+    if let Some(schedule) = field("schedule") {
+        v.schedule(schedule);
+    }
+    if let Some(shell_commands) = field("shellCommands") {
+        v.shell_commands(shell_commands);
+    }
+    if let Some(data_files) = field("dataFiles") {
+        v.data_files(data_files);
+    }
+    if let Some(http_sources) = field("httpSources") {
+        v.http_sources(http_sources);
+    }
+    if let Some(permissions) = field("permissions") {
+        v.permissions(permissions);
+    }
+    if let Some(proxy_hosts) = field("proxyHosts") {
+        v.proxy_hosts(proxy_hosts);
+    }
+    if let Some(elements) = field("elements") {
+        v.elements(elements, "$.elements", &mut HashSet::new());
+    }
+    if let Some(custom_fields) = field("customFields") {
+        v.custom_fields(custom_fields);
+    }
+    if let Some(custom_assets) = field("customAssets") {
+        v.custom_assets(custom_assets);
+    }
+    if let Some(theme) = obj.get("theme") {
+        v.theme(theme);
+    }
+
+    v.issues
+}
@@ -2,9 +2,11 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::path::Path;
-use std::{fs, path::PathBuf};
+use std::{collections::BTreeMap, fs, path::PathBuf};
 use tauri::{AppHandle, Manager};
 
+use crate::manifest::WidgetManifest;
+use crate::persist;
 use crate::setup::init::TEMPLATES;
 use crate::setup::utils::copy_embedded_dir;
 
@@ -19,18 +21,19 @@ pub trait Migration {
     fn down(&self, json: &mut Value);
 
     fn apply_to_file(&self, path: &Path, direction: Direction) -> Result<()> {
-        let mut file_content = std::fs::read_to_string(path)?;
-        let mut json: Value = serde_json::from_str(&file_content)?;
+        persist::with_lock(path, || {
+            let mut json: Value = serde_json::from_str(&persist::read(path)?)?;
 
-        match direction {
-            Direction::Up => self.up(&mut json),
-            Direction::Down => self.down(&mut json),
-        }
+            match direction {
+                Direction::Up => self.up(&mut json),
+                Direction::Down => self.down(&mut json),
+            }
 
-        file_content = serde_json::to_string_pretty(&json)?;
-        std::fs::write(path, file_content)?;
+            // Only write back manifests that are still valid after the migration
+            WidgetManifest::from_value(json)?.save(path)?;
 
-        Ok(())
+            Ok(())
+        })
     }
 
     fn seed_new_widget(&self) -> Option<&str> {
@@ -67,6 +70,66 @@ pub trait Migration {
 #[derive(Serialize, Deserialize, Default)]
 struct MigrationState {
     applied: Vec<String>,
+    /// Widget folder -> applied migrations that failed on it, in order. They
+    /// are retried on the next run before anything else touches the widget.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pending: BTreeMap<String, Vec<String>>,
+}
+
+/// Runs the pending migrations of one widget in order, stopping at the first
+/// that fails so later ones never see a manifest an earlier one skipped.
+fn retry_pending(
+    migrations: &[Box<dyn Migration>],
+    manifest_path: &Path,
+    pending: &mut Vec<String>,
+) {
+    while let Some(name) = pending.first() {
+        let Some(migration) = migrations.iter().find(|m| m.name() == name.as_str()) else {
+            pending.remove(0);
+            continue;
+        };
+        if let Err(e) = migration.apply_to_file(manifest_path, Direction::Up) {
+            eprintln!(
+                "Migration {} still fails for {}: {}",
+                name,
+                manifest_path.display(),
+                e
+            );
+            return;
+        }
+        println!("⬆️ Applied pending {} to {}", name, manifest_path.display());
+        pending.remove(0);
+    }
+}
+
+fn read_state(state_path: &Path) -> Result<MigrationState> {
+    Ok(if state_path.exists() {
+        serde_json::from_str(&persist::read(state_path)?)?
+    } else {
+        MigrationState::default()
+    })
+}
+
+/// Names of the migrations already applied to the installed widgets.
+pub fn applied_migrations(app: &AppHandle) -> Result<Vec<String>> {
+    let state_path = app.path().app_data_dir()?.join(".migrations.json");
+    Ok(read_state(&state_path)?.applied)
+}
+
+/// Brings a manifest from another install up to date, running every manifest
+/// migration that install had not applied yet.
+pub fn migrate_manifest_value(
+    json: &mut Value,
+    migrations: Vec<Box<dyn Migration>>,
+    applied: &[String],
+) {
+    for migration in migrations {
+        if migration.seed_new_widget().is_none()
+            && !applied.iter().any(|name| name == migration.name())
+        {
+            migration.up(json);
+        }
+    }
 }
 
 pub fn run_migrations(
@@ -80,22 +143,30 @@ pub fn run_migrations(
         .expect("failed to resolve app data dir");
     let state_path = app_data_dir.join(".migrations.json");
 
-    let mut state: MigrationState = if state_path.exists() {
-        serde_json::from_str(&fs::read_to_string(&state_path)?)?
-    } else {
-        MigrationState::default()
-    };
+    let mut state = read_state(&state_path)?;
 
     let widgets_root = app_data_dir.join("widgets");
     let widget_dirs = fs::read_dir(&widgets_root)?
         .filter_map(|e| e.ok())
         .filter(|e| e.path().is_dir())
         .collect::<Vec<_>>();
+    state
+        .pending
+        .retain(|dir, pending| !pending.is_empty() && widgets_root.join(dir).is_dir());
 
     match direction {
         Direction::Up => {
+            for (dir, pending) in state.pending.iter_mut() {
+                retry_pending(
+                    &migrations,
+                    &widgets_root.join(dir).join("manifest.json"),
+                    pending,
+                );
+            }
+            state.pending.retain(|_, pending| !pending.is_empty());
+
             let total = migrations.len();
-            for migration in migrations {
+            for migration in &migrations {
                 let name = migration.name().to_string();
                 if !state.applied.contains(&name) {
                     println!("⬆️ Running migration: {}", name);
@@ -103,8 +174,25 @@ pub fn run_migrations(
                         migration.add_new_widget(new_widget_name, &widgets_root)?;
                     } else {
                         for widget in &widget_dirs {
+                            let dir = widget.file_name().to_string_lossy().to_string();
                             let manifest_path = widget.path().join("manifest.json");
-                            migration.apply_to_file(&manifest_path, Direction::Up)?;
+                            if !manifest_path.exists() {
+                                continue;
+                            }
+                            // Queued behind an earlier failure, keep the order
+                            if let Some(pending) = state.pending.get_mut(&dir) {
+                                pending.push(name.clone());
+                                continue;
+                            }
+                            if let Err(e) = migration.apply_to_file(&manifest_path, Direction::Up) {
+                                eprintln!(
+                                    "Migration {} failed for {}, retrying next start: {}",
+                                    name,
+                                    manifest_path.display(),
+                                    e
+                                );
+                                state.pending.insert(dir, vec![name.clone()]);
+                            }
                         }
                     };
                     state.applied.push(name);
@@ -122,9 +210,21 @@ pub fn run_migrations(
                         migration.remove_widget(widget_name, &widgets_root)?;
                     } else {
                         for widget in &widget_dirs {
+                            let dir = widget.file_name().to_string_lossy().to_string();
+                            // Never applied to this widget, nothing to undo
+                            if let Some(pending) = state.pending.get_mut(&dir) {
+                                if let Some(at) = pending.iter().position(|name| *name == last) {
+                                    pending.remove(at);
+                                    continue;
+                                }
+                            }
                             let manifest_path = widget.path().join("manifest.json");
-                            migration.apply_to_file(&manifest_path, Direction::Down)?;
+                            if let Err(e) = migration.apply_to_file(&manifest_path, Direction::Down)
+                            {
+                                eprintln!("Skipping {}: {}", manifest_path.display(), e);
+                            }
                         }
+                        state.pending.retain(|_, pending| !pending.is_empty());
                     }
                 }
                 println!("🎉 Rolled back last migration");
@@ -134,6 +234,6 @@ pub fn run_migrations(
         }
     }
 
-    fs::write(state_path, serde_json::to_string_pretty(&state)?)?;
+    persist::write(&state_path, serde_json::to_string_pretty(&state)?)?;
     Ok(())
 }
@@ -0,0 +1,174 @@
+//! Crash-safe reads and writes of the JSON files the app keeps, like
+//! `store.json` and widget manifests.
+//!
+//! Writes go to `<file>.tmp` and are renamed over the file, so it is never
+//! seen half written. The previous contents are kept in `<file>.bak` when they
+//! were valid JSON, and reads fall back to it when the file is corrupt.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex, OnceLock},
+    thread::{self, ThreadId},
+};
+
+const TMP_EXTENSION: &str = "tmp";
+const BACKUP_EXTENSION: &str = "bak";
+
+/// Owner thread and depth of every locked file. Locks are reentrant so a
+/// read-modify-write can call `read` and `write` while holding one.
+#[derive(Default)]
+struct Locks {
+    held: Mutex<HashMap<PathBuf, (ThreadId, usize)>>,
+    released: Condvar,
+}
+
+fn locks() -> &'static Locks {
+    static LOCKS: OnceLock<Locks> = OnceLock::new();
+    LOCKS.get_or_init(Locks::default)
+}
+
+struct FileLock(PathBuf);
+
+impl FileLock {
+    fn acquire(path: &Path) -> Self {
+        let key = lock_key(path);
+        let me = thread::current().id();
+        let locks = locks();
+        let mut held = locks.held.lock().unwrap();
+        loop {
+            match held.get_mut(&key) {
+                Some((owner, depth)) if *owner == me => {
+                    *depth += 1;
+                    break;
+                }
+                Some(_) => held = locks.released.wait(held).unwrap(),
+                None => {
+                    held.insert(key.clone(), (me, 1));
+                    break;
+                }
+            }
+        }
+        Self(key)
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let locks = locks();
+        let mut held = locks.held.lock().unwrap();
+        if let Some((_, depth)) = held.get_mut(&self.0) {
+            *depth -= 1;
+            if *depth == 0 {
+                held.remove(&self.0);
+                locks.released.notify_all();
+            }
+        }
+    }
+}
+
+/// The same file can be reached through differently spelled paths.
+fn lock_key(path: &Path) -> PathBuf {
+    match (path.parent().map(fs::canonicalize), path.file_name()) {
+        (Some(Ok(parent)), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    sibling(path, BACKUP_EXTENSION)
+}
+
+/// Whether `path` is a temporary or backup file kept next to another one.
+pub fn is_sidecar(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == TMP_EXTENSION || ext == BACKUP_EXTENSION)
+}
+
+fn is_json(contents: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(contents).is_ok()
+}
+
+/// Runs `f` while no other thread reads or writes `path` through this module.
+pub fn with_lock<T>(path: &Path, f: impl FnOnce() -> T) -> T {
+    let _lock = FileLock::acquire(path);
+    f()
+}
+
+/// Replaces `path` with `contents` in one step.
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    with_lock(path, || {
+        let tmp_path = sibling(path, TMP_EXTENSION);
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(contents.as_ref())?;
+        file.sync_all()?;
+        drop(file);
+
+        // Only a file that parses is worth going back to
+        if fs::read_to_string(path).is_ok_and(|current| is_json(&current)) {
+            if let Err(e) = fs::copy(path, backup_path(path)) {
+                eprintln!("Error backing up {}: {}", path.display(), e);
+            }
+        }
+        fs::rename(&tmp_path, path)
+    })
+}
+
+/// Reads `path`, restoring it from its backup when it is not valid JSON.
+/// Corrupt contents without a usable backup are returned as they are, for
+/// the caller to report.
+pub fn read(path: &Path) -> io::Result<String> {
+    with_lock(path, || {
+        let contents = fs::read_to_string(path)?;
+        if is_json(&contents) {
+            return Ok(contents);
+        }
+        match restore(path) {
+            Some(backup) => Ok(backup),
+            None => Ok(contents),
+        }
+    })
+}
+
+fn restore(path: &Path) -> Option<String> {
+    let backup = fs::read_to_string(backup_path(path))
+        .ok()
+        .filter(|backup| is_json(backup))?;
+    eprintln!("{} is corrupt, restoring its backup", path.display());
+    if let Err(e) = write(path, &backup) {
+        eprintln!("Error restoring {}: {}", path.display(), e);
+    }
+    Some(backup)
+}
+
+/// Checks `path` on startup: removes a write left over from a crash and
+/// restores the backup of a corrupt file.
+pub fn recover(path: &Path) {
+    with_lock(path, || {
+        let tmp_path = sibling(path, TMP_EXTENSION);
+        if tmp_path.exists() {
+            eprintln!("Removing unfinished write {}", tmp_path.display());
+            let _ = fs::remove_file(&tmp_path);
+        }
+        match fs::read_to_string(path) {
+            Ok(contents) if !is_json(&contents) => {
+                if restore(path).is_none() {
+                    eprintln!("{} is corrupt and has no backup", path.display());
+                }
+            }
+            Ok(_) => {}
+            // Not there is fine, it is created when first written
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => eprintln!("Error reading {}: {}", path.display(), e),
+        }
+    })
+}
@@ -0,0 +1,325 @@
+//! Versioned JSON API under `/api/v1/` on the localhost server, for URL
+//! widgets and pages that can not use Tauri IPC.
+//!
+//! HTML widgets call it from their own origin. Other pages send the widget's
+//! API token as `Authorization: Bearer <token>`, or as `?token=` where headers
+//! can not be set, like `EventSource`.
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    io::Read,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::channel,
+        OnceLock,
+    },
+};
+use tauri::{AppHandle, Listener, Manager};
+use tiny_http::{Header, Method, Response as HttpResponse};
+
+use super::{localhost, sse};
+use crate::{
+    commands::{
+        chat::{MediaQueryIntent, MediaQueryRequest},
+        media::{get_media, media_action},
+        permissions::widget_allows,
+        secrets::widget_for_api_token,
+        subscriptions::{subscribe, unsubscribe},
+        system::read_system_info,
+        utils::query_media_history_util,
+    },
+    db::DatabaseState,
+};
+
+pub const PREFIX: &str = "/api/";
+/// Providers `/api/v1/events` can stream, the event each one emits and the
+/// permission it needs
+const TOPICS: [(&str, &str, &str); 2] = [
+    ("media", "media_updated", "media:read"),
+    ("audio", "audio-samples", "audio:read"),
+];
+const MAX_BODY: u64 = 64 * 1024;
+
+static APP: OnceLock<AppHandle> = OnceLock::new();
+static NEXT_STREAM: AtomicU64 = AtomicU64::new(0);
+
+pub fn init(app: &AppHandle) {
+    let _ = APP.set(app.clone());
+}
+
+/// Set once the app is set up, requests can arrive before that.
+pub fn app() -> Option<&'static AppHandle> {
+    APP.get()
+}
+
+struct ApiError(u16, String);
+
+impl ApiError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self(status, message.into())
+    }
+}
+
+type ApiResult = Result<Value, ApiError>;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MediaActionBody {
+    player_id: String,
+    action: String,
+    position: Option<u64>,
+}
+
+/// The widget a request acts for, from its origin or its token.
+fn authorize(
+    app: &AppHandle,
+    req: &tiny_http::Request,
+    query: &[(String, String)],
+) -> Option<String> {
+    if let Some(widget_key) = localhost::origin_widget(localhost::request_header(req, "Host")) {
+        return Some(widget_key);
+    }
+    let token = localhost::request_header(req, "Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .or_else(|| {
+            query
+                .iter()
+                .find(|(name, _)| name == "token")
+                .map(|(_, value)| value.as_str())
+        })?;
+    widget_for_api_token(app, token)
+}
+
+fn respond(req: tiny_http::Request, status: u16, body: Option<Value>) {
+    let mut response = match body {
+        Some(body) => HttpResponse::from_data(body.to_string().into_bytes()),
+        None => HttpResponse::from_data(vec![]),
+    }
+    .with_status_code(status);
+    for (name, value) in cors_headers() {
+        if let Ok(header) = Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+            response.add_header(header);
+        }
+    }
+    if let Ok(header) = Header::from_bytes("Content-Type", "application/json") {
+        response.add_header(header);
+    }
+    if let Err(e) = req.respond(response) {
+        eprintln!("Error responding to API request: {}", e);
+    }
+}
+
+/// Tokens are sent in a header rather than cookies, so any origin may call.
+fn cors_headers() -> Vec<(String, String)> {
+    [
+        ("Access-Control-Allow-Origin", "*"),
+        ("Access-Control-Allow-Methods", "GET, POST, OPTIONS"),
+        (
+            "Access-Control-Allow-Headers",
+            "Authorization, Content-Type",
+        ),
+        ("Access-Control-Max-Age", "600"),
+        ("Cache-Control", "no-store"),
+    ]
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .to_vec()
+}
+
+fn query_value<'a>(query: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    query
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+fn read_body(req: &mut tiny_http::Request) -> Result<MediaActionBody, ApiError> {
+    let mut body = String::new();
+    req.as_reader()
+        .take(MAX_BODY)
+        .read_to_string(&mut body)
+        .map_err(|e| ApiError::new(400, e.to_string()))?;
+    serde_json::from_str(&body).map_err(|e| ApiError::new(400, e.to_string()))
+}
+
+/// The permission an endpoint needs, see `commands::permissions`.
+fn required_scope(method: &Method, path: &str) -> Option<&'static str> {
+    match (method, path) {
+        (Method::Get, "/api/v1/media" | "/api/v1/media/history") => Some("media:read"),
+        (Method::Post, "/api/v1/media") => Some("media:control"),
+        (Method::Get, "/api/v1/system") => Some("system:read"),
+        _ => None,
+    }
+}
+
+fn forbidden(req: tiny_http::Request, widget_key: &str, scope: &str) {
+    let message = format!("Widget {} needs the `{}` permission", widget_key, scope);
+    eprintln!("Refused API request: {}", message);
+    respond(req, 403, Some(json!({ "error": message })));
+}
+
+async fn route(
+    app: &AppHandle,
+    method: &Method,
+    path: &str,
+    query: &[(String, String)],
+    body: Option<MediaActionBody>,
+) -> ApiResult {
+    let server_error = |e: String| ApiError::new(500, e);
+    match (method, path) {
+        (Method::Get, "/api/v1/media") => {
+            let players = get_media(app.clone(), app.state())
+                .await
+                .map_err(|e| server_error(e.to_string()))?;
+            Ok(json!(players))
+        }
+        (Method::Post, "/api/v1/media") => {
+            let body = body.ok_or_else(|| ApiError::new(400, "Missing body"))?;
+            media_action(app.state(), body.player_id, body.action, body.position)
+                .await
+                .map_err(|e| server_error(e.to_string()))?;
+            Ok(Value::Null)
+        }
+        (Method::Get, "/api/v1/media/history") => {
+            let limit = match query_value(query, "limit") {
+                Some(limit) => Some(
+                    limit
+                        .parse::<i64>()
+                        .map_err(|_| ApiError::new(400, "limit must be a number"))?,
+                ),
+                None => None,
+            };
+            let request = MediaQueryRequest {
+                intent: MediaQueryIntent::History,
+                start_time: query_value(query, "start").map(str::to_string),
+                end_time: query_value(query, "end").map(str::to_string),
+                search_query: None,
+                limit,
+            };
+            query_media_history_util(&app.state::<DatabaseState>().0, request)
+                .await
+                .map_err(server_error)
+        }
+        (Method::Get, "/api/v1/system") => {
+            let has_network = query_value(query, "network") != Some("false");
+            tauri::async_runtime::spawn_blocking(move || read_system_info(Some(has_network)))
+                .await
+                .map_err(|e| server_error(e.to_string()))
+        }
+        (_, "/api/v1/media" | "/api/v1/media/history" | "/api/v1/system" | "/api/v1/events") => {
+            Err(ApiError::new(405, "Method not allowed"))
+        }
+        _ => Err(ApiError::new(404, "Not found")),
+    }
+}
+
+/// Mirrors the app's `media_updated` and `audio-samples` events. `?topics=`
+/// picks some of `media` and `audio`, both by default.
+fn stream_events(
+    app: &AppHandle,
+    req: tiny_http::Request,
+    query: &[(String, String)],
+    widget_key: &str,
+) {
+    let wanted: Vec<&str> = query_value(query, "topics")
+        .map(|topics| topics.split(',').map(str::trim).collect())
+        .unwrap_or_else(|| TOPICS.iter().map(|(topic, ..)| *topic).collect());
+    if let Some(unknown) = wanted
+        .iter()
+        .find(|topic| !TOPICS.iter().any(|(known, ..)| known == *topic))
+    {
+        return respond(
+            req,
+            400,
+            Some(json!({ "error": format!("Unknown topic: {}", unknown) })),
+        );
+    }
+    let topics: Vec<(&str, &str, &str)> = TOPICS
+        .into_iter()
+        .filter(|(topic, ..)| wanted.contains(topic))
+        .collect();
+    if let Some((_, _, scope)) = topics
+        .iter()
+        .find(|(_, _, scope)| !widget_allows(app, widget_key, scope))
+    {
+        return forbidden(req, widget_key, scope);
+    }
+
+    // Providers are started the same way windows subscribe to them
+    let label = format!("api-{}", NEXT_STREAM.fetch_add(1, Ordering::Relaxed));
+    let (sender, events) = channel();
+    let mut listeners = vec![];
+    for &(namespace, event, _) in &topics {
+        if let Err(e) = tauri::async_runtime::block_on(subscribe(app, &label, namespace)) {
+            eprintln!("Error starting {} for the API: {}", namespace, e);
+        }
+        let sender = sender.clone();
+        listeners.push(app.listen_any(event, move |e| {
+            let _ = sender.send(sse::Event::new(event, e.payload()));
+        }));
+    }
+    drop(sender);
+
+    let app = app.clone();
+    let namespaces: Vec<&str> = topics.iter().map(|(namespace, ..)| *namespace).collect();
+    sse::stream(req, cors_headers(), events, move || {
+        for listener in listeners {
+            app.unlisten(listener);
+        }
+        tauri::async_runtime::block_on(async {
+            for namespace in namespaces {
+                unsubscribe(&app, &label, namespace).await;
+            }
+        });
+    });
+}
+
+pub fn handle(mut req: tiny_http::Request) {
+    let Some(app) = app() else {
+        return respond(req, 503, Some(json!({ "error": "Starting up" })));
+    };
+    let Ok(url) = reqwest::Url::parse(&format!("http://localhost{}", req.url())) else {
+        return respond(req, 400, Some(json!({ "error": "Bad request" })));
+    };
+    let path = url.path().trim_end_matches('/').to_string();
+    let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+
+    if *req.method() == Method::Options {
+        return respond(req, 204, None);
+    }
+    let Some(widget_key) = authorize(app, &req, &query) else {
+        eprintln!("Refused API request for {}", path);
+        return respond(req, 401, Some(json!({ "error": "Unauthorized" })));
+    };
+    if *req.method() == Method::Get && path == "/api/v1/events" {
+        return stream_events(app, req, &query, &widget_key);
+    }
+    if let Some(scope) = required_scope(req.method(), &path) {
+        if !widget_allows(app, &widget_key, scope) {
+            return forbidden(req, &widget_key, scope);
+        }
+    }
+
+    let body = match *req.method() {
+        Method::Post if path == "/api/v1/media" => match read_body(&mut req) {
+            Ok(body) => Some(body),
+            Err(ApiError(status, message)) => {
+                return respond(req, status, Some(json!({ "error": message })))
+            }
+        },
+        _ => None,
+    };
+    let method = req.method().clone();
+    match tauri::async_runtime::block_on(route(app, &method, &path, &query, body)) {
+        Ok(Value::Null) => respond(req, 204, None),
+        Ok(value) => respond(req, 200, Some(value)),
+        Err(ApiError(status, message)) => {
+            eprintln!(
+                "API request {} for {} failed: {}",
+                path, widget_key, message
+            );
+            respond(req, status, Some(json!({ "error": message })))
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Tells HTML widget pages that the files in their folder changed, over
+//! Server-Sent Events at `/<key>/__live-reload`.
+
+use notify_debouncer_full::{
+    new_debouncer,
+    notify::{RecommendedWatcher, RecursiveMode},
+    DebounceEventResult, Debouncer, RecommendedCache,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex, OnceLock,
+    },
+    time::Duration,
+};
+
+use super::sse::{self, Event};
+
+/// Last path segment of the events endpoint, after the widget key
+pub const ENDPOINT: &str = "__live-reload";
+/// Bundlers write several files per build, they become one reload
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(200);
+
+type Clients = Arc<Mutex<Vec<(u64, Sender<Event>)>>>;
+
+struct Channel {
+    _debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+    clients: Clients,
+}
+
+/// One watcher per folder, kept while a page listens to it.
+#[derive(Default)]
+struct LiveReload {
+    channels: Mutex<HashMap<PathBuf, Channel>>,
+    next_id: Mutex<u64>,
+}
+
+fn live_reload() -> &'static LiveReload {
+    static LIVE_RELOAD: OnceLock<LiveReload> = OnceLock::new();
+    LIVE_RELOAD.get_or_init(LiveReload::default)
+}
+
+impl LiveReload {
+    fn subscribe(&self, folder: &Path, sender: Sender<Event>) -> Result<u64, String> {
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            *next_id
+        };
+        let mut channels = self.channels.lock().unwrap();
+        if let Some(channel) = channels.get(folder) {
+            channel.clients.lock().unwrap().push((id, sender));
+            return Ok(id);
+        }
+
+        let clients: Clients = Arc::new(Mutex::new(vec![(id, sender)]));
+        let notified = Arc::clone(&clients);
+        let mut debouncer = new_debouncer(
+            DEBOUNCE_TIMEOUT,
+            None,
+            move |result: DebounceEventResult| {
+                let changed = result.is_ok_and(|events| {
+                    events
+                        .iter()
+                        .map(|e| e.event.kind)
+                        .any(|kind| kind.is_create() || kind.is_modify() || kind.is_remove())
+                });
+                if changed {
+                    notified
+                        .lock()
+                        .unwrap()
+                        .retain(|(_, sender)| sender.send(Event::new("reload", "")).is_ok());
+                }
+            },
+        )
+        .map_err(|e| e.to_string())?;
+        debouncer
+            .watch(folder, RecursiveMode::Recursive)
+            .map_err(|e| e.to_string())?;
+        channels.insert(
+            folder.to_path_buf(),
+            Channel {
+                _debouncer: debouncer,
+                clients,
+            },
+        );
+        Ok(id)
+    }
+
+    fn unsubscribe(&self, folder: &Path, id: u64) {
+        let mut channels = self.channels.lock().unwrap();
+        let Some(channel) = channels.get(folder) else {
+            return;
+        };
+        let empty = {
+            let mut clients = channel.clients.lock().unwrap();
+            clients.retain(|(client, _)| *client != id);
+            clients.is_empty()
+        };
+        if empty {
+            channels.remove(folder);
+        }
+    }
+}
+
+/// Keeps the request open and sends a `reload` event whenever `folder` changes.
+pub fn serve(req: tiny_http::Request, folder: PathBuf) {
+    let (sender, events) = channel();
+    let id = match live_reload().subscribe(&folder, sender) {
+        Ok(id) => id,
+        Err(e) => {
+            eprintln!("Error watching {}: {}", folder.display(), e);
+            let response =
+                tiny_http::Response::from_string("500 Internal Server Error").with_status_code(500);
+            let _ = req.respond(response);
+            return;
+        }
+    };
+    sse::stream(req, vec![], events, move || {
+        live_reload().unsubscribe(&folder, id)
+    });
+}
+
+/// Adds the client that reloads the page on `reload` events, before
+/// `</body>` when there is one.
+pub fn inject_client(html: &str, endpoint: &str) -> String {
+    let endpoint = serde_json::to_string(endpoint).unwrap_or_default();
+    let script = format!(
+        "<script>new EventSource({endpoint}).addEventListener(\"reload\", () => location.reload());</script>"
+    );
+    let at = html
+        .to_ascii_lowercase()
+        .rfind("</body>")
+        .unwrap_or(html.len());
+    format!("{}{}{}", &html[..at], script, &html[at..])
+}
@@ -1,15 +1,363 @@
 //! Expose your apps assets through a localhost server instead of the default custom protocol.
 //!
 //! **Note: This plugins brings considerable security risks and you should only use it if you know what your are doing. If in doubt, use the default custom protocol implementation.**
+//!
+//! Every widget is served from its own origin, `http://<token>.localhost:<port>/<key>/`.
+//! The token is derived from a random secret created per session, so other
+//! local processes can not guess it and widgets can not reach each other's
+//! files.
 
-use std::{collections::HashMap, fs, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
 use http::Uri;
+use sha2::{Digest, Sha256};
 use tauri::{
     plugin::{Builder as PluginBuilder, TauriPlugin},
     Manager, Runtime,
 };
-use tiny_http::{Header, Response as HttpResponse, Server};
+use tiny_http::{Header, Method, Response as HttpResponse, Server, StatusCode};
+
+use super::{api, live_reload, proxy};
+
+/// Names Windows opens as devices in any folder
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Requests served at the same time, so a slow one does not hold up the rest
+const WORKERS: usize = 4;
+/// Bigger text files are sent as they are rather than compressed in memory
+const MAX_COMPRESSED_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Widget keys by the token of their origin, for widgets opened this session
+static ORIGINS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+static DEV_FOLDERS: Mutex<BTreeMap<String, PathBuf>> = Mutex::new(BTreeMap::new());
+static SESSION_SECRET: OnceLock<[u8; 32]> = OnceLock::new();
+
+fn session_secret() -> &'static [u8; 32] {
+    SESSION_SECRET.get_or_init(rand::random)
+}
+
+pub fn token_for(secret: &[u8], key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(secret);
+    hasher.update(key.as_bytes());
+    // 128 bits, and short enough for a DNS label
+    format!("{:x}", hasher.finalize())[..32].to_string()
+}
+
+/// Percent-encoded path of `file` in the widget's folder.
+fn widget_path(key: &str, file: &str) -> String {
+    let mut url = reqwest::Url::parse("http://localhost/").expect("valid base URL");
+    url.path_segments_mut()
+        .expect("http URLs have a path")
+        .pop_if_empty()
+        .push(key)
+        .extend(file.split('/'));
+    url.path().to_string()
+}
+
+/// URL of `file` in the widget's folder, on the widget's own origin.
+pub fn widget_url(port: u16, key: &str, file: &str) -> String {
+    let token = token_for(session_secret(), key);
+    ORIGINS
+        .lock()
+        .unwrap()
+        .insert(token.clone(), key.to_string());
+    format!("http://{token}.localhost:{port}{}", widget_path(key, file))
+}
+
+/// Serves the widget straight from `folder`, its source, instead of the copy
+/// under `files/<key>`, and adds the live reload client to its pages. `None`
+/// goes back to the copy.
+pub fn set_dev_folder(key: &str, folder: Option<PathBuf>) {
+    let mut dev_folders = DEV_FOLDERS.lock().unwrap();
+    match folder {
+        Some(folder) => dev_folders.insert(key.to_string(), folder),
+        None => dev_folders.remove(key),
+    };
+}
+
+/// Whether `key` is served straight from a dev folder, which reloads its
+/// pages itself.
+pub fn has_dev_folder(key: &str) -> bool {
+    DEV_FOLDERS.lock().unwrap().contains_key(key)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rejection {
+    BadRequest,
+    Forbidden,
+    NotFound,
+}
+
+impl Rejection {
+    fn status(self) -> (u16, &'static str) {
+        match self {
+            Self::BadRequest => (400, "400 Bad Request"),
+            Self::Forbidden => (403, "403 Forbidden"),
+            Self::NotFound => (404, "404 Not Found"),
+        }
+    }
+}
+
+fn percent_decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// A decoded path segment that names a file or folder, nothing that moves up,
+/// starts over at a root, or means something special to Windows.
+fn is_plain_segment(segment: &str) -> bool {
+    let stem = segment.split('.').next().unwrap_or_default();
+    !(segment.is_empty()
+        || segment == "."
+        || segment == ".."
+        || segment.contains(['/', '\\', ':', '\0'])
+        // Windows drops trailing dots and spaces, `.. ` would become `..`
+        || segment.ends_with(['.', ' '])
+        || RESERVED_NAMES
+            .iter()
+            .any(|name| stem.trim_end().eq_ignore_ascii_case(name)))
+}
+
+/// Splits a request path into the widget key and the file it asks for,
+/// relative to the widget folder. Empty segments are skipped and a trailing
+/// `/` asks for `index.html`.
+pub fn parse_path(path: &str) -> Result<(String, Vec<String>), Rejection> {
+    if !path.starts_with('/') {
+        return Err(Rejection::BadRequest);
+    }
+    let mut segments = vec![];
+    for raw in path.split('/').filter(|s| !s.is_empty()) {
+        let segment = percent_decode(raw).ok_or(Rejection::BadRequest)?;
+        if !is_plain_segment(&segment) {
+            return Err(Rejection::Forbidden);
+        }
+        segments.push(segment);
+    }
+    if segments.is_empty() {
+        return Err(Rejection::NotFound);
+    }
+    let key = segments.remove(0);
+    if segments.is_empty() || path.ends_with('/') {
+        segments.push("index.html".to_string());
+    }
+    Ok((key, segments))
+}
+
+/// `host` without its port.
+fn host_name(host: &str) -> &str {
+    host.rsplit_once(':')
+        .filter(|(_, port)| port.chars().all(|c| c.is_ascii_digit()))
+        .map_or(host, |(name, _)| name)
+}
+
+/// Whether the `Host` header is the origin of `key` for this session.
+pub fn is_authorized(secret: &[u8], host: Option<&str>, key: &str) -> bool {
+    let Some(host) = host else {
+        return false;
+    };
+    host_name(host).eq_ignore_ascii_case(&format!("{}.localhost", token_for(secret, key)))
+}
+
+/// The widget whose origin the `Host` header is, if it was opened this session.
+pub fn origin_widget(host: Option<&str>) -> Option<String> {
+    let name = host_name(host?).to_ascii_lowercase();
+    let token = name.strip_suffix(".localhost")?;
+    ORIGINS.lock().unwrap().get(token).cloned()
+}
+
+/// The file under `widget_root` a request path points to. Both are
+/// canonicalized, so links out of the widget folder are refused as well.
+pub fn resolve_path(widget_root: &Path, segments: &[String]) -> Result<PathBuf, Rejection> {
+    let widget_root = widget_root
+        .canonicalize()
+        .map_err(|_| Rejection::NotFound)?;
+    let mut path = widget_root.clone();
+    path.extend(segments);
+    let mut path = path.canonicalize().map_err(|_| Rejection::NotFound)?;
+    if path.is_dir() {
+        path = path
+            .join("index.html")
+            .canonicalize()
+            .map_err(|_| Rejection::NotFound)?;
+    }
+    if !path.starts_with(&widget_root) || !path.is_file() {
+        return Err(Rejection::Forbidden);
+    }
+    Ok(path)
+}
+
+/// Validator that changes with the file's size and modification time. Each
+/// encoding of the file is a different representation and gets its own.
+pub fn etag_for(len: u64, modified: Option<SystemTime>, encoding: Option<Encoding>) -> String {
+    let nanos = modified
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos());
+    match encoding {
+        Some(encoding) => format!("\"{len:x}-{nanos:x}-{}\"", encoding.name()),
+        None => format!("\"{len:x}-{nanos:x}\""),
+    }
+}
+
+pub fn http_date(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+/// `If-None-Match` wins over `If-Modified-Since`, as RFC 9110 says.
+pub fn is_not_modified(
+    if_none_match: Option<&str>,
+    if_modified_since: Option<&str>,
+    etag: &str,
+    modified: Option<SystemTime>,
+) -> bool {
+    if let Some(if_none_match) = if_none_match {
+        return if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag);
+    }
+    let (Some(since), Some(modified)) = (if_modified_since, modified) else {
+        return false;
+    };
+    let Ok(since) = DateTime::parse_from_rfc2822(since.trim()) else {
+        return false;
+    };
+    // HTTP dates have whole seconds
+    DateTime::<Utc>::from(modified).timestamp() <= since.timestamp()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteRange {
+    /// First and last byte, inclusive
+    Satisfiable(u64, u64),
+    Unsatisfiable,
+    /// Not a single byte range, the whole file is sent
+    Ignored,
+}
+
+/// A single `bytes=start-end`, `bytes=start-` or `bytes=-suffix` range.
+pub fn parse_range(header: &str, len: u64) -> ByteRange {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return ByteRange::Ignored;
+    };
+    if spec.contains(',') {
+        return ByteRange::Ignored;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Ignored;
+    };
+    let (start, end) = (start.trim(), end.trim());
+    let parse = |s: &str| s.parse::<u64>().ok();
+    let range = match (start.is_empty(), end.is_empty()) {
+        (true, true) => return ByteRange::Ignored,
+        (true, false) => match parse(end) {
+            Some(0) => return ByteRange::Unsatisfiable,
+            Some(suffix) => Some((len.saturating_sub(suffix), len.saturating_sub(1))),
+            None => return ByteRange::Ignored,
+        },
+        (false, true) => parse(start).map(|start| (start, len.saturating_sub(1))),
+        (false, false) => match (parse(start), parse(end)) {
+            (Some(start), Some(end)) if start <= end => {
+                Some((start, end.min(len.saturating_sub(1))))
+            }
+            _ => return ByteRange::Ignored,
+        },
+    };
+    match range {
+        Some((start, end)) if start < len => ByteRange::Satisfiable(start, end),
+        Some(_) => ByteRange::Unsatisfiable,
+        None => ByteRange::Ignored,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+        }
+    }
+}
+
+/// Brotli when the client takes it, then gzip. `;q=0` refuses an encoding.
+pub fn preferred_encoding(accept_encoding: &str) -> Option<Encoding> {
+    let accepted: Vec<&str> = accept_encoding
+        .split(',')
+        .filter_map(|part| {
+            let mut params = part.split(';').map(str::trim);
+            let name = params.next()?;
+            let refused = params.any(|p| {
+                p.strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q <= 0.0)
+            });
+            (!refused && !name.is_empty()).then_some(name)
+        })
+        .collect();
+    [Encoding::Brotli, Encoding::Gzip]
+        .into_iter()
+        .find(|e| accepted.iter().any(|a| a.eq_ignore_ascii_case(e.name())))
+}
+
+/// Text formats, media and archives are compressed already.
+pub fn is_compressible(content_type: &str) -> bool {
+    content_type.starts_with("text/")
+        || matches!(
+            content_type,
+            "application/javascript"
+                | "application/json"
+                | "application/xml"
+                | "application/wasm"
+                | "image/svg+xml"
+        )
+}
+
+fn compress(file: &mut File, encoding: Encoding) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Brotli => {
+            let mut writer = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+            std::io::copy(file, &mut writer)?;
+            Ok(writer.into_inner())
+        }
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            std::io::copy(file, &mut encoder)?;
+            encoder.finish()
+        }
+    }
+}
 
 pub struct Request {
     #[allow(dead_code)]
@@ -41,6 +389,206 @@ pub struct Builder {
     on_request: OnRequest,
 }
 
+fn respond(req: tiny_http::Request, response: HttpResponse<impl std::io::Read>) {
+    if let Err(e) = req.respond(response) {
+        eprintln!("Error responding to localhost request: {}", e);
+    }
+}
+
+fn reject(req: tiny_http::Request, rejection: Rejection) {
+    let (code, text) = rejection.status();
+    respond(req, HttpResponse::from_string(text).with_status_code(code));
+}
+
+pub fn request_header<'a>(req: &'a tiny_http::Request, name: &str) -> Option<&'a str> {
+    req.headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
+
+/// Streams `path` from disk, answering conditional and range requests, and
+/// compressing text when the client accepts it.
+fn serve_file(
+    req: tiny_http::Request,
+    path: &Path,
+    extra_headers: HashMap<String, String>,
+) -> std::io::Result<()> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let len = metadata.len();
+    let modified = metadata.modified().ok();
+    let last_modified = modified.map(http_date);
+
+    // Ranges are served from the file as it is, and `If-Range` with an old
+    // validator asks for the whole, changed file instead
+    let identity_etag = etag_for(len, modified, None);
+    let range = request_header(&req, "Range")
+        .filter(|_| {
+            request_header(&req, "If-Range")
+                .is_none_or(|v| v == identity_etag || Some(v) == last_modified.as_deref())
+        })
+        .map(|r| parse_range(r, len))
+        // Several ranges are answered with the whole file
+        .filter(|range| *range != ByteRange::Ignored);
+    let content_type = extra_headers.get("Content-Type").map_or("", String::as_str);
+    let encoding = (range.is_none() && is_compressible(content_type) && len <= MAX_COMPRESSED_SIZE)
+        .then(|| preferred_encoding(request_header(&req, "Accept-Encoding").unwrap_or("")))
+        .flatten();
+    let etag = etag_for(len, modified, encoding);
+
+    let mut headers: Vec<Header> = extra_headers
+        .iter()
+        .filter_map(|(name, value)| Header::from_bytes(name.as_bytes(), value.as_bytes()).ok())
+        .collect();
+    let mut add = |name: &str, value: &str| {
+        if let Ok(header) = Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+            headers.push(header);
+        }
+    };
+    add("ETag", &etag);
+    if let Some(last_modified) = &last_modified {
+        add("Last-Modified", last_modified);
+    }
+    add("Cache-Control", "no-cache");
+    add("Accept-Ranges", "bytes");
+    add("Vary", "Accept-Encoding");
+
+    if is_not_modified(
+        request_header(&req, "If-None-Match"),
+        request_header(&req, "If-Modified-Since"),
+        &etag,
+        modified,
+    ) {
+        let response = HttpResponse::new(StatusCode(304), headers, std::io::empty(), Some(0), None);
+        return req.respond(response);
+    }
+
+    match range {
+        Some(ByteRange::Unsatisfiable) => {
+            add("Content-Range", &format!("bytes */{len}"));
+            let response =
+                HttpResponse::new(StatusCode(416), headers, std::io::empty(), Some(0), None);
+            return req.respond(response);
+        }
+        Some(ByteRange::Satisfiable(start, end)) => {
+            add("Content-Range", &format!("bytes {start}-{end}/{len}"));
+            file.seek(SeekFrom::Start(start))?;
+            let length = end - start + 1;
+            let response = HttpResponse::new(
+                StatusCode(206),
+                headers,
+                file.take(length),
+                Some(length as usize),
+                None,
+            );
+            return req.respond(response);
+        }
+        Some(ByteRange::Ignored) | None => {}
+    }
+
+    if let Some(encoding) = encoding {
+        let body = compress(&mut file, encoding)?;
+        add("Content-Encoding", encoding.name());
+        let length = body.len();
+        let response = HttpResponse::new(
+            StatusCode(200),
+            headers,
+            std::io::Cursor::new(body),
+            Some(length),
+            None,
+        );
+        return req.respond(response);
+    }
+    let response = HttpResponse::new(StatusCode(200), headers, file, Some(len as usize), None);
+    req.respond(response)
+}
+
+/// Pages of widgets in development are never cached, and reload themselves
+/// when their folder changes.
+fn serve_dev_page(
+    req: tiny_http::Request,
+    path: &Path,
+    key: &str,
+    extra_headers: HashMap<String, String>,
+) -> std::io::Result<()> {
+    let html = std::fs::read_to_string(path)?;
+    let html = live_reload::inject_client(&html, &widget_path(key, live_reload::ENDPOINT));
+    let mut response = HttpResponse::from_data(html.into_bytes());
+    let headers = extra_headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .chain([("Cache-Control", "no-store")]);
+    for (name, value) in headers {
+        if let Ok(header) = Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+            response.add_header(header);
+        }
+    }
+    req.respond(response)
+}
+
+fn handle(req: tiny_http::Request, root: &Path, on_request: &OnRequest) {
+    let path: String = req
+        .url()
+        .parse::<Uri>()
+        .map(|uri| uri.path().into())
+        .unwrap_or_else(|_| req.url().into());
+    if path.starts_with(api::PREFIX) {
+        return api::handle(req);
+    }
+    if path == proxy::PREFIX {
+        return proxy::handle(req);
+    }
+    let (key, segments) = match parse_path(&path) {
+        Ok(parsed) => parsed,
+        Err(rejection) => {
+            eprintln!("Refused localhost request for {}: {:?}", path, rejection);
+            return reject(req, rejection);
+        }
+    };
+    if !matches!(req.method(), Method::Get | Method::Head) {
+        return respond(
+            req,
+            HttpResponse::from_string("405 Method Not Allowed").with_status_code(405),
+        );
+    }
+    let host = request_header(&req, "Host").map(str::to_string);
+    if !is_authorized(session_secret(), host.as_deref(), &key) {
+        eprintln!("Refused localhost request for {} from {:?}", path, host);
+        return reject(req, Rejection::Forbidden);
+    }
+    let dev_folder = DEV_FOLDERS.lock().unwrap().get(&key).cloned();
+    let widget_root = dev_folder.clone().unwrap_or_else(|| root.join(&key));
+    if segments == [live_reload::ENDPOINT] {
+        return live_reload::serve(req, widget_root);
+    }
+    let asset_path = match resolve_path(&widget_root, &segments) {
+        Ok(asset_path) => asset_path,
+        Err(rejection) => return reject(req, rejection),
+    };
+
+    let request = Request {
+        url: req.url().into(),
+    };
+    let mut response = Response {
+        headers: Default::default(),
+    };
+    let mime_type = mime_guess::from_path(&asset_path).first_or_octet_stream();
+    response.add_header("Content-Type", mime_type.essence_str());
+    response.add_header("X-Content-Type-Options", "nosniff");
+    if let Some(on_request) = on_request {
+        on_request(&request, &mut response);
+    }
+    let result = if dev_folder.is_some() && mime_type == mime_guess::mime::TEXT_HTML {
+        serve_dev_page(req, &asset_path, &key, response.headers)
+    } else {
+        serve_file(req, &asset_path, response.headers)
+    };
+    if let Err(e) = result {
+        eprintln!("Error serving {}: {}", asset_path.display(), e);
+    }
+}
+
 impl Builder {
     pub fn new(port: u16) -> Self {
         Self {
@@ -77,74 +625,377 @@ impl Builder {
                     .path()
                     .resolve("files", tauri::path::BaseDirectory::AppCache)
                     .unwrap();
-                println!("{:?}", default_asset_path.display());
-
-                std::thread::spawn(move || {
-                    let server =
-                        Server::http(format!("{host}:{port}")).expect("Unable to spawn server");
-                    println!("Localhost server running at http://{host}:{port}");
-                    for req in server.incoming_requests() {
-                        let mut asset_path = default_asset_path.clone();
-                        let path: String = req
-                            .url()
-                            .parse::<Uri>()
-                            .map(|uri| uri.path().into())
-                            .unwrap_or_else(|_| req.url().into());
-                        println!("Received request for: {}", path);
-                        let paths = path.split('/');
-                        for p in paths {
-                            asset_path = asset_path.join(p);
-                        }
 
-                        if let Ok(exists) = fs::exists(asset_path.clone()) {
-                            if !exists {
-                                println!("File not found: {:?}", asset_path.display());
-                                req.respond(
-                                    HttpResponse::from_string("404 Not Found")
-                                        .with_status_code(404),
-                                )
-                                .expect("unable to respond");
-                            } else {
-                                if let Some(data) = fs::read(asset_path.clone()).ok() {
-                                    let mime_type =
-                                        mime_guess::from_path::<&Path>(asset_path.as_ref())
-                                            .first_or_octet_stream();
-
-                                    let request = Request {
-                                        url: req.url().into(),
-                                    };
-                                    let mut response = Response {
-                                        headers: Default::default(),
-                                    };
-
-                                    response.add_header("Content-Type", mime_type.essence_str());
-
-                                    if let Some(on_request) = &on_request {
-                                        on_request(&request, &mut response);
-                                    }
-
-                                    let mut resp = HttpResponse::from_data(data);
-                                    for (header, value) in response.headers {
-                                        if let Ok(h) = Header::from_bytes(header.as_bytes(), value)
-                                        {
-                                            resp.add_header(h);
-                                        }
-                                    }
-                                    req.respond(resp).expect("unable to setup response");
-                                } else {
-                                    println!("Error reading file: {:?}", asset_path.display());
-                                    req.respond(
-                                        HttpResponse::from_string("500 Internal Server Error")
-                                            .with_status_code(500),
-                                    )
-                                    .expect("unable to respond");
-                                };
-                            }
+                let server = Arc::new(
+                    Server::http(format!("{host}:{port}")).expect("Unable to spawn server"),
+                );
+                println!("Localhost server running at http://{host}:{port}");
+                let on_request = Arc::new(on_request);
+                for _ in 0..WORKERS {
+                    let server = Arc::clone(&server);
+                    let root = default_asset_path.clone();
+                    let on_request = Arc::clone(&on_request);
+                    std::thread::spawn(move || {
+                        for req in server.incoming_requests() {
+                            handle(req, &root, &on_request);
                         }
-                    }
-                });
+                    });
+                }
                 Ok(())
             })
             .build()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeSet, fs, time::Duration};
+
+    fn file(path: &str) -> Result<(String, Vec<String>), Rejection> {
+        parse_path(path)
+    }
+
+    fn segments(key: &str, files: &[&str]) -> Result<(String, Vec<String>), Rejection> {
+        Ok((
+            key.to_string(),
+            files.iter().map(|f| f.to_string()).collect(),
+        ))
+    }
+
+    /// A widget folder with `index.html` and `sub/page.html`, next to a file
+    /// it must not reach.
+    struct Fixture {
+        dir: PathBuf,
+        root: PathBuf,
+        secret: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "delta-widgets-localhost-{}-{name}",
+                std::process::id()
+            ));
+            let root = dir.join("widget");
+            fs::create_dir_all(root.join("sub")).unwrap();
+            fs::write(root.join("index.html"), "index").unwrap();
+            fs::write(root.join("sub").join("page.html"), "page").unwrap();
+            let secret = dir.join("secret.txt");
+            fs::write(&secret, "secret").unwrap();
+            Self { dir, root, secret }
+        }
+
+        fn resolve(&self, segments: &[&str]) -> Result<PathBuf, Rejection> {
+            let segments: Vec<String> = segments.iter().map(|s| s.to_string()).collect();
+            resolve_path(&self.root, &segments)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn plain_paths() {
+        assert_eq!(file("/key/"), segments("key", &["index.html"]));
+        assert_eq!(file("/key"), segments("key", &["index.html"]));
+        assert_eq!(file("/key/a//b.js"), segments("key", &["a", "b.js"]));
+        assert_eq!(file("/key/my%20file.js"), segments("key", &["my file.js"]));
+        assert_eq!(file("/"), Err(Rejection::NotFound));
+        assert_eq!(file("key/index.html"), Err(Rejection::BadRequest));
+        assert_eq!(file("/key/%zz"), Err(Rejection::BadRequest));
+        assert_eq!(file("/key/%e2%28"), Err(Rejection::BadRequest));
+    }
+
+    #[test]
+    fn parent_segments() {
+        for path in [
+            "/key/../other/index.html",
+            "/../key/index.html",
+            "/key/%2e%2e/secret.txt",
+            "/key/%2E%2E/secret.txt",
+            "/key/.%2e/secret.txt",
+            "/key/./index.html",
+            "/key/%2e/index.html",
+        ] {
+            assert_eq!(file(path), Err(Rejection::Forbidden), "{path}");
+        }
+    }
+
+    #[test]
+    fn encoded_separators() {
+        for path in [
+            "/key/..%2fsecret.txt",
+            "/key/sub%2f..%2f..%2fsecret.txt",
+            "/key/..%5csecret.txt",
+            "/key/sub%5C..%5C..%5Csecret.txt",
+            "/key/%00index.html",
+        ] {
+            assert_eq!(file(path), Err(Rejection::Forbidden), "{path}");
+        }
+    }
+
+    #[test]
+    fn absolute_paths_and_drive_letters() {
+        for path in [
+            "/key/%2fetc%2fpasswd",
+            "/key/%5cWindows%5cwin.ini",
+            "/key/C:",
+            "/key/C%3a%5cWindows",
+            "/key/c%3A",
+            "/key/file.txt:stream",
+            "/C%3a/index.html",
+        ] {
+            assert_eq!(file(path), Err(Rejection::Forbidden), "{path}");
+        }
+    }
+
+    #[test]
+    fn windows_names() {
+        for path in [
+            "/key/CON",
+            "/key/nul",
+            "/key/nul.txt",
+            "/key/Com1.js",
+            "/key/lpt9.tar.gz",
+            "/key/aux%20.html",
+            "/key/index.html.",
+            "/key/index.html%20",
+            "/key/..%20",
+            "/key/sub./page.html",
+        ] {
+            assert_eq!(file(path), Err(Rejection::Forbidden), "{path}");
+        }
+        // Only the whole name is reserved
+        assert_eq!(file("/key/console.js"), segments("key", &["console.js"]));
+        assert_eq!(file("/key/com10.js"), segments("key", &["com10.js"]));
+    }
+
+    #[test]
+    fn resolves_inside_the_root() {
+        let fixture = Fixture::new("inside");
+        let root = fixture.root.canonicalize().unwrap();
+        assert_eq!(
+            fixture.resolve(&["index.html"]),
+            Ok(root.join("index.html"))
+        );
+        assert_eq!(
+            fixture.resolve(&["sub", "page.html"]),
+            Ok(root.join("sub").join("page.html"))
+        );
+        // A folder without an index
+        assert_eq!(fixture.resolve(&["sub"]), Err(Rejection::NotFound));
+        assert_eq!(fixture.resolve(&["missing.js"]), Err(Rejection::NotFound));
+    }
+
+    #[test]
+    fn refuses_paths_out_of_the_root() {
+        let fixture = Fixture::new("outside");
+        assert_eq!(
+            fixture.resolve(&["..", "secret.txt"]),
+            Err(Rejection::Forbidden)
+        );
+        assert_eq!(
+            fixture.resolve(&["sub", "..", "..", "secret.txt"]),
+            Err(Rejection::Forbidden)
+        );
+        // An absolute segment replaces the root when joined
+        let secret = fixture.secret.to_string_lossy().to_string();
+        assert_eq!(fixture.resolve(&[&secret]), Err(Rejection::Forbidden));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_symlinks_out_of_the_root() {
+        use std::os::unix::fs::symlink;
+
+        let fixture = Fixture::new("symlinks");
+        symlink(&fixture.secret, fixture.root.join("link.txt")).unwrap();
+        symlink(&fixture.dir, fixture.root.join("up")).unwrap();
+        symlink(
+            fixture.root.join("sub").join("page.html"),
+            fixture.root.join("inside.html"),
+        )
+        .unwrap();
+
+        assert_eq!(fixture.resolve(&["link.txt"]), Err(Rejection::Forbidden));
+        assert_eq!(
+            fixture.resolve(&["up", "secret.txt"]),
+            Err(Rejection::Forbidden)
+        );
+        // Links that stay inside are fine
+        assert!(fixture.resolve(&["inside.html"]).is_ok());
+    }
+
+    #[test]
+    fn host_token() {
+        let secret = [7u8; 32];
+        let host = format!("{}.localhost", token_for(&secret, "key"));
+
+        assert!(is_authorized(&secret, Some(&host), "key"));
+        assert!(is_authorized(&secret, Some(&format!("{host}:1420")), "key"));
+        assert!(is_authorized(
+            &secret,
+            Some(&host.to_ascii_uppercase()),
+            "key"
+        ));
+
+        assert!(!is_authorized(&secret, None, "key"));
+        assert!(!is_authorized(&secret, Some(""), "key"));
+        assert!(!is_authorized(&secret, Some("localhost:1420"), "key"));
+        // The origin of another widget, or of another session
+        assert!(!is_authorized(&secret, Some(&host), "other"));
+        assert!(!is_authorized(&[8u8; 32], Some(&host), "key"));
+        assert!(!is_authorized(
+            &secret,
+            Some(&format!("{}.localhost", token_for(&secret, "other"))),
+            "key"
+        ));
+        assert!(!is_authorized(
+            &secret,
+            Some(&format!("{host}.evil.com")),
+            "key"
+        ));
+        assert!(!is_authorized(
+            &secret,
+            Some(&format!("evil.{host}")),
+            "key"
+        ));
+    }
+
+    #[test]
+    fn ranges() {
+        use ByteRange::*;
+        assert_eq!(parse_range("bytes=0-99", 1000), Satisfiable(0, 99));
+        assert_eq!(parse_range(" bytes= 10 - 19 ", 1000), Satisfiable(10, 19));
+        // Open-ended and past the end stop at the last byte
+        assert_eq!(parse_range("bytes=500-", 1000), Satisfiable(500, 999));
+        assert_eq!(parse_range("bytes=900-5000", 1000), Satisfiable(900, 999));
+        // Suffixes count from the end, longer ones are the whole file
+        assert_eq!(parse_range("bytes=-100", 1000), Satisfiable(900, 999));
+        assert_eq!(parse_range("bytes=-2000", 1000), Satisfiable(0, 999));
+
+        assert_eq!(parse_range("bytes=1000-", 1000), Unsatisfiable);
+        assert_eq!(parse_range("bytes=1000-1100", 1000), Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), Unsatisfiable);
+        assert_eq!(parse_range("bytes=-5", 0), Unsatisfiable);
+
+        for header in [
+            "bytes=0-0,5-9",
+            "bytes=0-1, -1",
+            "items=0-1",
+            "bytes=5-1",
+            "bytes=-",
+            "bytes=5",
+            "bytes=a-b",
+            "bytes=-x",
+        ] {
+            assert_eq!(parse_range(header, 1000), Ignored, "{}", header);
+        }
+    }
+
+    #[test]
+    fn not_modified() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_000_000_000_500);
+        let modified = Some(time);
+        let etag = etag_for(10, modified, None);
+        let date = http_date(time);
+        let earlier = http_date(UNIX_EPOCH + Duration::from_secs(999_999_999));
+
+        assert!(is_not_modified(Some(&etag), None, &etag, modified));
+        assert!(is_not_modified(Some("*"), None, &etag, modified));
+        assert!(is_not_modified(
+            Some(&format!("W/{etag}")),
+            None,
+            &etag,
+            modified
+        ));
+        assert!(is_not_modified(
+            Some(&format!("\"other\", {etag}")),
+            None,
+            &etag,
+            modified
+        ));
+        assert!(!is_not_modified(Some("\"other\""), None, &etag, modified));
+        assert!(!is_not_modified(None, None, &etag, modified));
+
+        // Whole seconds, so the same second counts as unchanged
+        assert!(is_not_modified(None, Some(&date), &etag, modified));
+        assert!(!is_not_modified(None, Some(&earlier), &etag, modified));
+        assert!(!is_not_modified(None, Some("yesterday"), &etag, modified));
+        assert!(!is_not_modified(None, Some(&date), &etag, None));
+
+        // If-None-Match wins over If-Modified-Since
+        assert!(!is_not_modified(
+            Some("\"other\""),
+            Some(&date),
+            &etag,
+            modified
+        ));
+        assert!(is_not_modified(
+            Some(&etag),
+            Some(&earlier),
+            &etag,
+            modified
+        ));
+    }
+
+    #[test]
+    fn etags_differ_per_encoding() {
+        let modified = Some(UNIX_EPOCH + Duration::from_secs(1_000));
+        let etags = [None, Some(Encoding::Brotli), Some(Encoding::Gzip)]
+            .map(|encoding| etag_for(10, modified, encoding));
+        assert_eq!(etags.iter().collect::<BTreeSet<_>>().len(), 3);
+        assert_eq!(etags[0], etag_for(10, modified, None));
+        assert_ne!(etags[0], etag_for(11, modified, None));
+        assert_ne!(etags[0], etag_for(10, None, None));
+        assert!(etags
+            .iter()
+            .all(|etag| etag.starts_with('"') && etag.ends_with('"')));
+    }
+
+    #[test]
+    fn encodings() {
+        assert_eq!(
+            preferred_encoding("gzip, deflate, br"),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(preferred_encoding("GZIP"), Some(Encoding::Gzip));
+        assert_eq!(
+            preferred_encoding("br;q=0, gzip;q=0.5"),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(preferred_encoding("br; q=0.0, gzip"), Some(Encoding::Gzip));
+        assert_eq!(preferred_encoding("br;q=0, gzip;q=0"), None);
+        assert_eq!(preferred_encoding("identity, deflate"), None);
+        assert_eq!(preferred_encoding(""), None);
+    }
+
+    #[test]
+    fn compressible_types() {
+        for content_type in [
+            "text/html",
+            "text/css",
+            "application/javascript",
+            "application/json",
+            "application/wasm",
+            "image/svg+xml",
+        ] {
+            assert!(is_compressible(content_type), "{}", content_type);
+        }
+        for content_type in [
+            "image/png",
+            "image/jpeg",
+            "video/mp4",
+            "font/woff2",
+            "application/zip",
+            "application/octet-stream",
+        ] {
+            assert!(!is_compressible(content_type), "{}", content_type);
+        }
+    }
+}
@@ -1 +1,5 @@
+pub mod api;
+pub mod live_reload;
 pub mod localhost;
+pub mod proxy;
+pub mod sse;
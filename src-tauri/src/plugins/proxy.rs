@@ -0,0 +1,252 @@
+//! `/proxy?url=<url>` on the localhost server, so HTML widgets can call APIs
+//! that do not allow their origin. Only hosts in the widget's `proxyHosts`
+//! are reached, and cookies are never passed either way.
+
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    redirect, Client, Url,
+};
+use serde_json::json;
+use std::{io::Read, time::Duration};
+use tiny_http::{Header, Response as HttpResponse};
+
+use super::{api, localhost};
+use crate::providers::ProviderContext;
+
+pub const PREFIX: &str = "/proxy";
+const TIMEOUT: Duration = Duration::from_secs(20);
+const MAX_REQUEST_BODY: u64 = 1024 * 1024;
+const MAX_RESPONSE_BODY: usize = 10 * 1024 * 1024;
+const MAX_REDIRECTS: usize = 5;
+
+/// Headers that describe the connection to the proxy rather than the
+/// request, or that would carry the widget's cookies and origin along
+const DROPPED_REQUEST_HEADERS: [&str; 12] = [
+    "host",
+    "cookie",
+    "origin",
+    "referer",
+    "connection",
+    "keep-alive",
+    "transfer-encoding",
+    "te",
+    "upgrade",
+    "content-length",
+    "accept-encoding",
+    "proxy-authorization",
+];
+const DROPPED_RESPONSE_HEADERS: [&str; 10] = [
+    "set-cookie",
+    "set-cookie2",
+    "connection",
+    "keep-alive",
+    "transfer-encoding",
+    "content-length",
+    "upgrade",
+    "strict-transport-security",
+    "alt-svc",
+    "proxy-authenticate",
+];
+
+/// `pattern` is a host, or `*.<domain>` for any subdomain of it.
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+        None => host == pattern,
+    }
+}
+
+fn is_allowed(allowed_hosts: &[String], url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https")
+        && url
+            .host_str()
+            .is_some_and(|host| allowed_hosts.iter().any(|p| host_matches(p, host)))
+}
+
+fn respond_error(req: tiny_http::Request, status: u16, message: String) {
+    let response = HttpResponse::from_data(json!({ "error": message }).to_string().into_bytes())
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").expect("valid header"));
+    if let Err(e) = req.respond(response) {
+        eprintln!("Error responding to proxy request: {}", e);
+    }
+}
+
+struct Proxied {
+    status: u16,
+    headers: Vec<Header>,
+    body: Vec<u8>,
+}
+
+async fn forward(
+    allowed_hosts: Vec<String>,
+    method: reqwest::Method,
+    url: Url,
+    headers: HeaderMap,
+    body: Vec<u8>,
+) -> Result<Proxied, (u16, String)> {
+    // Redirects are followed only to hosts the widget may reach anyway
+    let policy_hosts = allowed_hosts.clone();
+    let client = Client::builder()
+        .timeout(TIMEOUT)
+        .redirect(redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if is_allowed(&policy_hosts, attempt.url()) {
+                attempt.follow()
+            } else {
+                let message = format!("redirect to {} is not in proxyHosts", attempt.url());
+                attempt.error(message)
+            }
+        }))
+        .build()
+        .map_err(|e| (500, e.to_string()))?;
+    let mut request = client.request(method, url).headers(headers);
+    if !body.is_empty() {
+        request = request.body(body);
+    }
+    let mut response = request.send().await.map_err(|e| {
+        if e.is_timeout() {
+            (504, format!("Timed out after {}s", TIMEOUT.as_secs()))
+        } else {
+            (502, format!("Request failed: {}", e))
+        }
+    })?;
+
+    let headers = response
+        .headers()
+        .iter()
+        .filter(|(name, _)| !DROPPED_RESPONSE_HEADERS.contains(&name.as_str()))
+        .filter_map(|(name, value)| Header::from_bytes(name.as_str(), value.as_bytes()).ok())
+        .collect();
+    let status = response.status().as_u16();
+    let mut body = vec![];
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| (502, format!("Reading the response failed: {}", e)))?
+    {
+        body.extend_from_slice(&chunk);
+        if body.len() > MAX_RESPONSE_BODY {
+            return Err((
+                502,
+                format!(
+                    "Response is larger than {} MB",
+                    MAX_RESPONSE_BODY / 1024 / 1024
+                ),
+            ));
+        }
+    }
+    Ok(Proxied {
+        status,
+        headers,
+        body,
+    })
+}
+
+pub fn handle(mut req: tiny_http::Request) {
+    let Some(app) = api::app() else {
+        return respond_error(req, 503, "Starting up".to_string());
+    };
+    // Only pages on a widget's own origin may use its allowlist
+    let Some(widget_key) = localhost::origin_widget(localhost::request_header(&req, "Host")) else {
+        eprintln!("Refused proxy request from an unknown origin");
+        return respond_error(req, 403, "Only HTML widgets can use the proxy".to_string());
+    };
+    let target = reqwest::Url::parse(&format!("http://localhost{}", req.url()))
+        .ok()
+        .and_then(|url| {
+            url.query_pairs()
+                .find(|(name, _)| name == "url")
+                .map(|(_, value)| value.into_owned())
+        });
+    let Some(url) = target.and_then(|target| Url::parse(&target).ok()) else {
+        return respond_error(req, 400, "Expected ?url=<absolute URL>".to_string());
+    };
+
+    let allowed_hosts = ProviderContext::for_widget(app, Some(&widget_key))
+        .manifest
+        .and_then(|manifest| manifest.proxy_hosts)
+        .unwrap_or_default();
+    if !is_allowed(&allowed_hosts, &url) {
+        let message = format!(
+            "{} is not in proxyHosts of widget {}",
+            url.host_str().unwrap_or(url.as_str()),
+            widget_key
+        );
+        eprintln!("Refused proxy request: {}", message);
+        return respond_error(req, 403, message);
+    }
+
+    let Ok(method) = reqwest::Method::from_bytes(req.method().as_str().as_bytes()) else {
+        return respond_error(req, 405, "Method not allowed".to_string());
+    };
+    let mut headers = HeaderMap::new();
+    for header in req.headers() {
+        let name = header.field.as_str().as_str().to_ascii_lowercase();
+        if DROPPED_REQUEST_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_bytes(header.value.as_bytes()),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    // Reading the body and waiting on the target can take up to TIMEOUT, the
+    // localhost server's workers are left free for other requests meanwhile
+    std::thread::spawn(move || {
+        let mut body = vec![];
+        if let Err(e) = req
+            .as_reader()
+            .take(MAX_REQUEST_BODY + 1)
+            .read_to_end(&mut body)
+        {
+            return respond_error(req, 400, e.to_string());
+        }
+        if body.len() as u64 > MAX_REQUEST_BODY {
+            return respond_error(
+                req,
+                413,
+                format!(
+                    "Request body is larger than {} MB",
+                    MAX_REQUEST_BODY / 1024 / 1024
+                ),
+            );
+        }
+
+        let result = tauri::async_runtime::block_on(forward(
+            allowed_hosts,
+            method,
+            url.clone(),
+            headers,
+            body,
+        ));
+        match result {
+            Ok(proxied) => {
+                let length = proxied.body.len();
+                let response = HttpResponse::new(
+                    tiny_http::StatusCode(proxied.status),
+                    proxied.headers,
+                    std::io::Cursor::new(proxied.body),
+                    Some(length),
+                    None,
+                );
+                if let Err(e) = req.respond(response) {
+                    eprintln!("Error responding to proxy request: {}", e);
+                }
+            }
+            Err((status, message)) => {
+                eprintln!(
+                    "Proxy request of {} to {} failed: {}",
+                    widget_key, url, message
+                );
+                respond_error(req, status, message);
+            }
+        }
+    });
+}
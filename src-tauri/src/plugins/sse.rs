@@ -0,0 +1,73 @@
+//! Server-Sent Events over a `tiny_http` request, for streams that stay open
+//! longer than a worker should be busy.
+
+use std::{
+    io::Write,
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+/// Comments sent while nothing happens, to find clients that went away
+const HEARTBEAT: Duration = Duration::from_secs(15);
+
+pub struct Event {
+    pub name: String,
+    pub data: String,
+}
+
+impl Event {
+    pub fn new(name: impl Into<String>, data: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            data: data.into(),
+        }
+    }
+}
+
+fn encode(event: &Event) -> String {
+    let mut text = format!("event: {}\n", event.name);
+    for line in event.data.split('\n') {
+        text.push_str("data: ");
+        text.push_str(line);
+        text.push('\n');
+    }
+    text.push('\n');
+    text
+}
+
+/// Answers `req` with the events sent to `events`, on a thread of its own.
+/// `on_close` runs once the client disconnects or every sender is dropped.
+pub fn stream(
+    req: tiny_http::Request,
+    headers: Vec<(String, String)>,
+    events: Receiver<Event>,
+    on_close: impl FnOnce() + Send + 'static,
+) {
+    std::thread::spawn(move || {
+        let mut writer = req.into_writer();
+        let mut send = || -> std::io::Result<()> {
+            let mut head = "HTTP/1.1 200 OK\r\n\
+                Content-Type: text/event-stream\r\n\
+                Cache-Control: no-store\r\n\
+                Connection: close\r\n"
+                .to_string();
+            for (name, value) in &headers {
+                head.push_str(&format!("{name}: {value}\r\n"));
+            }
+            head.push_str("\r\nretry: 1000\n\n");
+            writer.write_all(head.as_bytes())?;
+            writer.flush()?;
+            loop {
+                match events.recv_timeout(HEARTBEAT) {
+                    Ok(event) => writer.write_all(encode(&event).as_bytes())?,
+                    Err(RecvTimeoutError::Timeout) => writer.write_all(b": ping\n\n")?,
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+                writer.flush()?;
+            }
+        };
+        // Fails once the client is gone
+        let _ = send();
+        on_close();
+    });
+}
@@ -0,0 +1,51 @@
+use tauri::{AppHandle, Manager};
+
+use super::{snapshot, BoxFuture, DataProvider, ProviderContext, Refresh, Snapshot};
+use crate::commands::audio::{current_level, get_current_device, start_capture, stop_capture};
+
+/// The output device and its loudness. Subscribed windows also get the raw
+/// `audio-samples` events from the capture stream.
+pub struct AudioProvider;
+
+impl DataProvider for AudioProvider {
+    fn namespace(&self) -> &'static str {
+        "audio"
+    }
+
+    fn keys(&self) -> Vec<String> {
+        vec!["device".to_string(), "level".to_string()]
+    }
+
+    fn refresh(&self) -> Refresh {
+        Refresh::Polling(std::time::Duration::from_millis(500))
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        _app: &'a AppHandle,
+        _context: &'a ProviderContext,
+    ) -> BoxFuture<'a, anyhow::Result<Snapshot>> {
+        Box::pin(async move {
+            let device = get_current_device().map_err(anyhow::Error::msg)?;
+            Ok(snapshot([
+                ("device", device.into()),
+                ("level", (current_level() * 100.0).round().into()),
+            ]))
+        })
+    }
+
+    fn start<'a>(&'a self, app: &'a AppHandle) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            start_capture(app.clone(), &app.state());
+            Ok(())
+        })
+    }
+
+    fn stop<'a>(&'a self, app: &'a AppHandle) -> BoxFuture<'a, ()> {
+        Box::pin(async move { stop_capture(&app.state()) })
+    }
+
+    fn emits_events(&self) -> bool {
+        true
+    }
+}
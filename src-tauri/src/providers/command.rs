@@ -0,0 +1,302 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    process::Stdio,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tauri::{AppHandle, Emitter};
+
+use super::{
+    flatten_json, BoxFuture, DataProvider, ProviderContext, Refresh, Snapshot, VariableValue,
+};
+use crate::{
+    commands::shell::{command_fingerprint, is_approved, resolve_program},
+    manifest::{CommandOutput, ShellCommand},
+};
+
+const DEFAULT_INTERVAL: u64 = 60;
+const DEFAULT_TIMEOUT: u64 = 10;
+/// Output past this is cut off before parsing
+const MAX_OUTPUT: usize = 64 * 1024;
+/// Variables the child process keeps, everything else is cleared
+const INHERITED_ENV: [&str; 7] = [
+    "PATH",
+    "PATHEXT",
+    "SystemRoot",
+    "windir",
+    "TEMP",
+    "TMP",
+    "LANG",
+];
+
+type Values = Vec<(String, VariableValue)>;
+
+/// Sent to the main window, which asks the user to approve the command.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ApprovalRequest<'a> {
+    widget_key: &'a str,
+    id: &'a str,
+    command: &'a str,
+    args: &'a [String],
+}
+
+struct LastRun {
+    at: Instant,
+    fingerprint: String,
+    values: Values,
+}
+
+/// Runs the approved `shellCommands` of a widget on their own intervals. Each
+/// run starts in the widget's folder with a cleared environment. That keeps
+/// scripts from depending on where the app was launched, it is not an OS
+/// level sandbox, which is why every command needs the user's approval.
+#[derive(Default)]
+pub struct CommandProvider {
+    /// By widget key and command id
+    runs: Mutex<HashMap<(String, String), LastRun>>,
+    running: Mutex<HashSet<(String, String)>>,
+    /// Widget key, command id and fingerprint of the approvals asked for, so
+    /// the user is asked once per session
+    requested: Mutex<HashSet<(String, String, String)>>,
+}
+
+fn status(ok: bool, error: &str) -> Values {
+    vec![
+        ("ok".to_string(), ok.into()),
+        ("error".to_string(), error.into()),
+    ]
+}
+
+/// Fields parsed from stdout. `output` is always the whole trimmed text.
+fn parse_output(format: CommandOutput, stdout: &str) -> Result<Values, String> {
+    let text = stdout.trim();
+    let mut values: Values = vec![("output".to_string(), text.into())];
+    match format {
+        CommandOutput::Text => {}
+        CommandOutput::Json => {
+            let json: Value =
+                serde_json::from_str(text).map_err(|e| format!("Invalid JSON output: {}", e))?;
+            match json {
+                Value::Object(_) | Value::Array(_) => flatten_json("", &json, &mut values),
+                scalar => flatten_json("value", &scalar, &mut values),
+            }
+        }
+        CommandOutput::KeyValue => {
+            for line in text.lines().map(str::trim) {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let Some((key, value)) = line.split_once('=') else {
+                    return Err(format!("Expected key=value, got `{}`", line));
+                };
+                values.push((key.trim().to_string(), value.trim().into()));
+            }
+        }
+    }
+    Ok(values)
+}
+
+fn truncated(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(&bytes[..bytes.len().min(MAX_OUTPUT)]).into_owned()
+}
+
+async fn run(dir: &Path, widget_key: &str, command: &ShellCommand) -> Values {
+    let program = match resolve_program(dir, &command.command) {
+        Ok(program) => program,
+        Err(e) => return status(false, &e),
+    };
+    let mut process = tokio::process::Command::new(program);
+    process
+        .args(&command.args)
+        .current_dir(dir)
+        .env_clear()
+        .envs(
+            INHERITED_ENV
+                .iter()
+                .filter_map(|k| Some((k, std::env::var_os(k)?))),
+        )
+        .env("WIDGET_DIR", dir)
+        .env("WIDGET_KEY", widget_key)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(windows)]
+    process.creation_flags(0x08000000); // CREATE_NO_WINDOW
+
+    let child = match process.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            return status(
+                false,
+                &format!("Failed to start {}: {}", command.command, e),
+            )
+        }
+    };
+    let timeout = command.timeout.unwrap_or(DEFAULT_TIMEOUT);
+    let output =
+        match tokio::time::timeout(Duration::from_secs(timeout), child.wait_with_output()).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => return status(false, &format!("Failed to run: {}", e)),
+            // Dropping the future kills the child
+            Err(_) => return status(false, &format!("Timed out after {}s", timeout)),
+        };
+
+    let stderr = truncated(&output.stderr).trim().to_string();
+    let mut error = match output.status.code() {
+        Some(0) => String::new(),
+        Some(code) => format!("Exited with code {}", code),
+        None => "Killed by a signal".to_string(),
+    };
+    let mut values = match parse_output(command.output, &truncated(&output.stdout)) {
+        Ok(values) => values,
+        Err(e) => {
+            if error.is_empty() {
+                error = e;
+            }
+            vec![(
+                "output".to_string(),
+                truncated(&output.stdout).trim().into(),
+            )]
+        }
+    };
+    values.push((
+        "exit_code".to_string(),
+        output
+            .status
+            .code()
+            .map_or(VariableValue::Text(String::new()), |c| (c as f64).into()),
+    ));
+    values.push(("stderr".to_string(), stderr.into()));
+    values.extend(status(error.is_empty(), &error));
+    values
+}
+
+impl CommandProvider {
+    fn request_approval(
+        &self,
+        app: &AppHandle,
+        dir: &Path,
+        widget_key: &str,
+        command: &ShellCommand,
+    ) {
+        let key = (
+            widget_key.to_string(),
+            command.id.clone(),
+            command_fingerprint(dir, command),
+        );
+        if !self.requested.lock().unwrap().insert(key) {
+            return;
+        }
+        let request = ApprovalRequest {
+            widget_key,
+            id: &command.id,
+            command: &command.command,
+            args: &command.args,
+        };
+        if let Err(e) = app.emit_to("main", "shell-command-approval", request) {
+            eprintln!("Error requesting command approval: {}", e);
+        }
+    }
+
+    /// Last values of `command`, running it first when its interval is up.
+    /// Runs are kept per `instance`, so a preview does not rerun the
+    /// published widget's.
+    async fn values(
+        &self,
+        dir: &Path,
+        instance: &str,
+        widget_key: &str,
+        command: &ShellCommand,
+    ) -> Values {
+        let key = (instance.to_string(), command.id.clone());
+        let fingerprint = command_fingerprint(dir, command);
+        let interval = Duration::from_secs(command.interval.unwrap_or(DEFAULT_INTERVAL));
+        let previous = self
+            .runs
+            .lock()
+            .unwrap()
+            .get(&key)
+            .filter(|run| run.fingerprint == fingerprint)
+            .map(|run| (run.at.elapsed() < interval, run.values.clone()));
+
+        match previous {
+            Some((true, values)) => return values,
+            _ if !self.running.lock().unwrap().insert(key.clone()) => {
+                return previous
+                    .map(|(_, values)| values)
+                    .unwrap_or_else(|| status(false, "Running"));
+            }
+            _ => {}
+        }
+
+        let values = run(dir, widget_key, command).await;
+        self.running.lock().unwrap().remove(&key);
+        self.runs.lock().unwrap().insert(
+            key,
+            LastRun {
+                at: Instant::now(),
+                fingerprint,
+                values: values.clone(),
+            },
+        );
+        values
+    }
+}
+
+impl DataProvider for CommandProvider {
+    fn namespace(&self) -> &'static str {
+        "cmd"
+    }
+
+    /// Fields of every command, `<id>` is the command's id.
+    fn keys(&self) -> Vec<String> {
+        ["output", "exit_code", "stderr", "ok", "error"]
+            .map(|field| format!("<id>.{field}"))
+            .to_vec()
+    }
+
+    /// Commands keep their own intervals, this only bounds how late a run
+    /// can start.
+    fn refresh(&self) -> Refresh {
+        Refresh::Polling(Duration::from_secs(1))
+    }
+
+    fn cache_key(&self, context: &ProviderContext) -> String {
+        context.instance()
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        app: &'a AppHandle,
+        context: &'a ProviderContext,
+    ) -> BoxFuture<'a, anyhow::Result<Snapshot>> {
+        Box::pin(async move {
+            let mut snapshot = Snapshot::new();
+            let (Some(widget_key), Some(manifest), Some(dir)) = (
+                context.widget_key.as_deref(),
+                context.manifest.as_ref(),
+                context.manifest_path.as_deref().and_then(Path::parent),
+            ) else {
+                return Ok(snapshot);
+            };
+            let instance = context.instance();
+            for command in manifest.shell_commands.iter().flatten() {
+                let values = if is_approved(app, widget_key, dir, command) {
+                    self.values(dir, &instance, widget_key, command).await
+                } else {
+                    self.request_approval(app, dir, widget_key, command);
+                    status(false, "Waiting for approval")
+                };
+                for (field, value) in values {
+                    snapshot.insert(format!("{}.{}", command.id, field), value);
+                }
+            }
+            Ok(snapshot)
+        })
+    }
+}
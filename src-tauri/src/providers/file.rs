@@ -0,0 +1,365 @@
+use notify_debouncer_full::{
+    new_debouncer,
+    notify::{RecommendedWatcher, RecursiveMode},
+    DebounceEventResult, Debouncer, RecommendedCache,
+};
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tauri::{AppHandle, Manager};
+
+use super::{
+    json_fields, BoxFuture, DataProvider, ProviderContext, ProviderRegistry, Refresh, Snapshot,
+    VariableValue,
+};
+use crate::{
+    commands::data_files::{is_approved, request_approval},
+    manifest::{DataFile, DataFileFormat},
+};
+
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(300);
+/// Files are read again at least this often, for folders that can not be
+/// watched
+const FALLBACK_INTERVAL: Duration = Duration::from_secs(30);
+/// Bigger files are refused, except for `lines` which only reads the end
+const MAX_FILE_SIZE: u64 = 8 * 1024 * 1024;
+const DEFAULT_LINES: usize = 10;
+
+type Values = Vec<(String, VariableValue)>;
+
+/// Reads the `dataFiles` of a widget. Their folders are watched so changes
+/// show up right away, not only on the fallback interval. Files outside the
+/// widget folder are only read once the user allows it.
+#[derive(Default)]
+pub struct FileProvider {
+    watcher: Mutex<Option<Debouncer<RecommendedWatcher, RecommendedCache>>>,
+    watched_dirs: Mutex<HashSet<PathBuf>>,
+    files: Arc<Mutex<HashSet<PathBuf>>>,
+    /// Widget key, file id and path of the approvals asked for, so the user
+    /// is asked once per session
+    requested: Mutex<HashSet<(String, String, PathBuf)>>,
+}
+
+/// `~/` is the home folder, relative paths start at the widget folder. `.`
+/// is dropped so the path matches what the watcher reports. Paths that end up
+/// outside the widget folder need the user's approval, see `is_inside`.
+fn resolve_path(app: &AppHandle, widget_dir: &Path, path: &str) -> PathBuf {
+    let home = app.path().home_dir().ok();
+    let path = match (
+        path.strip_prefix("~/").or_else(|| path.strip_prefix("~\\")),
+        home,
+    ) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => widget_dir.join(path),
+    };
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+/// Whether `path` stays in the widget folder, also after following symlinks.
+fn is_inside(widget_dir: &Path, path: &Path) -> bool {
+    if !path.starts_with(widget_dir) || path.components().any(|c| c == Component::ParentDir) {
+        return false;
+    }
+    match (fs::canonicalize(widget_dir), fs::canonicalize(path)) {
+        (Ok(dir), Ok(path)) => path.starts_with(dir),
+        // Nothing to follow yet, it is checked again on every read
+        _ => true,
+    }
+}
+
+fn read_capped(path: &Path) -> std::io::Result<String> {
+    let size = std::fs::metadata(path)?.len();
+    if size > MAX_FILE_SIZE {
+        return Err(std::io::Error::other(format!(
+            "file is larger than {} MB",
+            MAX_FILE_SIZE / 1024 / 1024
+        )));
+    }
+    std::fs::read_to_string(path)
+}
+
+/// The last `count` lines, reading backwards from the end so big logs are
+/// cheap.
+fn tail_lines(path: &Path, count: usize) -> std::io::Result<Vec<String>> {
+    const BLOCK: u64 = 8 * 1024;
+    let mut file = File::open(path)?;
+    let mut end = file.seek(SeekFrom::End(0))?;
+    let mut buffer: Vec<u8> = vec![];
+    // One more newline than lines wanted, a trailing one ends the last line
+    while end > 0 && buffer.iter().filter(|&&b| b == b'\n').count() <= count {
+        let start = end.saturating_sub(BLOCK);
+        let mut block = vec![0; (end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut block)?;
+        block.extend_from_slice(&buffer);
+        buffer = block;
+        end = start;
+    }
+    let text = String::from_utf8_lossy(&buffer);
+    let lines: Vec<&str> = text.lines().collect();
+    // Without reaching the start, the first line may be cut off
+    let skip = lines.len().saturating_sub(count);
+    Ok(lines[skip..].iter().map(|l| l.to_string()).collect())
+}
+
+/// RFC 4180 CSV: quoted fields may contain the delimiter, newlines and `""`.
+fn parse_csv(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if in_quotes => in_quotes = false,
+            '"' if field.is_empty() => in_quotes = true,
+            c if in_quotes => field.push(c),
+            c if c == delimiter => row.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.retain(|r| !(r.len() == 1 && r[0].is_empty()));
+    rows
+}
+
+fn extract_json(file: &DataFile, text: &str, errors: &mut Vec<String>) -> Result<Values, String> {
+    let json: Value = serde_json::from_str(text).map_err(|e| format!("Invalid JSON: {}", e))?;
+    Ok(json_fields(&json, file.select.as_ref(), errors))
+}
+
+fn extract_csv(file: &DataFile, text: &str) -> Result<Values, String> {
+    let rows = parse_csv(text, file.delimiter.unwrap_or(','));
+    let (header, data) = if file.header.unwrap_or(true) {
+        rows.split_first()
+            .map_or((None, &rows[..]), |(h, d)| (Some(h), d))
+    } else {
+        (None, &rows[..])
+    };
+    let index = file.row.unwrap_or(-1);
+    let position = if index < 0 {
+        data.len() as i64 + index
+    } else {
+        index
+    };
+    let row = usize::try_from(position)
+        .ok()
+        .and_then(|i| data.get(i))
+        .ok_or_else(|| format!("No row {} in {} rows", index, data.len()))?;
+
+    let names: Vec<String> = match &file.columns {
+        Some(columns) => columns.clone(),
+        None => match header {
+            Some(header) => header.clone(),
+            None => (0..row.len()).map(|i| i.to_string()).collect(),
+        },
+    };
+    let mut values: Values = vec![("rows".to_string(), (data.len() as f64).into())];
+    for name in names {
+        let column = header
+            .and_then(|h| h.iter().position(|c| *c == name))
+            .or_else(|| name.parse::<usize>().ok());
+        let value = match column.and_then(|i| row.get(i)) {
+            Some(cell) => cell.as_str().into(),
+            None => return Err(format!("No column {}", name)),
+        };
+        values.push((name, value));
+    }
+    Ok(values)
+}
+
+fn status(ok: bool, error: &str) -> Values {
+    vec![
+        ("ok".to_string(), ok.into()),
+        ("error".to_string(), error.into()),
+    ]
+}
+
+/// Every field the manifest asks for carries the error, so a widget shows
+/// what went wrong instead of an empty value.
+fn error_values(file: &DataFile, error: &str) -> Values {
+    let fields: Vec<String> = match file.format {
+        DataFileFormat::Json => file
+            .select
+            .as_ref()
+            .map(|s| s.keys().cloned().collect())
+            .unwrap_or_default(),
+        DataFileFormat::Csv => file.columns.clone().unwrap_or_default(),
+        DataFileFormat::Text | DataFileFormat::Lines => vec!["text".to_string()],
+    };
+    let mut values: Values = fields
+        .into_iter()
+        .map(|field| (field, format!("Error: {}", error).into()))
+        .collect();
+    values.extend(status(false, error));
+    values
+}
+
+fn read(file: &DataFile, path: &Path) -> Values {
+    let mut errors = vec![];
+    let result = match file.format {
+        DataFileFormat::Lines => tail_lines(path, file.lines.unwrap_or(DEFAULT_LINES))
+            .map_err(|e| format!("Cannot read {}: {}", file.path, e))
+            .map(|lines| {
+                let mut values: Values = vec![
+                    ("text".to_string(), lines.join("\n").into()),
+                    (
+                        "last".to_string(),
+                        lines.last().cloned().unwrap_or_default().into(),
+                    ),
+                    ("count".to_string(), (lines.len() as f64).into()),
+                ];
+                for (i, line) in lines.into_iter().enumerate() {
+                    values.push((format!("lines.{i}"), line.into()));
+                }
+                values
+            }),
+        format => read_capped(path)
+            .map_err(|e| format!("Cannot read {}: {}", file.path, e))
+            .and_then(|text| match format {
+                DataFileFormat::Json => extract_json(file, &text, &mut errors),
+                DataFileFormat::Csv => extract_csv(file, &text),
+                _ => Ok(vec![("text".to_string(), text.into())]),
+            }),
+    };
+    match result {
+        Ok(mut values) => {
+            values.extend(status(errors.is_empty(), &errors.join("; ")));
+            values
+        }
+        Err(e) => error_values(file, &e),
+    }
+}
+
+impl FileProvider {
+    /// Watches the folder rather than the file, editors and tools often
+    /// replace files instead of writing to them.
+    fn watch(&self, app: &AppHandle, path: &Path) {
+        self.files.lock().unwrap().insert(path.to_path_buf());
+        let Some(dir) = path.parent() else {
+            return;
+        };
+        if self.watched_dirs.lock().unwrap().contains(dir) {
+            return;
+        }
+
+        let mut watcher = self.watcher.lock().unwrap();
+        if watcher.is_none() {
+            let app = app.clone();
+            let files = Arc::clone(&self.files);
+            let debouncer = new_debouncer(
+                DEBOUNCE_TIMEOUT,
+                None,
+                move |result: DebounceEventResult| {
+                    let Ok(events) = result else {
+                        return;
+                    };
+                    let files = files.lock().unwrap();
+                    let changed = events
+                        .iter()
+                        .flat_map(|e| &e.event.paths)
+                        .any(|p| files.contains(p));
+                    if changed {
+                        app.state::<ProviderRegistry>().notify_changed("file");
+                    }
+                },
+            );
+            match debouncer {
+                Ok(debouncer) => *watcher = Some(debouncer),
+                Err(e) => {
+                    eprintln!("Error creating data file watcher: {}", e);
+                    return;
+                }
+            }
+        }
+        let Some(debouncer) = watcher.as_mut() else {
+            return;
+        };
+        match debouncer.watch(dir, RecursiveMode::NonRecursive) {
+            Ok(()) => {
+                self.watched_dirs.lock().unwrap().insert(dir.to_path_buf());
+            }
+            // Picked up by the fallback interval instead
+            Err(e) => eprintln!("Error watching {}: {}", dir.display(), e),
+        }
+    }
+}
+
+impl DataProvider for FileProvider {
+    fn namespace(&self) -> &'static str {
+        "file"
+    }
+
+    fn keys(&self) -> Vec<String> {
+        ["text", "ok", "error"]
+            .map(|field| format!("<id>.{field}"))
+            .to_vec()
+    }
+
+    fn refresh(&self) -> Refresh {
+        Refresh::Polling(FALLBACK_INTERVAL)
+    }
+
+    fn cache_key(&self, context: &ProviderContext) -> String {
+        context.instance()
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        app: &'a AppHandle,
+        context: &'a ProviderContext,
+    ) -> BoxFuture<'a, anyhow::Result<Snapshot>> {
+        Box::pin(async move {
+            let mut snapshot = Snapshot::new();
+            let (Some(widget_key), Some(manifest), Some(dir)) = (
+                context.widget_key.as_deref(),
+                context.manifest.as_ref(),
+                context.manifest_path.as_deref().and_then(Path::parent),
+            ) else {
+                return Ok(snapshot);
+            };
+            for file in manifest.data_files.iter().flatten() {
+                let path = resolve_path(app, dir, &file.path);
+                if !is_inside(dir, &path) && !is_approved(app, widget_key, &file.id, &path) {
+                    let request = (widget_key.to_string(), file.id.clone(), path.clone());
+                    if self.requested.lock().unwrap().insert(request) {
+                        request_approval(app, widget_key, &file.id, &path);
+                    }
+                    for (field, value) in error_values(file, "Waiting for approval") {
+                        snapshot.insert(format!("{}.{}", file.id, field), value);
+                    }
+                    continue;
+                }
+                self.watch(app, &path);
+                let values = {
+                    let file = file.clone();
+                    tauri::async_runtime::spawn_blocking(move || read(&file, &path)).await?
+                };
+                for (field, value) in values {
+                    snapshot.insert(format!("{}.{}", file.id, field), value);
+                }
+            }
+            Ok(snapshot)
+        })
+    }
+}
@@ -0,0 +1,631 @@
+use reqwest::{
+    header::{HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RETRY_AFTER},
+    Client, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tauri::{AppHandle, Manager};
+
+use super::{
+    flatten_json, json_fields, BoxFuture, DataProvider, ProviderContext, Refresh, Snapshot,
+    VariableValue,
+};
+use crate::{
+    commands::secrets::{fill_secrets, get_widget_secret},
+    manifest::HttpSource,
+};
+
+const DEFAULT_INTERVAL: u64 = 300;
+const DEFAULT_TIMEOUT: u64 = 15;
+/// First retry after a failed request, doubled for every failure after it
+const ERROR_RETRY: Duration = Duration::from_secs(30);
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+/// Bodies past this are refused
+const MAX_BODY: usize = 2 * 1024 * 1024;
+
+type Values = Vec<(String, VariableValue)>;
+
+/// The last good response of a source. Kept on disk so widgets have data
+/// right after a restart, or while offline.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedResponse {
+    /// The manifest URL, secrets are not filled in
+    pub url: String,
+    #[serde(default)]
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    pub body: String,
+    /// RFC 3339
+    pub fetched_at: String,
+}
+
+/// A successful response, `None` from `fetch_source` means not modified.
+pub struct Fetched {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: String,
+}
+
+pub struct FetchError {
+    pub message: String,
+    /// From the server's `Retry-After`
+    pub retry_after: Option<Duration>,
+}
+
+impl FetchError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+}
+
+/// `Retry-After` as seconds or as an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+}
+
+/// How long to wait after `failures` failed requests in a row. Never sooner
+/// than the server asked for.
+pub fn backoff(failures: u32, retry_after: Option<Duration>) -> Duration {
+    let exponential = ERROR_RETRY
+        .saturating_mul(1 << failures.saturating_sub(1).min(16))
+        .min(MAX_BACKOFF);
+    retry_after.map_or(exponential, |r| r.min(MAX_BACKOFF).max(exponential))
+}
+
+/// One conditional GET, with the validators of `previous`. Takes no app state
+/// so it can be pointed at a local server.
+pub async fn fetch_source(
+    client: &Client,
+    url: &str,
+    headers: &[(String, String)],
+    timeout: Duration,
+    previous: Option<&CachedResponse>,
+) -> Result<Option<Fetched>, FetchError> {
+    let mut request = client.get(url).timeout(timeout);
+    for (name, value) in headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    if let Some(previous) = previous {
+        if let Some(etag) = &previous.etag {
+            request = request.header(IF_NONE_MATCH, etag.as_str());
+        }
+        if let Some(last_modified) = &previous.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified.as_str());
+        }
+    }
+
+    let mut response = request.send().await.map_err(|e| {
+        FetchError::new(if e.is_timeout() {
+            format!("Timed out after {}s", timeout.as_secs())
+        } else {
+            format!("Request failed: {}", e)
+        })
+    })?;
+    let status = response.status();
+    let header = |name: HeaderName| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    if status == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    if !status.is_success() {
+        return Err(FetchError {
+            message: format!("HTTP {}", status),
+            retry_after: header(RETRY_AFTER).as_deref().and_then(parse_retry_after),
+        });
+    }
+
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+    let mut body = vec![];
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| FetchError::new(format!("Reading the response failed: {}", e)))?
+    {
+        body.extend_from_slice(&chunk);
+        if body.len() > MAX_BODY {
+            return Err(FetchError::new(format!(
+                "Response is larger than {} MB",
+                MAX_BODY / 1024 / 1024
+            )));
+        }
+    }
+    Ok(Some(Fetched {
+        etag,
+        last_modified,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    }))
+}
+
+/// Fields of a response body. JSON is picked apart with `select`, anything
+/// else is `text`.
+pub fn extract(source: &HttpSource, body: &str, errors: &mut Vec<String>) -> Values {
+    match serde_json::from_str::<Value>(body) {
+        Ok(json) if source.select.is_none() && !(json.is_object() || json.is_array()) => {
+            let mut values = vec![];
+            flatten_json("value", &json, &mut values);
+            values
+        }
+        Ok(json) => json_fields(&json, source.select.as_ref(), errors),
+        Err(e) if source.select.is_some() => {
+            let error = format!("Response is not JSON: {}", e);
+            let values = source
+                .select
+                .iter()
+                .flat_map(|s| s.keys())
+                .map(|field| (field.clone(), format!("Error: {}", error).into()))
+                .collect();
+            errors.push(error);
+            values
+        }
+        Err(_) => vec![("text".to_string(), body.into())],
+    }
+}
+
+#[derive(Default)]
+struct SourceState {
+    /// URL, headers and selection as written in the manifest, a change
+    /// starts over
+    config: String,
+    loaded: bool,
+    response: Option<CachedResponse>,
+    values: Values,
+    /// Problems with the fields of `response`
+    field_errors: Vec<String>,
+    /// Why the last request failed
+    error: Option<String>,
+    failures: u32,
+    next_fetch: Option<Instant>,
+}
+
+impl SourceState {
+    fn set_response(&mut self, source: &HttpSource, response: CachedResponse) {
+        let mut errors = vec![];
+        self.values = extract(source, &response.body, &mut errors);
+        self.field_errors = errors;
+        self.response = Some(response);
+    }
+
+    fn snapshot_values(&self) -> Values {
+        let error = self
+            .error
+            .iter()
+            .chain(&self.field_errors)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("; ");
+        let mut values = self.values.clone();
+        values.push(("ok".to_string(), error.is_empty().into()));
+        values.push(("error".to_string(), error.into()));
+        values.push((
+            "updated".to_string(),
+            self.response
+                .as_ref()
+                .map_or("", |r| r.fetched_at.as_str())
+                .into(),
+        ));
+        // Values are from an earlier response because the last request failed
+        values.push((
+            "stale".to_string(),
+            (self.response.is_some() && self.error.is_some()).into(),
+        ));
+        values
+    }
+}
+
+/// Polls the `httpSources` of a widget on their own intervals.
+pub struct HttpProvider {
+    client: Client,
+    /// By widget key and source id
+    sources: Mutex<HashMap<(String, String), SourceState>>,
+    running: Mutex<HashSet<(String, String)>>,
+}
+
+impl Default for HttpProvider {
+    fn default() -> Self {
+        Self {
+            client: Client::builder()
+                .user_agent(concat!("DeltaWidgets/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .unwrap_or_default(),
+            sources: Mutex::new(HashMap::new()),
+            running: Mutex::new(HashSet::new()),
+        }
+    }
+}
+
+fn cache_path(app: &AppHandle, widget_key: &str, id: &str) -> Option<PathBuf> {
+    let cache_dir = app.path().app_cache_dir().ok()?;
+    Some(
+        cache_dir
+            .join("http")
+            .join(widget_key)
+            .join(format!("{id}.json")),
+    )
+}
+
+fn read_cache(path: &Path, source: &HttpSource) -> Option<CachedResponse> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str::<CachedResponse>(&content)
+        .ok()
+        .filter(|cached| cached.url == source.url)
+}
+
+fn write_cache(path: &Path, response: &CachedResponse) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string(response)?)
+}
+
+fn load_cached(app: &AppHandle, widget_key: &str, source: &HttpSource) -> Option<CachedResponse> {
+    read_cache(&cache_path(app, widget_key, &source.id)?, source)
+}
+
+fn save_cached(app: &AppHandle, widget_key: &str, id: &str, response: &CachedResponse) {
+    let Some(path) = cache_path(app, widget_key, id) else {
+        return;
+    };
+    if let Err(e) = write_cache(&path, response) {
+        eprintln!("Error caching {}: {}", path.display(), e);
+    }
+}
+
+/// URL and headers of `source` with `{{secret:<name>}}` filled in by `secret`.
+fn filled_request(
+    source: &HttpSource,
+    secret: impl Fn(&str) -> Result<Option<String>, String>,
+) -> Result<(String, Vec<(String, String)>), String> {
+    let url = fill_secrets(&source.url, &secret)?;
+    let headers = source
+        .headers
+        .iter()
+        .flatten()
+        .map(|(name, value)| Ok((name.clone(), fill_secrets(value, &secret)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok((url, headers))
+}
+
+impl HttpProvider {
+    /// Last values of `source`, requesting it first when it is due. State is
+    /// kept per `instance`, so a preview does not reset the published widget's.
+    async fn values(
+        &self,
+        app: &AppHandle,
+        instance: &str,
+        widget_key: &str,
+        source: &HttpSource,
+    ) -> Values {
+        let key = (instance.to_string(), source.id.clone());
+        let config = json!([source.url, source.headers, source.select]).to_string();
+        let previous = {
+            let mut sources = self.sources.lock().unwrap();
+            let state = sources.entry(key.clone()).or_default();
+            if state.config != config {
+                *state = SourceState {
+                    config,
+                    ..Default::default()
+                };
+            }
+            if !state.loaded {
+                state.loaded = true;
+                if let Some(cached) = load_cached(app, widget_key, source) {
+                    state.set_response(source, cached);
+                }
+            }
+            let due = state.next_fetch.is_none_or(|at| at <= Instant::now());
+            if !due || !self.running.lock().unwrap().insert(key.clone()) {
+                return state.snapshot_values();
+            }
+            state.response.clone()
+        };
+
+        let filled = filled_request(source, |name| get_widget_secret(app, widget_key, name));
+        let result = match filled {
+            Ok((url, headers)) => {
+                let timeout = Duration::from_secs(source.timeout.unwrap_or(DEFAULT_TIMEOUT));
+                Ok(fetch_source(&self.client, &url, &headers, timeout, previous.as_ref()).await)
+            }
+            Err(e) => Err(e),
+        };
+        self.running.lock().unwrap().remove(&key);
+
+        let mut sources = self.sources.lock().unwrap();
+        let state = sources.entry(key).or_default();
+        let interval = Duration::from_secs(source.interval.unwrap_or(DEFAULT_INTERVAL));
+        match result {
+            // Tried again on the next poll, so setting the secret takes
+            // effect right away
+            Err(e) => state.error = Some(e),
+            Ok(Ok(fetched)) => {
+                state.error = None;
+                state.failures = 0;
+                state.next_fetch = Some(Instant::now() + interval);
+                let fetched_at = chrono::Utc::now().to_rfc3339();
+                let response = match (fetched, state.response.take()) {
+                    (Some(fetched), _) => Some(CachedResponse {
+                        url: source.url.clone(),
+                        etag: fetched.etag,
+                        last_modified: fetched.last_modified,
+                        body: fetched.body,
+                        fetched_at,
+                    }),
+                    (None, Some(previous)) => Some(CachedResponse {
+                        fetched_at,
+                        ..previous
+                    }),
+                    // Not modified without having asked for it
+                    (None, None) => None,
+                };
+                if let Some(response) = response {
+                    save_cached(app, widget_key, &source.id, &response);
+                    state.set_response(source, response);
+                }
+            }
+            Ok(Err(e)) => {
+                state.failures += 1;
+                state.next_fetch = Some(Instant::now() + backoff(state.failures, e.retry_after));
+                state.error = Some(e.message);
+            }
+        }
+        state.snapshot_values()
+    }
+}
+
+impl DataProvider for HttpProvider {
+    fn namespace(&self) -> &'static str {
+        "http"
+    }
+
+    fn keys(&self) -> Vec<String> {
+        ["text", "ok", "error", "updated", "stale"]
+            .map(|field| format!("<id>.{field}"))
+            .to_vec()
+    }
+
+    /// Sources keep their own intervals, this only bounds how late a request
+    /// can start.
+    fn refresh(&self) -> Refresh {
+        Refresh::Polling(Duration::from_secs(1))
+    }
+
+    fn cache_key(&self, context: &ProviderContext) -> String {
+        context.instance()
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        app: &'a AppHandle,
+        context: &'a ProviderContext,
+    ) -> BoxFuture<'a, anyhow::Result<Snapshot>> {
+        Box::pin(async move {
+            let mut snapshot = Snapshot::new();
+            let (Some(widget_key), Some(manifest)) =
+                (context.widget_key.as_deref(), context.manifest.as_ref())
+            else {
+                return Ok(snapshot);
+            };
+            let instance = context.instance();
+            for source in manifest.http_sources.iter().flatten() {
+                for (field, value) in self.values(app, &instance, widget_key, source).await {
+                    snapshot.insert(format!("{}.{}", source.id, field), value);
+                }
+            }
+            Ok(snapshot)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::localhost::request_header;
+    use std::{collections::BTreeMap, thread};
+
+    /// Status, headers and body of a reply
+    type Reply = (u16, Vec<(&'static str, String)>, String);
+
+    /// Answers requests on a local port with `respond` until the tests end,
+    /// returns the server's URL.
+    fn serve(respond: impl Fn(&tiny_http::Request) -> Reply + Send + 'static) -> String {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", server.server_addr().to_ip().unwrap());
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let (status, headers, body) = respond(&request);
+                let mut response = tiny_http::Response::from_string(body).with_status_code(status);
+                for (name, value) in headers {
+                    response.add_header(tiny_http::Header::from_bytes(name, value).unwrap());
+                }
+                let _ = request.respond(response);
+            }
+        });
+        url
+    }
+
+    fn source(url: &str) -> HttpSource {
+        serde_json::from_value(json!({ "id": "test", "url": url })).unwrap()
+    }
+
+    fn cached(url: &str, fetched: Fetched) -> CachedResponse {
+        CachedResponse {
+            url: url.to_string(),
+            etag: fetched.etag,
+            last_modified: fetched.last_modified,
+            body: fetched.body,
+            fetched_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    async fn fetch(
+        url: &str,
+        headers: &[(String, String)],
+        previous: Option<&CachedResponse>,
+    ) -> Result<Option<Fetched>, FetchError> {
+        fetch_source(
+            &Client::new(),
+            url,
+            headers,
+            Duration::from_secs(5),
+            previous,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn etag_not_modified() {
+        let url = serve(|req| match request_header(req, "If-None-Match") {
+            Some("\"v1\"") => (304, vec![], String::new()),
+            _ => (
+                200,
+                vec![("ETag", "\"v1\"".to_string())],
+                "{\"a\":1}".to_string(),
+            ),
+        });
+        let Ok(Some(first)) = fetch(&url, &[], None).await else {
+            panic!("expected a response");
+        };
+        assert_eq!(first.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(first.body, "{\"a\":1}");
+
+        let previous = cached(&url, first);
+        assert!(matches!(fetch(&url, &[], Some(&previous)).await, Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn last_modified_not_modified() {
+        const DATE: &str = "Wed, 21 Oct 2015 07:28:00 GMT";
+        let url = serve(|req| match request_header(req, "If-Modified-Since") {
+            Some(DATE) => (304, vec![], String::new()),
+            _ => (
+                200,
+                vec![("Last-Modified", DATE.to_string())],
+                "text".to_string(),
+            ),
+        });
+        let Ok(Some(first)) = fetch(&url, &[], None).await else {
+            panic!("expected a response");
+        };
+        assert_eq!(first.last_modified.as_deref(), Some(DATE));
+        assert_eq!(first.etag, None);
+
+        let previous = cached(&url, first);
+        assert!(matches!(fetch(&url, &[], Some(&previous)).await, Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn retry_after_delays_the_next_request() {
+        let url = serve(|_| (503, vec![("Retry-After", "120".to_string())], String::new()));
+        let Err(e) = fetch(&url, &[], None).await else {
+            panic!("expected an error");
+        };
+        assert_eq!(e.message, "HTTP 503 Service Unavailable");
+        assert_eq!(e.retry_after, Some(Duration::from_secs(120)));
+        assert_eq!(backoff(1, e.retry_after), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        assert_eq!(backoff(1, None), ERROR_RETRY);
+        assert_eq!(backoff(2, None), ERROR_RETRY * 2);
+        assert_eq!(backoff(4, None), ERROR_RETRY * 8);
+        assert_eq!(backoff(100, None), MAX_BACKOFF);
+        // Not sooner than the backoff, not later than the maximum
+        assert_eq!(backoff(3, Some(Duration::from_secs(1))), ERROR_RETRY * 4);
+        assert_eq!(
+            backoff(1, Some(Duration::from_secs(24 * 60 * 60))),
+            MAX_BACKOFF
+        );
+    }
+
+    #[test]
+    fn retry_after_seconds_and_dates() {
+        assert_eq!(parse_retry_after(" 30 "), Some(Duration::from_secs(30)));
+        let later = (chrono::Utc::now() + chrono::Duration::seconds(90)).to_rfc2822();
+        let wait = parse_retry_after(&later).unwrap();
+        assert!(wait > Duration::from_secs(80) && wait <= Duration::from_secs(90));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn disk_cache_is_revalidated_after_reload() {
+        let url = serve(|req| match request_header(req, "If-None-Match") {
+            Some("\"v1\"") => (304, vec![], String::new()),
+            _ => (
+                200,
+                vec![("ETag", "\"v1\"".to_string())],
+                "{\"a\":1}".to_string(),
+            ),
+        });
+        let dir = std::env::temp_dir().join(format!("delta-widgets-http-{}", std::process::id()));
+        let path = dir.join("test").join("source.json");
+        let Ok(Some(first)) = fetch(&url, &[], None).await else {
+            panic!("expected a response");
+        };
+        write_cache(&path, &cached(&url, first)).unwrap();
+
+        let loaded = read_cache(&path, &source(&url)).expect("a cached response");
+        assert_eq!(loaded.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(loaded.body, "{\"a\":1}");
+        assert!(matches!(fetch(&url, &[], Some(&loaded)).await, Ok(None)));
+        // A source pointed somewhere else starts over
+        assert!(read_cache(&path, &source("http://127.0.0.1:1/")).is_none());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn secrets_are_filled_in() {
+        let url = serve(|req| match request_header(req, "Authorization") {
+            Some("Bearer s3cret") => (200, vec![], "ok".to_string()),
+            _ => (401, vec![], String::new()),
+        });
+        let mut source = source(&format!("{url}?key={{{{secret:key}}}}"));
+        source.headers = Some(BTreeMap::from([(
+            "Authorization".to_string(),
+            "Bearer {{secret:token}}".to_string(),
+        )]));
+
+        let (filled_url, headers) = filled_request(&source, |name| {
+            Ok(match name {
+                "token" => Some("s3cret".to_string()),
+                "key" => Some("k".to_string()),
+                _ => None,
+            })
+        })
+        .unwrap();
+        assert_eq!(filled_url, format!("{url}?key=k"));
+        assert!(matches!(
+            fetch(&filled_url, &headers, None).await,
+            Ok(Some(fetched)) if fetched.body == "ok"
+        ));
+
+        // Nothing is sent without the secret
+        let missing = filled_request(&source, |_| Ok(None));
+        assert_eq!(missing.err().as_deref(), Some("Secret key is not set"));
+    }
+}
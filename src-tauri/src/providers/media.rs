@@ -0,0 +1,210 @@
+use base64::{prelude::BASE64_STANDARD, Engine};
+use std::sync::Mutex;
+use tauri::{AppHandle, EventId, Listener, Manager};
+
+use super::{
+    snapshot, BoxFuture, DataProvider, ProviderContext, ProviderRegistry, Refresh, Snapshot,
+};
+use crate::{
+    commands::media::{get_media, start_media_listener, stop_media_listener, MediaInfo},
+    template::format_duration,
+};
+
+const PLAY_BUTTON: &str =
+    "https://cdn.pixabay.com/photo/2017/03/13/04/25/play-button-2138735_1280.png";
+const PLAYER_ICON: &str = "https://i.pinimg.com/736x/bd/47/48/bd47480253e31367320c6f31eb2844ea.jpg";
+const CONTROLS: [&str; 8] = [
+    "next_enabled",
+    "prev_enabled",
+    "play_enabled",
+    "pause_enabled",
+    "stop_enabled",
+    "shuffle_enabled",
+    "repeat_enabled",
+    "toggle_enabled",
+];
+
+/// The current media session, pushed by the media listener's `media_updated`.
+#[derive(Default)]
+pub struct MediaProvider {
+    listener: Mutex<Option<EventId>>,
+}
+
+/// Same as `convertFileSrc` in the frontend.
+fn asset_url(path: &str) -> String {
+    let mut url = reqwest::Url::parse("http://asset.localhost/").expect("valid base URL");
+    url.path_segments_mut()
+        .expect("base URL has a path")
+        .pop_if_empty()
+        .push(path);
+    url.to_string()
+}
+
+fn or_na(text: &str) -> String {
+    if text.is_empty() {
+        "NA".to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+fn media_snapshot(media: Option<&MediaInfo>) -> Snapshot {
+    let Some(media) = media else {
+        return snapshot([
+            ("artist", "No media playing".into()),
+            ("title", "No media playing".into()),
+            ("player", "No media playing".into()),
+            ("status", PLAY_BUTTON.into()),
+            ("thumbnail", PLAY_BUTTON.into()),
+            ("position_text", "0:00".into()),
+            ("duration_text", "0:00".into()),
+        ]);
+    };
+    // Field access goes through JSON so this follows the shape the frontend sees
+    let value = serde_json::to_value(media).unwrap_or_default();
+    let text = |pointer: &str| {
+        value
+            .pointer(pointer)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+    };
+    let number = |pointer: &str| {
+        value
+            .pointer(pointer)
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.0)
+    };
+
+    let duration =
+        number("/timeline_properties/end_time") - number("/timeline_properties/start_time");
+    let position = number("/timeline_properties/position");
+    let player_icon = Some(text("/player/icon")).filter(|icon| !icon.is_empty());
+    let player = value
+        .pointer("/player/name")
+        .or_else(|| value.get("player_id"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("NA");
+    let thumbnail: Vec<u8> = value
+        .get("thumbnail")
+        .and_then(|v| v.as_array())
+        .map(|a| {
+            a.iter()
+                .filter_map(|b| b.as_u64())
+                .map(|b| b as u8)
+                .collect()
+        })
+        .unwrap_or_default();
+    let thumbnail = if !thumbnail.is_empty() {
+        format!(
+            "data:image/png;base64,{}",
+            BASE64_STANDARD.encode(thumbnail)
+        )
+    } else if let Some(icon) = player_icon {
+        asset_url(icon)
+    } else {
+        PLAY_BUTTON.to_string()
+    };
+
+    let mut values = snapshot([
+        ("artist", or_na(text("/artist")).into()),
+        ("title", or_na(text("/title")).into()),
+        ("player", player.into()),
+        ("status", or_na(text("/playback_info/status")).into()),
+        ("thumbnail", thumbnail.into()),
+        (
+            "player_icon",
+            player_icon
+                .map(asset_url)
+                .unwrap_or_else(|| PLAYER_ICON.to_string())
+                .into(),
+        ),
+        ("position", position.into()),
+        ("duration", duration.trunc().into()),
+        ("position_text", format_duration(position as u64).into()),
+        (
+            "duration_text",
+            format_duration(duration.max(0.0) as u64).into(),
+        ),
+    ]);
+    for control in CONTROLS {
+        let enabled = value
+            .pointer(&format!("/playback_info/controls/{control}"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        values.insert(control.to_string(), enabled.into());
+    }
+    values
+}
+
+impl DataProvider for MediaProvider {
+    fn namespace(&self) -> &'static str {
+        "media"
+    }
+
+    fn keys(&self) -> Vec<String> {
+        [
+            "artist",
+            "title",
+            "player",
+            "status",
+            "thumbnail",
+            "player_icon",
+            "position",
+            "duration",
+            "position_text",
+            "duration_text",
+        ]
+        .into_iter()
+        .chain(CONTROLS)
+        .map(str::to_string)
+        .collect()
+    }
+
+    fn refresh(&self) -> Refresh {
+        Refresh::Push
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        app: &'a AppHandle,
+        _context: &'a ProviderContext,
+    ) -> BoxFuture<'a, anyhow::Result<Snapshot>> {
+        Box::pin(async move {
+            let players = get_media(app.clone(), app.state())
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            Ok(media_snapshot(players.first()))
+        })
+    }
+
+    fn start<'a>(&'a self, app: &'a AppHandle) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            start_media_listener(app, &app.state())
+                .await
+                .map_err(|e| e.to_string())?;
+            let handle = app.clone();
+            let id = app.listen_any("media_updated", move |_| {
+                handle.state::<ProviderRegistry>().notify_changed("media");
+            });
+            if let Some(old) = self.listener.lock().unwrap().replace(id) {
+                app.unlisten(old);
+            }
+            Ok(())
+        })
+    }
+
+    fn stop<'a>(&'a self, app: &'a AppHandle) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            if let Some(id) = self.listener.lock().unwrap().take() {
+                app.unlisten(id);
+            }
+            if let Err(e) = stop_media_listener(&app.state()).await {
+                eprintln!("Error stopping media listener: {}", e);
+            }
+        })
+    }
+
+    fn emits_events(&self) -> bool {
+        true
+    }
+}
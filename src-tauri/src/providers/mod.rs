@@ -0,0 +1,464 @@
+//! Data sources behind `{{namespace:key}}` variables and the manifest
+//! `requires` list. A new source is one `DataProvider` registered in
+//! `ProviderRegistry::default`.
+
+mod audio;
+mod command;
+mod file;
+mod http;
+mod media;
+mod system;
+mod weather;
+
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+use tauri::{AppHandle, Manager};
+
+use crate::{expression, jsonpath, manifest::WidgetManifest};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// How long a push snapshot is reused while its provider is not started
+const UNSTARTED_PUSH_MAX_AGE: Duration = Duration::from_secs(1);
+/// Failed fetches are retried after at most this long
+const ERROR_RETRY: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Refresh {
+    /// Fetched again once the snapshot is older than the interval
+    Polling(Duration),
+    /// Fetched again after the provider reports a change with
+    /// `ProviderRegistry::notify_changed`
+    Push,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum VariableValue {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+}
+
+impl fmt::Display for VariableValue {
+    /// Formats values the way the frontend prints them, `1` rather than `1.0`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(text) => f.write_str(text),
+            Self::Number(n) if n.fract() == 0.0 && n.is_finite() => write!(f, "{}", *n as i64),
+            Self::Number(n) => write!(f, "{n}"),
+            Self::Bool(b) => write!(f, "{b}"),
+        }
+    }
+}
+
+impl From<String> for VariableValue {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<&str> for VariableValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+
+impl From<f64> for VariableValue {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<bool> for VariableValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<VariableValue> for expression::Value {
+    fn from(value: VariableValue) -> Self {
+        match value {
+            VariableValue::Text(text) => Self::Text(text),
+            VariableValue::Number(n) => Self::Number(n),
+            VariableValue::Bool(b) => Self::Bool(b),
+        }
+    }
+}
+
+/// Key -> value, everything a provider knows at one point in time.
+pub type Snapshot = BTreeMap<String, VariableValue>;
+
+pub fn snapshot<const N: usize>(entries: [(&str, VariableValue); N]) -> Snapshot {
+    entries
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect()
+}
+
+/// `a.b.0.c` keys for every scalar in `value`.
+pub fn flatten_json(prefix: &str, value: &Value, out: &mut Vec<(String, VariableValue)>) {
+    let key = |k: &str| {
+        if prefix.is_empty() {
+            k.to_string()
+        } else {
+            format!("{prefix}.{k}")
+        }
+    };
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map {
+                flatten_json(&key(k), v, out);
+            }
+        }
+        Value::Array(items) => {
+            for (i, v) in items.iter().enumerate() {
+                flatten_json(&key(&i.to_string()), v, out);
+            }
+        }
+        Value::String(s) => out.push((prefix.to_string(), s.as_str().into())),
+        Value::Number(n) => out.push((prefix.to_string(), n.as_f64().unwrap_or(0.0).into())),
+        Value::Bool(b) => out.push((prefix.to_string(), (*b).into())),
+        Value::Null => out.push((prefix.to_string(), "".into())),
+    }
+}
+
+/// Values `select` (field -> JSONPath) picks out of `json`, or every value
+/// without a selection. Fields without a match carry the error as their value
+/// and add it to `errors`, the others stay usable.
+pub fn json_fields(
+    json: &Value,
+    select: Option<&BTreeMap<String, String>>,
+    errors: &mut Vec<String>,
+) -> Vec<(String, VariableValue)> {
+    let mut values = vec![];
+    let Some(select) = select else {
+        flatten_json("", json, &mut values);
+        return values;
+    };
+    for (field, path) in select {
+        let value = match jsonpath::select_one(json, path) {
+            Ok(Some(Value::String(s))) => s.into(),
+            Ok(Some(Value::Number(n))) => n.as_f64().unwrap_or(0.0).into(),
+            Ok(Some(Value::Bool(b))) => b.into(),
+            Ok(Some(Value::Null)) => "".into(),
+            Ok(Some(other)) => other.to_string().into(),
+            Ok(None) => {
+                errors.push(format!("No match for {}", path));
+                format!("Error: no match for {}", path).into()
+            }
+            Err(e) => {
+                errors.push(e.clone());
+                format!("Error: {}", e).into()
+            }
+        };
+        values.push((field.clone(), value));
+    }
+    values
+}
+
+/// The widget a snapshot is fetched for, providers with per-widget settings
+/// read them from its manifest.
+#[derive(Default)]
+pub struct ProviderContext {
+    pub widget_key: Option<String>,
+    /// For a preview window, which shows the draft rather than the published
+    /// widget.
+    pub preview: bool,
+    pub manifest_path: Option<PathBuf>,
+    pub manifest: Option<WidgetManifest>,
+}
+
+/// Manifests found by `ProviderContext::lookup`, by widget key and whether
+/// the draft was asked for, with the modification time they were read at. The
+/// widget watcher clears it when a manifest is added, changed or removed.
+static MANIFESTS: Mutex<BTreeMap<(String, bool), (PathBuf, SystemTime, WidgetManifest)>> =
+    Mutex::new(BTreeMap::new());
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Drops the manifests cached for providers.
+pub fn invalidate_manifests() {
+    MANIFESTS.lock().unwrap().clear();
+}
+
+impl ProviderContext {
+    /// Looks the widget up in published widgets first, then in drafts.
+    pub fn for_widget(app: &AppHandle, widget_key: Option<&str>) -> Self {
+        Self::lookup(app, widget_key, false)
+    }
+
+    /// Like `for_widget`, but drafts come first for a `preview`.
+    pub fn lookup(app: &AppHandle, widget_key: Option<&str>, preview: bool) -> Self {
+        let Some(widget_key) = widget_key else {
+            return Self::default();
+        };
+        let (manifest_path, manifest) = Self::find_manifest(app, widget_key, preview).unzip();
+        Self {
+            widget_key: Some(widget_key.to_string()),
+            preview,
+            manifest_path,
+            manifest,
+        }
+    }
+
+    fn find_manifest(
+        app: &AppHandle,
+        widget_key: &str,
+        preview: bool,
+    ) -> Option<(PathBuf, WidgetManifest)> {
+        let cache_key = (widget_key.to_string(), preview);
+        // Writes made by the app itself land before the watcher reports them
+        if let Some((path, read_at, manifest)) = MANIFESTS.lock().unwrap().get(&cache_key) {
+            if modified(path) == Some(*read_at) {
+                return Some((path.clone(), manifest.clone()));
+            }
+        }
+
+        let sub_dirs = if preview {
+            ["saves", "widgets"]
+        } else {
+            ["widgets", "saves"]
+        };
+        let (path, manifest) = sub_dirs.into_iter().find_map(|sub_dir| {
+            let dir = app
+                .path()
+                .resolve(sub_dir, tauri::path::BaseDirectory::AppData)
+                .ok()?;
+            fs::read_dir(dir).ok()?.flatten().find_map(|entry| {
+                let path = entry.path().join("manifest.json");
+                WidgetManifest::load(&path)
+                    .ok()
+                    .filter(|m| m.key == widget_key)
+                    .map(|m| (path, m))
+            })
+        })?;
+        if let Some(read_at) = modified(&path) {
+            MANIFESTS
+                .lock()
+                .unwrap()
+                .insert(cache_key, (path.clone(), read_at, manifest.clone()));
+        }
+        Some((path, manifest))
+    }
+
+    pub fn for_window(app: &AppHandle, label: &str) -> Self {
+        Self::lookup(app, widget_key_for(label), is_preview(label))
+    }
+
+    /// Tells the widget's data apart from other widgets', and the draft's
+    /// from the published widget's.
+    pub fn instance(&self) -> String {
+        let key = self.widget_key.as_deref().unwrap_or_default();
+        if self.preview {
+            format!("{key}:preview")
+        } else {
+            key.to_string()
+        }
+    }
+
+    /// Value of a manifest custom field, empty values count as unset.
+    pub fn custom_field(&self, id: &str) -> Option<&str> {
+        self.manifest
+            .as_ref()?
+            .custom_fields
+            .as_ref()?
+            .get(id)
+            .map(|field| field.value.as_str())
+            .filter(|value| !value.is_empty())
+    }
+}
+
+/// `widget-<key>` or `widget-preview-<key>` -> `<key>`
+pub fn widget_key_for(label: &str) -> Option<&str> {
+    label
+        .strip_prefix("widget-preview-")
+        .or_else(|| label.strip_prefix("widget-"))
+}
+
+/// Whether `label` is the window previewing a draft.
+pub fn is_preview(label: &str) -> bool {
+    label.starts_with("widget-preview-")
+}
+
+pub trait DataProvider: Send + Sync {
+    fn namespace(&self) -> &'static str;
+
+    /// Keys a snapshot can contain, for listing variables.
+    fn keys(&self) -> Vec<String>;
+
+    fn refresh(&self) -> Refresh;
+
+    /// Snapshots are cached per namespace and cache key. Providers whose data
+    /// depends on the widget return something that tells widgets apart.
+    fn cache_key(&self, _context: &ProviderContext) -> String {
+        String::new()
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        app: &'a AppHandle,
+        context: &'a ProviderContext,
+    ) -> BoxFuture<'a, anyhow::Result<Snapshot>>;
+
+    /// Starts whatever keeps the data live, called when the first window
+    /// subscribes to the namespace.
+    fn start<'a>(&'a self, _app: &'a AppHandle) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Called when the last subscribed window goes away.
+    fn stop<'a>(&'a self, _app: &'a AppHandle) -> BoxFuture<'a, ()> {
+        Box::pin(async {})
+    }
+
+    /// Whether the provider sends its own events while started. Otherwise
+    /// subscribed windows get `<namespace>_updated` with the snapshot.
+    fn emits_events(&self) -> bool {
+        false
+    }
+}
+
+struct CachedSnapshot {
+    snapshot: Option<Snapshot>,
+    fetched_at: Instant,
+    stale: bool,
+}
+
+#[derive(Serialize)]
+pub struct ProviderInfo {
+    pub namespace: &'static str,
+    pub keys: Vec<String>,
+    /// `None` for push providers
+    pub polling_interval_ms: Option<u64>,
+}
+
+/// Every data provider, and the snapshots they last returned.
+pub struct ProviderRegistry {
+    providers: BTreeMap<&'static str, Arc<dyn DataProvider>>,
+    cache: Mutex<HashMap<(&'static str, String), CachedSnapshot>>,
+    started: Mutex<HashSet<&'static str>>,
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            providers: BTreeMap::new(),
+            cache: Mutex::new(HashMap::new()),
+            started: Mutex::new(HashSet::new()),
+        };
+        registry.register(media::MediaProvider::default());
+        registry.register(audio::AudioProvider);
+        registry.register(system::SystemProvider);
+        registry.register(weather::WeatherProvider);
+        registry.register(command::CommandProvider::default());
+        registry.register(file::FileProvider::default());
+        registry.register(http::HttpProvider::default());
+        registry
+    }
+}
+
+impl ProviderRegistry {
+    pub fn register(&mut self, provider: impl DataProvider + 'static) {
+        self.providers
+            .insert(provider.namespace(), Arc::new(provider));
+    }
+
+    pub fn get(&self, namespace: &str) -> Option<Arc<dyn DataProvider>> {
+        self.providers.get(namespace).cloned()
+    }
+
+    pub fn list(&self) -> Vec<ProviderInfo> {
+        self.providers
+            .values()
+            .map(|provider| ProviderInfo {
+                namespace: provider.namespace(),
+                keys: provider.keys(),
+                polling_interval_ms: match provider.refresh() {
+                    Refresh::Polling(interval) => Some(interval.as_millis() as u64),
+                    Refresh::Push => None,
+                },
+            })
+            .collect()
+    }
+
+    pub async fn start(&self, app: &AppHandle, namespace: &str) -> Result<(), String> {
+        let provider = self
+            .get(namespace)
+            .ok_or_else(|| format!("Unknown data provider: {}", namespace))?;
+        provider.start(app).await?;
+        self.started.lock().unwrap().insert(provider.namespace());
+        Ok(())
+    }
+
+    pub async fn stop(&self, app: &AppHandle, namespace: &str) {
+        let Some(provider) = self.get(namespace) else {
+            return;
+        };
+        self.started.lock().unwrap().remove(provider.namespace());
+        provider.stop(app).await;
+    }
+
+    /// Marks the snapshots of a push provider as outdated.
+    pub fn notify_changed(&self, namespace: &str) {
+        for ((cached_namespace, _), cached) in self.cache.lock().unwrap().iter_mut() {
+            if *cached_namespace == namespace {
+                cached.stale = true;
+            }
+        }
+    }
+
+    /// Latest snapshot of `namespace`, fetched again when the provider's
+    /// refresh strategy says so. `None` for unknown namespaces and failed
+    /// fetches.
+    pub async fn snapshot(
+        &self,
+        app: &AppHandle,
+        namespace: &str,
+        context: &ProviderContext,
+    ) -> Option<Snapshot> {
+        let provider = self.get(namespace)?;
+        let key = (provider.namespace(), provider.cache_key(context));
+        let max_age = match provider.refresh() {
+            Refresh::Polling(interval) => interval,
+            Refresh::Push if self.started.lock().unwrap().contains(key.0) => Duration::MAX,
+            Refresh::Push => UNSTARTED_PUSH_MAX_AGE,
+        };
+
+        if let Some(cached) = self.cache.lock().unwrap().get(&key) {
+            let max_age = match cached.snapshot {
+                Some(_) => max_age,
+                None => max_age.min(ERROR_RETRY),
+            };
+            if !cached.stale && cached.fetched_at.elapsed() < max_age {
+                return cached.snapshot.clone();
+            }
+        }
+
+        let snapshot = provider
+            .fetch(app, context)
+            .await
+            .map_err(|e| eprintln!("Error reading {} data: {}", namespace, e))
+            .ok();
+        self.cache.lock().unwrap().insert(
+            key,
+            CachedSnapshot {
+                snapshot: snapshot.clone(),
+                fetched_at: Instant::now(),
+                stale: false,
+            },
+        );
+        snapshot
+    }
+}
@@ -0,0 +1,149 @@
+use serde_json::Value;
+use std::time::Duration;
+use tauri::AppHandle;
+
+use super::{snapshot, BoxFuture, DataProvider, ProviderContext, Refresh, Snapshot, VariableValue};
+use crate::{commands::system::read_system_info, template::human_storage_size};
+
+const KEYS: [&str; 26] = [
+    "hostname",
+    "os",
+    "os_version",
+    "kernel",
+    "cpu_model",
+    "cpu_lcores",
+    "cpu_usage",
+    "cpu_speed",
+    "memory_total",
+    "memory_used",
+    "memory_available",
+    "memory_total_bytes",
+    "memory_used_bytes",
+    "memory_available_bytes",
+    "swap_total",
+    "swap_used",
+    "swap_available",
+    "swap_total_bytes",
+    "swap_used_bytes",
+    "swap_available_bytes",
+    "battery_model",
+    "battery_vendor",
+    "battery_health",
+    "battery_charge",
+    "battery_cycles",
+    "battery_technology",
+];
+
+/// Host, CPU, memory and battery info from sysinfo.
+pub struct SystemProvider;
+
+fn system_snapshot(system: &Value) -> Snapshot {
+    let number = |value: &Value, pointer: &str| {
+        value
+            .pointer(pointer)
+            .and_then(Value::as_f64)
+            .unwrap_or(0.0)
+    };
+    let text_or_na = |value: &Value, pointer: &str| -> VariableValue {
+        value
+            .pointer(pointer)
+            .and_then(Value::as_str)
+            .filter(|s| !s.is_empty())
+            .unwrap_or("NA")
+            .into()
+    };
+    let battery = system.pointer("/batteries/0").unwrap_or(&Value::Null);
+    let total_memory = number(system, "/total_memory");
+    let used_memory = number(system, "/used_memory");
+    let total_swap = number(system, "/total_swap");
+    let used_swap = number(system, "/used_swap");
+    let os = ["/os_name", "/os_version"]
+        .iter()
+        .filter_map(|p| system.pointer(p).and_then(Value::as_str))
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    snapshot([
+        ("hostname", text_or_na(system, "/hostname")),
+        (
+            "os",
+            if os.is_empty() {
+                "NA".into()
+            } else {
+                os.into()
+            },
+        ),
+        ("os_version", text_or_na(system, "/os_version")),
+        ("kernel", text_or_na(system, "/kernel_version")),
+        ("cpu_model", text_or_na(system, "/cpu/brand")),
+        ("cpu_lcores", number(system, "/cpu/count").into()),
+        (
+            "cpu_usage",
+            format!("{:.1}%", number(system, "/cpu/usage")).into(),
+        ),
+        (
+            "cpu_speed",
+            format!("{:.2} Ghz", number(system, "/cpu/speed") / 1000.0).into(),
+        ),
+        ("memory_total", human_storage_size(total_memory).into()),
+        ("memory_used", human_storage_size(used_memory).into()),
+        (
+            "memory_available",
+            human_storage_size(total_memory - used_memory).into(),
+        ),
+        ("memory_total_bytes", total_memory.into()),
+        ("memory_used_bytes", used_memory.into()),
+        (
+            "memory_available_bytes",
+            (total_memory - used_memory).into(),
+        ),
+        ("swap_total", human_storage_size(total_swap).into()),
+        ("swap_used", human_storage_size(used_swap).into()),
+        (
+            "swap_available",
+            human_storage_size(total_swap - used_swap).into(),
+        ),
+        ("swap_total_bytes", total_swap.into()),
+        ("swap_used_bytes", used_swap.into()),
+        ("swap_available_bytes", (total_swap - used_swap).into()),
+        ("battery_model", text_or_na(battery, "/model")),
+        ("battery_vendor", text_or_na(battery, "/vendor")),
+        (
+            "battery_health",
+            (number(battery, "/state_of_health") * 100.0).round().into(),
+        ),
+        (
+            "battery_charge",
+            (number(battery, "/state_of_charge") * 100.0).round().into(),
+        ),
+        ("battery_cycles", number(battery, "/cycle_count").into()),
+        ("battery_technology", text_or_na(battery, "/technology")),
+    ])
+}
+
+impl DataProvider for SystemProvider {
+    fn namespace(&self) -> &'static str {
+        "system"
+    }
+
+    fn keys(&self) -> Vec<String> {
+        KEYS.map(str::to_string).to_vec()
+    }
+
+    fn refresh(&self) -> Refresh {
+        Refresh::Polling(Duration::from_secs(2))
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        _app: &'a AppHandle,
+        _context: &'a ProviderContext,
+    ) -> BoxFuture<'a, anyhow::Result<Snapshot>> {
+        Box::pin(async move {
+            let system =
+                tauri::async_runtime::spawn_blocking(|| read_system_info(Some(false))).await?;
+            Ok(system_snapshot(&system))
+        })
+    }
+}
@@ -0,0 +1,140 @@
+use serde_json::Value;
+use std::time::Duration;
+use tauri::AppHandle;
+
+use super::{snapshot, BoxFuture, DataProvider, ProviderContext, Refresh, Snapshot, VariableValue};
+
+const KEYS: [&str; 13] = [
+    "city",
+    "region",
+    "country",
+    "temperature_celsius",
+    "temperature_fahrenheit",
+    "humidity",
+    "description",
+    "icon",
+    "precip_mm",
+    "precip_in",
+    "pressure_mb",
+    "pressure_in",
+    "uv_index",
+];
+
+/// Current weather from weatherapi.com for the widget's `weatherCity` custom
+/// field, or for the city looked up from the IP.
+pub struct WeatherProvider;
+
+fn city(context: &ProviderContext) -> String {
+    context
+        .custom_field("weatherCity")
+        .unwrap_or_default()
+        .to_string()
+}
+
+async fn fetch_weather(city: &str) -> anyhow::Result<Value> {
+    let Some(api_key) = option_env!("VITE_WEATHER_API_KEY") else {
+        anyhow::bail!("No weather API key configured");
+    };
+    let client = reqwest::Client::new();
+    let city = if city.trim().is_empty() {
+        let location: Value = client
+            .get("http://ip-api.com/json")
+            .send()
+            .await?
+            .json()
+            .await?;
+        ["city", "regionName", "country"]
+            .iter()
+            .filter_map(|k| location.get(*k).and_then(Value::as_str))
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(", ")
+    } else {
+        city.to_string()
+    };
+    Ok(client
+        .get("https://api.weatherapi.com/v1/current.json")
+        .query(&[("q", city.as_str()), ("key", api_key)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+fn weather_snapshot(weather: &Value) -> Snapshot {
+    let text_or_na = |pointer: &str| -> VariableValue {
+        weather
+            .pointer(pointer)
+            .and_then(Value::as_str)
+            .filter(|s| !s.is_empty())
+            .unwrap_or("NA")
+            .into()
+    };
+    let number = |field: &str| weather.pointer(&format!("/current/{field}"));
+    let with_unit = |field: &str, unit: &str| -> VariableValue {
+        match number(field).and_then(Value::as_f64) {
+            Some(n) => format!("{}{unit}", VariableValue::Number(n)).into(),
+            None => format!("undefined{unit}").into(),
+        }
+    };
+    let fixed = |field: &str, unit: &str| -> VariableValue {
+        let n = number(field).and_then(Value::as_f64).unwrap_or(0.0);
+        format!("{n:.1}{unit}").into()
+    };
+    snapshot([
+        ("city", text_or_na("/location/name")),
+        ("region", text_or_na("/location/region")),
+        ("country", text_or_na("/location/country")),
+        ("temperature_celsius", fixed("temp_c", "°C")),
+        ("temperature_fahrenheit", fixed("temp_f", "°F")),
+        ("humidity", fixed("humidity", "%")),
+        ("description", text_or_na("/current/condition/text")),
+        (
+            "icon",
+            weather
+                .pointer("/current/condition/icon")
+                .and_then(Value::as_str)
+                .unwrap_or("")
+                .into(),
+        ),
+        ("precip_mm", with_unit("precip_mm", "mm")),
+        ("precip_in", with_unit("precip_in", "in")),
+        ("pressure_mb", with_unit("pressure_mb", "mb")),
+        ("pressure_in", with_unit("pressure_in", "in")),
+        (
+            "uv_index",
+            number("uv").and_then(Value::as_f64).unwrap_or(0.0).into(),
+        ),
+    ])
+}
+
+impl DataProvider for WeatherProvider {
+    fn namespace(&self) -> &'static str {
+        "weather"
+    }
+
+    fn keys(&self) -> Vec<String> {
+        KEYS.map(str::to_string).to_vec()
+    }
+
+    fn refresh(&self) -> Refresh {
+        Refresh::Polling(Duration::from_secs(60 * 60))
+    }
+
+    /// One snapshot per city, the empty string is the city from the IP
+    fn cache_key(&self, context: &ProviderContext) -> String {
+        city(context)
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        _app: &'a AppHandle,
+        context: &'a ProviderContext,
+    ) -> BoxFuture<'a, anyhow::Result<Snapshot>> {
+        Box::pin(async move {
+            let weather = fetch_weather(&city(context)).await?;
+            Ok(weather_snapshot(&weather))
+        })
+    }
+}
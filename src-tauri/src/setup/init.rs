@@ -2,15 +2,27 @@ use include_dir::{include_dir, Dir};
 use serde_json::Value;
 use std::fs;
 use tauri::{
-    menu::{Menu, MenuItem},
+    menu::{IsMenuItem, Menu, MenuItem, Submenu},
     tray::TrayIconBuilder,
-    Manager,
+    AppHandle, Manager, Wry,
 };
 use tauri_plugin_autostart::ManagerExt;
 
-use crate::commands::store::{self, KVPair};
+use crate::commands::{
+    layouts::{apply_layout_by_name, get_layouts},
+    store::{self, KVPair},
+};
+use crate::manifest::WidgetManifest;
 use crate::migrations::all_migrations;
-use crate::{commands::widget::create_widget_window, setup::utils::ensure_paths};
+use crate::persist;
+use crate::{
+    commands::widget::create_widget_window,
+    setup::{
+        scheduler::{init_scheduler, ScheduleContext},
+        utils::ensure_paths,
+        watcher::init_watcher,
+    },
+};
 use crate::{
     db,
     migration::{run_migrations, Direction},
@@ -49,11 +61,60 @@ fn init_updater(app: &tauri::App) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn init_tray(app: &tauri::App) -> anyhow::Result<()> {
+const TRAY_ID: &str = "main";
+const LAYOUT_MENU_PREFIX: &str = "layout:";
+
+fn build_tray_menu(app: &AppHandle) -> anyhow::Result<Menu<Wry>> {
     let show_i = MenuItem::with_id(app, "show", "Show", true, None::<&str>)?;
     let quit_i = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
-    let menu = Menu::with_items(app, &[&show_i, &quit_i])?;
-    let tray = TrayIconBuilder::new()
+
+    let layouts = get_layouts(app).unwrap_or_default();
+    let mut layout_items = vec![];
+    for layout in &layouts {
+        layout_items.push(MenuItem::with_id(
+            app,
+            format!("{LAYOUT_MENU_PREFIX}{}", layout.name),
+            &layout.name,
+            true,
+            None::<&str>,
+        )?);
+    }
+    if layout_items.is_empty() {
+        layout_items.push(MenuItem::with_id(
+            app,
+            "no-layouts",
+            "No saved layouts",
+            false,
+            None::<&str>,
+        )?);
+    }
+    let layout_refs: Vec<&dyn IsMenuItem<Wry>> = layout_items
+        .iter()
+        .map(|i| i as &dyn IsMenuItem<Wry>)
+        .collect();
+    let layouts_i = Submenu::with_items(app, "Layouts", true, &layout_refs)?;
+
+    Ok(Menu::with_items(app, &[&show_i, &layouts_i, &quit_i])?)
+}
+
+/// Rebuilds the tray menu, e.g. after layouts were added or renamed.
+pub fn refresh_tray_menu(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    match build_tray_menu(app) {
+        Ok(menu) => {
+            if let Err(e) = tray.set_menu(Some(menu)) {
+                eprintln!("Error updating tray menu: {}", e);
+            }
+        }
+        Err(e) => eprintln!("Error building tray menu: {}", e),
+    }
+}
+
+fn init_tray(app: &tauri::App) -> anyhow::Result<()> {
+    let menu = build_tray_menu(app.handle())?;
+    let tray = TrayIconBuilder::with_id(TRAY_ID)
         .menu(&menu)
         .icon(app.default_window_icon().unwrap().clone())
         .tooltip("Delta Widgets")
@@ -70,8 +131,18 @@ fn init_tray(app: &tauri::App) -> anyhow::Result<()> {
             println!("quit menu item was clicked");
             app.exit(0);
         }
-        _ => {
-            println!("menu item {:?} not handled", event.id);
+        id => {
+            if let Some(name) = id.strip_prefix(LAYOUT_MENU_PREFIX) {
+                let name = name.to_string();
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) = apply_layout_by_name(&app, &name).await {
+                        eprintln!("Error applying layout {}: {}", name, e);
+                    }
+                });
+            } else {
+                println!("menu item {:?} not handled", event.id);
+            }
         }
     });
 
@@ -135,7 +206,7 @@ fn init_widgets(app: &tauri::App) -> anyhow::Result<()> {
 
     let app_handle = app.handle().clone();
     tauri::async_runtime::spawn(async move {
-        let mut paths: Vec<String> = vec![];
+        let mut visible: Vec<(String, WidgetManifest)> = vec![];
         widgets_dir
             .exists()
             .then(|| {
@@ -160,9 +231,13 @@ fn init_widgets(app: &tauri::App) -> anyhow::Result<()> {
             eprintln!("Migration failed: {:?}", e);
         }
 
-        let entries = widgets_dir
-            .read_dir()
-            .expect("Cannot read widgets directory");
+        let entries = match widgets_dir.read_dir() {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Cannot read widgets directory: {}", e);
+                return;
+            }
+        };
         for entry in entries {
             let entry = match entry {
                 Ok(e) => e,
@@ -172,34 +247,67 @@ fn init_widgets(app: &tauri::App) -> anyhow::Result<()> {
             let entry_path = entry.path();
             let manifest_path = entry_path.join("manifest.json");
             if manifest_path.exists() {
-                if let Ok(json) = fs::read_to_string(&manifest_path)
-                    .and_then(|contents| Ok(serde_json::from_str::<serde_json::Value>(&contents)))
-                    .expect("Cannot read manifest")
-                {
-                    let visible = json
-                        .get("visible")
-                        .and_then(Value::as_bool)
-                        .unwrap_or_else(|| false);
-
-                    if visible {
-                        paths.push(manifest_path.to_str().unwrap().to_string());
+                match WidgetManifest::load(&manifest_path) {
+                    Ok(manifest) => {
+                        if manifest.visible.unwrap_or(false) {
+                            visible.push((manifest_path.to_string_lossy().to_string(), manifest));
+                        }
                     }
+                    Err(e) => eprintln!("Skipping {}: {}", manifest_path.display(), e),
                 }
             }
         }
+        let context = ScheduleContext::current(
+            &app_handle,
+            visible.iter().filter_map(|(_, m)| m.schedule.as_ref()),
+        )
+        .await;
+        let paths = visible
+            .into_iter()
+            .filter(|(_, m)| context.allows(m.schedule.as_ref()))
+            .map(|(path, _)| path);
         for path in paths {
-            create_widget_window(
+            if let Err(e) = create_widget_window(
                 app_handle.clone(),
                 serde_json::json!(path).to_string(),
                 Some(false),
             )
-            .await;
+            .await
+            {
+                eprintln!("Failed to open widget {}: {}", path, e);
+            }
         }
     });
 
     Ok(())
 }
 
+/// Restores the store and manifests that a crash left corrupt, before anything
+/// reads them.
+fn init_recovery(app: &tauri::App) -> anyhow::Result<()> {
+    let app_data = app.path().app_data_dir()?;
+    let mut files = vec![
+        app_data.join("store.json"),
+        app_data.join(".migrations.json"),
+    ];
+    for dir in ["widgets", "saves"] {
+        let Ok(entries) = app_data.join(dir).read_dir() else {
+            continue;
+        };
+        files.extend(
+            entries
+                .flatten()
+                .map(|entry| entry.path().join("manifest.json"))
+                .filter(|path| path.exists()),
+        );
+    }
+    for path in files {
+        persist::recover(&path);
+    }
+
+    Ok(())
+}
+
 fn init_db(app: &tauri::App) -> anyhow::Result<()> {
     // Initialize database
     tauri::async_runtime::block_on(async move {
@@ -216,11 +324,16 @@ fn init_db(app: &tauri::App) -> anyhow::Result<()> {
 
 pub fn init_app(app: &&mut tauri::App) -> anyhow::Result<()> {
     ensure_paths(&app);
+    init_recovery(&app)?;
     init_updater(&app)?;
     init_tray(&app)?;
     init_autostart(&app)?;
     init_widgets(&app)?;
+    init_scheduler(&app);
     init_db(&app)?;
+    if let Err(e) = init_watcher(&app) {
+        eprintln!("Error starting widget watcher: {}", e);
+    }
 
     Ok(())
 }
@@ -1,2 +1,4 @@
 pub mod init;
+pub mod scheduler;
 pub mod utils;
+pub mod watcher;
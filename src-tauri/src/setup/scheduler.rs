@@ -0,0 +1,150 @@
+use chrono::{Datelike, Local, NaiveDateTime, Timelike};
+use serde_json::json;
+use std::{fs, path::PathBuf, time::Duration};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    commands::{
+        media::get_media,
+        system::is_on_battery,
+        widget::{close_widget_window, create_widget_window},
+    },
+    manifest::{minutes_of_day, Schedule, ScheduleMatch, ScheduleRule, WidgetManifest},
+};
+
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What schedule rules are checked against.
+pub struct ScheduleContext {
+    pub now: NaiveDateTime,
+    pub on_battery: bool,
+    pub media_playing: bool,
+}
+
+impl ScheduleContext {
+    /// Only asks for battery and media state when one of `schedules` uses it.
+    pub async fn current<'a>(
+        app: &AppHandle,
+        schedules: impl IntoIterator<Item = &'a Schedule>,
+    ) -> Self {
+        let rules: Vec<&ScheduleRule> = schedules.into_iter().flat_map(|s| &s.rules).collect();
+        let on_battery = rules
+            .iter()
+            .any(|r| matches!(r, ScheduleRule::Battery { .. }))
+            && is_on_battery();
+        let media_playing = if rules
+            .iter()
+            .any(|r| matches!(r, ScheduleRule::Media { .. }))
+        {
+            match get_media(app.clone(), app.state()).await {
+                Ok(players) => players.iter().any(|p| p.is_playing()),
+                Err(e) => {
+                    eprintln!("Error reading media state for schedules: {}", e);
+                    false
+                }
+            }
+        } else {
+            false
+        };
+        Self {
+            now: Local::now().naive_local(),
+            on_battery,
+            media_playing,
+        }
+    }
+
+    fn matches(&self, rule: &ScheduleRule) -> bool {
+        match rule {
+            ScheduleRule::Time { start, end } => {
+                let (Some(start), Some(end)) = (minutes_of_day(start), minutes_of_day(end)) else {
+                    return false;
+                };
+                let now = self.now.hour() * 60 + self.now.minute();
+                if start <= end {
+                    now >= start && now < end
+                } else {
+                    now >= start || now < end
+                }
+            }
+            ScheduleRule::Weekdays { days } => {
+                let today = self.now.weekday().num_days_from_monday();
+                days.iter().any(|d| *d as u32 == today)
+            }
+            ScheduleRule::Battery { on_battery } => self.on_battery == *on_battery,
+            ScheduleRule::Media { playing } => self.media_playing == *playing,
+        }
+    }
+
+    /// Whether a widget with `schedule` should be on screen right now. No
+    /// schedule, or one without rules, always allows it.
+    pub fn allows(&self, schedule: Option<&Schedule>) -> bool {
+        let Some(schedule) = schedule.filter(|s| !s.rules.is_empty()) else {
+            return true;
+        };
+        match schedule.match_mode {
+            ScheduleMatch::All => schedule.rules.iter().all(|r| self.matches(r)),
+            ScheduleMatch::Any => schedule.rules.iter().any(|r| self.matches(r)),
+        }
+    }
+}
+
+/// Visible published widgets that have a schedule.
+fn scheduled_widgets(app: &AppHandle) -> anyhow::Result<Vec<(PathBuf, WidgetManifest)>> {
+    let widgets_dir = app
+        .path()
+        .resolve("widgets", tauri::path::BaseDirectory::AppData)?;
+    Ok(fs::read_dir(&widgets_dir)?
+        .flatten()
+        .filter_map(|entry| {
+            let manifest_path = entry.path().join("manifest.json");
+            let manifest = WidgetManifest::load(&manifest_path).ok()?;
+            (manifest.visible.unwrap_or(false) && manifest.schedule.is_some())
+                .then_some((manifest_path, manifest))
+        })
+        .collect())
+}
+
+/// Opens or closes scheduled widgets to match their rules. The `visible` flag
+/// is left alone, a hidden widget stays hidden whatever its schedule says.
+pub async fn apply_schedules(app: &AppHandle) -> anyhow::Result<()> {
+    let widgets = scheduled_widgets(app)?;
+    if widgets.is_empty() {
+        return Ok(());
+    }
+    let context =
+        ScheduleContext::current(app, widgets.iter().filter_map(|(_, m)| m.schedule.as_ref()))
+            .await;
+
+    for (path, manifest) in widgets {
+        let label = manifest.window_label();
+        let is_open = app.get_webview_window(&label).is_some();
+        let should_show = context.allows(manifest.schedule.as_ref());
+        if should_show && !is_open {
+            let path = json!(path.to_string_lossy()).to_string();
+            if let Err(e) = create_widget_window(app.clone(), path, Some(false)).await {
+                eprintln!("Error opening scheduled widget {}: {}", manifest.key, e);
+            }
+        } else if !should_show && is_open {
+            if let Err(e) = close_widget_window(app.clone(), label).await {
+                eprintln!("Error closing scheduled widget {}: {}", manifest.key, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Re-evaluates schedules periodically. The first check happens one interval
+/// after startup, `init_widgets` already applies them when opening widgets.
+pub fn init_scheduler(app: &tauri::App) {
+    let app_handle = app.handle().clone();
+    tauri::async_runtime::spawn(async move {
+        let start = tokio::time::Instant::now() + SCHEDULE_INTERVAL;
+        let mut interval = tokio::time::interval_at(start, SCHEDULE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = apply_schedules(&app_handle).await {
+                eprintln!("Error applying widget schedules: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,203 @@
+use notify_debouncer_full::{
+    new_debouncer,
+    notify::{RecommendedWatcher, RecursiveMode},
+    DebounceEventResult, Debouncer, RecommendedCache,
+};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{
+    commands::utils::copy_dir_all,
+    manifest::{WidgetManifest, WidgetType},
+    persist,
+    plugins::localhost,
+    providers,
+};
+
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Watches `widgets/`, `saves/` and the source folders of HTML widgets so
+/// open widget windows pick up changes made on disk.
+pub struct WidgetWatcher {
+    debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+    widgets_dir: PathBuf,
+    saves_dir: PathBuf,
+    /// HTML source folder -> key of the widget served from it
+    html_sources: HashMap<PathBuf, String>,
+    /// Last manifest seen per file, without window state
+    manifests: HashMap<PathBuf, Value>,
+}
+
+impl WidgetWatcher {
+    fn label_for(&self, manifest_path: &Path, key: &str) -> String {
+        if manifest_path.starts_with(&self.saves_dir) {
+            format!("widget-preview-{key}")
+        } else {
+            format!("widget-{key}")
+        }
+    }
+
+    fn watch_html_source(&mut self, manifest: &WidgetManifest) {
+        if manifest.widget_type != WidgetType::Html {
+            return;
+        }
+        let Some(source) = manifest.file.as_ref().map(PathBuf::from) else {
+            return;
+        };
+        if !source.is_dir() || self.html_sources.contains_key(&source) {
+            return;
+        }
+        // Sources inside widgets/ or saves/ are already covered
+        if !source.starts_with(&self.widgets_dir) && !source.starts_with(&self.saves_dir) {
+            if let Err(e) = self.debouncer.watch(&source, RecursiveMode::Recursive) {
+                eprintln!("Error watching {}: {}", source.display(), e);
+                return;
+            }
+        }
+        self.html_sources.insert(source, manifest.key.clone());
+    }
+
+    /// Returns the window to notify if the manifest changed in a way the
+    /// widget cares about. Moving or resizing a window, or rescheduling it,
+    /// is not such a change.
+    fn manifest_changed(&mut self, manifest_path: &Path) -> Option<String> {
+        let manifest = WidgetManifest::load(manifest_path).ok()?;
+        self.watch_html_source(&manifest);
+
+        let mut value = manifest.to_value().ok()?;
+        if let Some(obj) = value.as_object_mut() {
+            obj.remove("position");
+            obj.remove("anchor");
+            obj.remove("monitorPositions");
+            obj.remove("dimensions");
+            obj.remove("schedule");
+        }
+        if self.manifests.get(manifest_path) == Some(&value) {
+            return None;
+        }
+        self.manifests.insert(manifest_path.to_path_buf(), value);
+        Some(self.label_for(manifest_path, &manifest.key))
+    }
+
+    /// `widgets/<dir>/...` or `saves/<dir>/...` -> `<dir>/manifest.json`
+    fn manifest_path_for(&self, path: &Path) -> Option<PathBuf> {
+        [&self.widgets_dir, &self.saves_dir]
+            .into_iter()
+            .find_map(|base| {
+                let dir = path.strip_prefix(base).ok()?.components().next()?;
+                Some(base.join(dir).join("manifest.json"))
+            })
+    }
+}
+
+fn handle_events(app: &AppHandle, paths: Vec<PathBuf>) {
+    let Some(state) = app.try_state::<Mutex<WidgetWatcher>>() else {
+        return;
+    };
+    let mut labels = HashSet::new();
+    let mut html_keys = HashMap::new();
+    {
+        let Ok(mut watcher) = state.lock() else {
+            return;
+        };
+        for path in &paths {
+            if let Some(manifest_path) = watcher.manifest_path_for(path) {
+                // A manifest written, or its widget folder added or removed
+                if path == &manifest_path || manifest_path.parent() == Some(path) {
+                    providers::invalidate_manifests();
+                }
+                if let Some(label) = watcher.manifest_changed(&manifest_path) {
+                    labels.insert(label);
+                }
+            }
+            for (source, key) in &watcher.html_sources {
+                if path.starts_with(source)
+                    && path.file_name() != Some("manifest.json".as_ref())
+                    && !persist::is_sidecar(path)
+                {
+                    html_keys.insert(key.clone(), source.clone());
+                }
+            }
+        }
+    }
+
+    for label in labels {
+        let _ = app.emit_to(label, "update-manifest", 1);
+    }
+
+    let Ok(files_dir) = app
+        .path()
+        .resolve("files", tauri::path::BaseDirectory::AppCache)
+    else {
+        return;
+    };
+    for (key, source) in html_keys {
+        // Served from the source folder, and live reload refreshes its pages
+        if localhost::has_dev_folder(&key) {
+            continue;
+        }
+        if let Err(e) = copy_dir_all(&source, files_dir.join(&key)) {
+            eprintln!("Error syncing HTML widget {}: {}", key, e);
+            continue;
+        }
+        for label in [format!("widget-{key}"), format!("widget-preview-{key}")] {
+            if let Some(window) = app.get_webview_window(&label) {
+                let _ = window.eval("window.location.reload()");
+            }
+        }
+    }
+}
+
+pub fn init_watcher(app: &tauri::App) -> anyhow::Result<()> {
+    let widgets_dir = app
+        .path()
+        .resolve("widgets", tauri::path::BaseDirectory::AppData)?;
+    let saves_dir = app
+        .path()
+        .resolve("saves", tauri::path::BaseDirectory::AppData)?;
+
+    let app_handle = app.handle().clone();
+    let mut debouncer = new_debouncer(
+        DEBOUNCE_TIMEOUT,
+        None,
+        move |result: DebounceEventResult| match result {
+            Ok(events) => {
+                let paths = events.into_iter().flat_map(|e| e.event.paths).collect();
+                handle_events(&app_handle, paths);
+            }
+            Err(errors) => {
+                for e in errors {
+                    eprintln!("Widget watcher error: {}", e);
+                }
+            }
+        },
+    )?;
+    for dir in [&widgets_dir, &saves_dir] {
+        std::fs::create_dir_all(dir)?;
+        debouncer.watch(dir, RecursiveMode::Recursive)?;
+    }
+
+    let mut watcher = WidgetWatcher {
+        debouncer,
+        widgets_dir,
+        saves_dir,
+        html_sources: HashMap::new(),
+        manifests: HashMap::new(),
+    };
+    for dir in [watcher.widgets_dir.clone(), watcher.saves_dir.clone()] {
+        for entry in std::fs::read_dir(&dir)?.flatten() {
+            let manifest_path = entry.path().join("manifest.json");
+            // Seed the cache so startup does not count as a change
+            watcher.manifest_changed(&manifest_path);
+        }
+    }
+
+    app.manage(Mutex::new(watcher));
+    Ok(())
+}
@@ -0,0 +1,357 @@
+//! `{{namespace}}` and `{{namespace:argument}}` templates, the syntax
+//! `parseDynamicText` resolves in JSON widgets, plus expressions with filters
+//! such as `{{system:memory_used_bytes | bytes}}`.
+
+use chrono::{DateTime, TimeZone};
+use std::{collections::BTreeSet, fmt::Write};
+
+use crate::expression::{self, Error, Expr, Value};
+
+/// What the frontend shows for a variable it can not resolve yet.
+pub const UNRESOLVED: &str = "Loading...";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    Variable {
+        namespace: &'a str,
+        argument: Option<&'a str>,
+    },
+    Expression(Expr),
+}
+
+fn parse_variable(inner: &str) -> Option<Segment<'_>> {
+    let (namespace, argument) = match inner.split_once(':') {
+        Some((namespace, argument)) => (namespace, Some(argument)),
+        None => (inner, None),
+    };
+    let is_word =
+        |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !is_word(namespace) || argument.is_some_and(str::is_empty) {
+        return None;
+    }
+    Some(Segment::Variable {
+        namespace,
+        argument,
+    })
+}
+
+/// A lone variable keeps its argument as written, `{{date:yyyy-MM-dd}}` is a
+/// format rather than a subtraction. Anything else that parses is an
+/// expression.
+fn parse_segment(inner: &str) -> Option<Segment<'_>> {
+    let variable = parse_variable(inner);
+    match (variable, expression::parse(inner)) {
+        (Some(variable), Ok(Expr::Variable { .. }) | Err(_)) => Some(variable),
+        (_, Ok(expr)) => Some(Segment::Expression(expr)),
+        (None, Err(_)) => None,
+    }
+}
+
+/// Splits a template into text and variables. Anything that does not parse
+/// as a variable is kept as text.
+pub fn parse(template: &str) -> Vec<Segment<'_>> {
+    let mut segments = vec![];
+    let mut text_start = 0;
+    let mut cursor = 0;
+    while let Some(open) = template[cursor..].find("{{").map(|i| cursor + i) {
+        let inner_start = open + 2;
+        // Like `[^}]+` in the frontend, a variable ends at the first `}`
+        let Some(close) = template[inner_start..].find('}').map(|i| inner_start + i) else {
+            break;
+        };
+        let variable = template[close..]
+            .starts_with("}}")
+            .then(|| parse_segment(&template[inner_start..close]))
+            .flatten();
+        match variable {
+            Some(variable) => {
+                if open > text_start {
+                    segments.push(Segment::Text(&template[text_start..open]));
+                }
+                segments.push(variable);
+                cursor = close + 2;
+                text_start = cursor;
+            }
+            None => cursor = open + 1,
+        }
+    }
+    if text_start < template.len() {
+        segments.push(Segment::Text(&template[text_start..]));
+    }
+    segments
+}
+
+/// Namespaces a template uses, so only their data has to be fetched.
+pub fn namespaces(template: &str) -> BTreeSet<String> {
+    let mut namespaces = BTreeSet::new();
+    for segment in parse(template) {
+        match segment {
+            Segment::Variable { namespace, .. } => {
+                namespaces.insert(namespace.to_string());
+            }
+            Segment::Expression(expr) => {
+                let mut used = BTreeSet::new();
+                expr.namespaces(&mut used);
+                namespaces.extend(used.into_iter().map(str::to_string));
+            }
+            Segment::Text(_) => {}
+        }
+    }
+    namespaces
+}
+
+/// `resolve` returns `None` for variables whose data is not loaded yet, they
+/// render as `Loading...`. Expressions that fail render their error.
+pub fn render(
+    template: &str,
+    mut resolve: impl FnMut(&str, Option<&str>) -> Option<Value>,
+) -> String {
+    parse(template)
+        .into_iter()
+        .map(|segment| match segment {
+            Segment::Text(text) => text.to_string(),
+            Segment::Variable {
+                namespace,
+                argument,
+            } => resolve(namespace, argument)
+                .map_or_else(|| UNRESOLVED.to_string(), |value| value.to_string()),
+            Segment::Expression(expr) => match expr.evaluate(&mut resolve) {
+                Ok(value) => value.to_string(),
+                Err(Error::Unresolved) => UNRESOLVED.to_string(),
+                Err(Error::Invalid(e)) => format!("Error: {}", e),
+            },
+        })
+        .collect()
+}
+
+/// Converts a date-fns format, as used by the `date`, `time` and `datetime`
+/// variables, to a chrono strftime format.
+fn date_fns_to_strftime(format: &str) -> String {
+    let chars: Vec<char> = format.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\'' {
+            // Quoted literal text, `''` is a quote
+            i += 1;
+            while i < chars.len() {
+                if chars[i] == '\'' {
+                    if chars.get(i + 1) == Some(&'\'') {
+                        out.push('\'');
+                        i += 2;
+                        continue;
+                    }
+                    break;
+                }
+                if chars[i] == '%' {
+                    out.push('%');
+                }
+                out.push(chars[i]);
+                i += 1;
+            }
+            i += 1;
+            continue;
+        }
+        if !c.is_ascii_alphabetic() {
+            if c == '%' {
+                out.push('%');
+            }
+            out.push(c);
+            i += 1;
+            continue;
+        }
+
+        let count = chars[i..].iter().take_while(|&&x| x == c).count();
+        let token = match (c, count) {
+            ('y', 2) => "%y",
+            ('y', _) => "%Y",
+            ('M', 1) => "%-m",
+            ('M', 2) => "%m",
+            ('M', 3) => "%b",
+            ('M', _) => "%B",
+            ('d', 1) => "%-d",
+            ('d', _) => "%d",
+            ('E' | 'e', 1..=3) => "%a",
+            ('E' | 'e', _) => "%A",
+            ('H', 1) => "%-H",
+            ('H', _) => "%H",
+            ('h', 1) => "%-I",
+            ('h', _) => "%I",
+            ('m', 1) => "%-M",
+            ('m', _) => "%M",
+            ('s', 1) => "%-S",
+            ('s', _) => "%S",
+            ('a', _) => "%p",
+            _ => "",
+        };
+        if token.is_empty() {
+            out.extend(&chars[i..i + count]);
+        } else {
+            out.push_str(token);
+        }
+        i += count;
+    }
+    out
+}
+
+/// Formats `date` with a date-fns format. A `:[Area/City]` time zone suffix
+/// is accepted but ignored, dates are always local.
+pub fn format_date<Tz: TimeZone>(date: &DateTime<Tz>, format: &str) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let format = match format.rfind(":[") {
+        Some(i) if format.ends_with(']') => &format[..i],
+        _ => format,
+    };
+    let mut out = String::new();
+    match write!(out, "{}", date.format(&date_fns_to_strftime(format))) {
+        Ok(_) => out,
+        Err(_) => "Invalid date or format".to_string(),
+    }
+}
+
+/// Milliseconds as `mm:ss`, or `hh:mm:ss` past an hour.
+pub fn format_duration(millis: u64) -> String {
+    let total = millis / 1000;
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        format!("{hours:02}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes:02}:{seconds:02}")
+    }
+}
+
+/// Bytes in binary units with one decimal, e.g. `1.5 GiB`.
+pub fn human_storage_size(bytes: f64) -> String {
+    const UNITS: [&str; 8] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB", "ZiB", "YiB"];
+    if bytes.abs() < 1024.0 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes;
+    let mut unit = 0;
+    loop {
+        value /= 1024.0;
+        if (value.abs() * 10.0).round() / 10.0 < 1024.0 || unit == UNITS.len() - 1 {
+            break;
+        }
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, NaiveDate};
+
+    fn render_with(template: &str) -> String {
+        render(template, |namespace, argument| {
+            match (namespace, argument) {
+                ("system", Some("cpu")) => Some(Value::Number(42.0)),
+                ("custom", Some("name")) => Some(Value::Text("Ada".to_string())),
+                ("date", Some(format)) => Some(Value::Text(format!("<{}>", format))),
+                _ => None,
+            }
+        })
+    }
+
+    #[test]
+    fn segments() {
+        assert_eq!(
+            parse("CPU {{system:cpu}}%"),
+            vec![
+                Segment::Text("CPU "),
+                Segment::Variable {
+                    namespace: "system",
+                    argument: Some("cpu"),
+                },
+                Segment::Text("%"),
+            ]
+        );
+        assert_eq!(
+            parse("{{weather}}"),
+            vec![Segment::Variable {
+                namespace: "weather",
+                argument: None,
+            }]
+        );
+        assert!(matches!(
+            parse("{{system:cpu | round}}").as_slice(),
+            [Segment::Expression(Expr::Filter { .. })]
+        ));
+        // Not variables, kept as text
+        for text in ["{{}}", "{{ }", "{{a:}}", "{{a}b}}", "{{1 +}}", "{{"] {
+            assert_eq!(parse(text), vec![Segment::Text(text)], "{}", text);
+        }
+    }
+
+    #[test]
+    fn lone_variables_keep_their_argument() {
+        assert_eq!(
+            parse("{{date:yyyy-MM-dd}}"),
+            vec![Segment::Variable {
+                namespace: "date",
+                argument: Some("yyyy-MM-dd"),
+            }]
+        );
+        assert_eq!(render_with("{{date:yyyy-MM-dd}}"), "<yyyy-MM-dd>");
+    }
+
+    #[test]
+    fn rendering() {
+        assert_eq!(
+            render_with("{{custom:name}} at {{system:cpu}}%"),
+            "Ada at 42%"
+        );
+        assert_eq!(render_with("{{system:cpu * 2}}"), "84");
+        assert_eq!(render_with("{{system:gpu}}"), UNRESOLVED);
+        assert_eq!(render_with("{{system:gpu + 1}}"), UNRESOLVED);
+        assert_eq!(render_with("{{1 / 0}}"), "Error: Division by zero");
+        assert_eq!(render_with("{ {{system:cpu}} }"), "{ 42 }");
+    }
+
+    #[test]
+    fn used_namespaces() {
+        assert_eq!(
+            namespaces("{{date:HH:mm}} {{system:cpu > 50 ? custom:hot : media:title}}")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            ["custom", "date", "media", "system"]
+        );
+    }
+
+    #[test]
+    fn date_fns_formats() {
+        assert_eq!(
+            date_fns_to_strftime("yyyy-MM-dd HH:mm:ss"),
+            "%Y-%m-%d %H:%M:%S"
+        );
+        assert_eq!(date_fns_to_strftime("EEEE, d MMMM"), "%A, %-d %B");
+        assert_eq!(date_fns_to_strftime("h:mm a"), "%-I:%M %p");
+        assert_eq!(date_fns_to_strftime("'at' HH 'o''clock'"), "at %H o'clock");
+        assert_eq!(date_fns_to_strftime("100%"), "100%%");
+
+        let date = NaiveDate::from_ymd_opt(2024, 3, 5)
+            .unwrap()
+            .and_hms_opt(14, 7, 0)
+            .unwrap()
+            .and_local_timezone(FixedOffset::east_opt(0).unwrap())
+            .unwrap();
+        assert_eq!(format_date(&date, "dd.MM.yy HH:mm"), "05.03.24 14:07");
+        assert_eq!(format_date(&date, "HH:mm:[Europe/Berlin]"), "14:07");
+    }
+
+    #[test]
+    fn durations_and_sizes() {
+        assert_eq!(format_duration(0), "00:00");
+        assert_eq!(format_duration(61_999), "01:01");
+        assert_eq!(format_duration(3_723_000), "01:02:03");
+        assert_eq!(human_storage_size(512.0), "512 B");
+        assert_eq!(human_storage_size(1536.0), "1.5 KiB");
+        assert_eq!(human_storage_size(1024.0 * 1024.0 - 1.0), "1.0 MiB");
+        assert_eq!(human_storage_size(1024f64.powi(9)), "1024.0 YiB");
+    }
+}
@@ -83,6 +83,23 @@ export const WidgetSchema = z.object({
 
   url: z.string().optional(),
   file: z.string().optional(),
+  dev: z.boolean().optional(),
+  permissions: z
+    .array(
+      z.enum([
+        "media:read",
+        "media:control",
+        "audio:read",
+        "system:read",
+        "store",
+        "keyring",
+        "file:read",
+        "http:read",
+        "cmd:read",
+      ]),
+    )
+    .optional(),
+  proxyHosts: z.array(z.string()).optional(),
 
   widgetType: z.enum(["url", "html", "json"]),
 
@@ -161,6 +178,19 @@ interface IWidget {
   elements?: IWidgetElement[];
   url?: string;
   file?: string;
+  dev?: boolean;
+  permissions?: (
+    | "media:read"
+    | "media:control"
+    | "audio:read"
+    | "system:read"
+    | "store"
+    | "keyring"
+    | "file:read"
+    | "http:read"
+    | "cmd:read"
+  )[];
+  proxyHosts?: string[];
   widgetType?: "url" | "html" | "json";
   customFields?: TCustomFields;
   customAssets?: ICustomAssets[];
@@ -205,6 +235,12 @@ const tauriCommands = `Available Tauri commands via window.__TAURI__.core.invoke
 | \`start_audio_capture\`      | Starts capturing live system audio samples for waveform visualization.        | _None_                      | Promise<void\>                          |
 | \`stop_audio_capture\`       | Stops the active system audio capture stream.                                 | _None_                      | Promise<void\>                          |
 | \`get_current_device_cmd\`   | Returns the ID of the current audio output device.                            | _None_                      | Promise<String\>                        |
+| \`render_template\`          | Resolves \`{{namespace:key}}\` variables in a string, e.g. \`{{media:title}}\`.  | \`{ template: string }\`      | Promise<String\>                        |
+| \`subscribe_template\`       | Renders a template and emits \`template-updated\` whenever the result changes. | \`{ template: string }\`      | Promise<{ id, text }\>                  |
+| \`unsubscribe_template\`     | Stops the updates of a template subscription.                                 | \`{ id: string }\`            | Promise<void\>                          |
+| \`list_variables\`           | Lists the variable namespaces backed by data providers, with their keys.      | _None_                      | Promise<{ namespace, keys }[]\>         |
+| \`list_shell_commands\`      | Lists the shell commands a widget declares and whether they are approved.     | \`{ widgetKey: string }\`     | Promise<{ id, command, approved }[]\>   |
+| \`list_widget_secrets\`      | Lists the secrets a widget's \`httpSources\` use and whether they are set.    | \`{ widgetKey: string }\`     | Promise<{ name, set }[]\>               |
 
 Use \`start_media_listener_cmd\` to begin monitoring system media metadata. Once started, the application will emit a \`media_updated\` event whenever information about the currently playing media changes (such as title, artist, album art, or playback state).
 
@@ -212,6 +248,24 @@ To receive live system audio waveform samples, call \`start_audio_capture\`. Thi
 
 Because continuous audio capture can increase CPU usage, it is recommended to call \`stop_audio_capture\` when audio sample updates are no longer needed.
 
+The same dynamic variables JSON widgets use (\`date\`, \`time\`, \`datetime\`, \`custom\`, \`media\`, \`system\`, \`weather\`) can be resolved with \`render_template\`. For values that change, call \`subscribe_template\` once and listen for the \`template-updated\` event, whose payload is \`{ id, text }\`.
+
+Inside \`{{ }}\` values can go through filters and expressions, evaluated in Rust: \`{{system:memory_used_bytes | bytes}}\`, \`{{media:position | duration}}\` (milliseconds, \`duration("s")\` for seconds), \`{{system:cpu_usage | round(1)}}\`, \`| floor\`, \`| ceil\`, \`| abs\`, \`| upper\`, \`| lower\`, \`| trim\`, \`| truncate(20)\`, \`{{weather:city | default("—")}}\` and \`{{now | date("%H:%M")}}\` (strftime, for Unix times, \`now\` or ISO dates). Arithmetic (\`+ - * / %\`), comparisons (\`== != < <= > >=\`), \`&&\`, \`||\`, \`!\` and \`cond ? a : b\` work too, e.g. \`{{media:playing ? "Playing" : "Paused"}}\`. Write \`namespace:key\` without spaces and put spaces around \`:\` in conditionals and around \`-\` when subtracting. Missing keys are null, so \`default\` can replace them.
+
+Widgets that need a data provider running while they are open can list its namespace in the manifest \`requires\` array, e.g. \`"requires": ["system"]\`. Windows subscribed to \`system\` or \`weather\` receive \`system_updated\` / \`weather_updated\` events with a \`{ key: value }\` snapshot whenever it changes.
+
+Widgets can show the output of a local program through \`shellCommands\` in the manifest, e.g. \`{ "id": "uptime", "command": "uptime", "interval": 60, "timeout": 10, "output": "text" }\`. \`output\` is \`text\`, \`json\` or \`key-value\`, and each command runs from the widget folder. Results are variables like \`{{cmd:uptime.output}}\`, JSON and key=value fields as \`{{cmd:<id>.<field>}}\`, plus \`exit_code\`, \`stderr\`, \`ok\` and \`error\`. A command only runs after the user approves it in the main window.
+
+Local files are read through \`dataFiles\`, e.g. \`{ "id": "report", "path": "report.json", "format": "json", "select": { "status": "$.build.status" } }\`. Paths are relative to the widget folder, absolute, or start with \`~/\`. \`format\` is \`text\` (\`{{file:<id>.text}}\`), \`json\` (JSONPath \`select\`, or every value as \`{{file:<id>.a.b.0}}\` without it), \`lines\` (the last \`lines\` lines as \`text\`, \`last\` and \`lines.<i>\`) or \`csv\` (one \`row\`, negative from the end, with \`columns\` by name). Files are re-read when they change. When a file can not be read, \`{{file:<id>.error}}\` says why, \`{{file:<id>.ok}}\` is false, and every selected field shows the error.
+
+Web APIs are polled through \`httpSources\`, e.g. \`{ "id": "stars", "url": "https://api.github.com/repos/o/r", "headers": { "Authorization": "Bearer {{secret:token}}" }, "interval": 300, "select": { "count": "$.stargazers_count" } }\`. \`{{secret:<name>}}\` in the URL or a header is read from the system keyring, the user enters it in the app, and widgets never see it. JSON responses become fields like \`dataFiles\`, other responses \`{{http:<id>.text}}\`. \`{{http:<id>.ok}}\`, \`error\`, \`updated\` and \`stale\` describe the last request. Failed requests are retried with backoff while the last good response keeps showing.
+
+While an HTML widget is being built, \`"dev": true\` in its manifest serves it straight from its \`file\` folder instead of a copy, and reloads the page whenever a file in that folder changes, so rebuilding the bundle is enough.
+
+HTML widgets can call APIs that do not allow their origin through \`fetch("/proxy?url=" + encodeURIComponent(url), options)\`. Only hosts listed in the manifest's \`proxyHosts\` are reached, e.g. \`"proxyHosts": ["api.github.com", "*.example.com"]\`. Cookies are not sent or stored, responses over 10 MB and requests over 20 seconds fail, and refused calls answer 403 with \`{ "error": "..." }\` saying why.
+
+HTML and URL widgets only reach the commands and data their manifest's \`permissions\` ask for, and the user is asked to allow them when the widget is first opened or its permissions change: \`media:read\` (\`get_media\`, the media listener, media history), \`media:control\` (\`media_action\`), \`audio:read\` (audio capture and device), \`system:read\` (\`get_system_info\`), \`store\` (\`write_to_store_cmd\` with keys starting with \`<widget key>:\`) and \`keyring\` (\`get_widget_password\`, \`set_widget_password\` and \`delete_widget_password\` with a \`name\`, for the widget's own passwords). Templates using \`media\`, \`audio\` or \`system\` variables need the matching permission too, and \`file\`, \`http\` or \`cmd\` variables need \`file:read\`, \`http:read\` or \`cmd:read\`, e.g. \`"permissions": ["media:read", "media:control"]\`. JSON widgets are not limited.
+
 ### SystemInfo
 
 | Parameter        | Type   | Description                                          |
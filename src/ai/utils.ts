@@ -5,15 +5,8 @@ import { createOpenAI } from "@ai-sdk/openai";
 import { createAnthropic } from "@ai-sdk/anthropic";
 import { LanguageModel } from "ai";
 import { commands } from "../common/commands";
-import {
-  getPassword,
-  setPassword,
-  deletePassword,
-} from "tauri-plugin-keyring-api";
 import { getStore } from "../common";
 
-const KEYRING_SERVICE_NAME = "delta-widgets";
-
 export const providers = [
   { name: "OpenAI", value: "openai" },
   { name: "Anthropic", value: "anthropic" },
@@ -62,10 +55,7 @@ export const getModels = async () => {
   const { modelProviders, selectedModelId } = store;
   const models: AIProviderConfig[] = [];
   for (let model of modelProviders || []) {
-    const apiKey = await getPassword(
-      KEYRING_SERVICE_NAME,
-      `model-key-${model.id}`,
-    );
+    const apiKey = await commands.getModelKey({ id: model.id });
     if (apiKey) {
       model.apiKey = apiKey;
     }
@@ -100,11 +90,7 @@ export const saveModel = async (
     newModels.push({ ...config, apiKey: undefined });
   }
   if (config.apiKey) {
-    await setPassword(
-      KEYRING_SERVICE_NAME,
-      `model-key-${config.id}`,
-      config.apiKey,
-    );
+    await commands.setModelKey({ id: config.id, key: config.apiKey });
   }
 
   const pairs = [
@@ -136,7 +122,7 @@ export const deleteModel = async (id: string) => {
     ],
   });
   try {
-    await deletePassword(KEYRING_SERVICE_NAME, `model-key-${id}`);
+    await commands.deleteModelKey({ id });
   } catch (error) {
     console.error(error);
   }
@@ -116,4 +116,14 @@ export const commands = {
       thumbnail: number[];
     }>("get_media_metadata", params),
   deleteChat: (params: { id: string }) => invoke<void>("delete_chat", params),
+  subscribeTemplate: (params: { template: string }) =>
+    invoke<{ id: string; text: string }>("subscribe_template", params),
+  unsubscribeTemplate: (params: { id: string }) =>
+    invoke<void>("unsubscribe_template", params),
+  getModelKey: (params: { id: string }) =>
+    invoke<string | null>("get_model_key", params),
+  setModelKey: (params: { id: string; key: string }) =>
+    invoke<void>("set_model_key", params),
+  deleteModelKey: (params: { id: string }) =>
+    invoke<void>("delete_model_key", params),
 };
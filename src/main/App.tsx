@@ -11,6 +11,8 @@ import {
 import { AppsAddInRegular } from "@fluentui/react-icons";
 import WidgetCard from "./components/WidgetCard";
 import { listen } from "@tauri-apps/api/event";
+import { invoke } from "@tauri-apps/api/core";
+import { ask } from "@tauri-apps/plugin-dialog";
 import { trackInstall, trackUpdated } from "./utils/analytics";
 import Sidebar, { sidebarWidth } from "./components/Sidebar";
 import { useDataStore } from "./stores/useDataStore";
@@ -68,13 +70,43 @@ function App() {
   }, []);
 
   useEffect(() => {
-    const unsub = listen<string>("creator-close", () => {
+    const refresh = () => {
       if (containerRef.current) {
         containerRef.current.style.minHeight = `${containerRef.current.clientHeight}px`;
       }
       updateAllWidgets().then(() => {
         setKey((prev) => prev + 1);
       });
+    };
+    const unsubs = [
+      listen<string>("creator-close", refresh),
+      // Widgets opened, closed or moved from outside the creator, e.g. layouts
+      listen<string>("widgets-changed", refresh),
+    ];
+
+    return () => {
+      unsubs.forEach((unsub) => unsub.then((f) => f()));
+    };
+  }, []);
+
+  useEffect(() => {
+    const unsub = listen<{
+      widgetKey: string;
+      id: string;
+      command: string;
+      args: string[];
+    }>("shell-command-approval", async ({ payload }) => {
+      const command = [payload.command, ...payload.args].join(" ");
+      const allowed = await ask(
+        `The widget "${payload.widgetKey}" wants to run this command from its folder:\n\n${command}\n\nOnly allow commands you trust.`,
+        { title: "Allow shell command?", kind: "warning" }
+      );
+      if (allowed) {
+        await invoke("approve_shell_command", {
+          widgetKey: payload.widgetKey,
+          id: payload.id,
+        });
+      }
     });
 
     return () => {
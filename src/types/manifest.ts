@@ -35,6 +35,19 @@ export interface IWidget {
   elements?: IWidgetElement[];
   url?: string;
   file?: string;
+  dev?: boolean;
+  permissions?: (
+    | "media:read"
+    | "media:control"
+    | "audio:read"
+    | "system:read"
+    | "store"
+    | "keyring"
+    | "file:read"
+    | "http:read"
+    | "cmd:read"
+  )[];
+  proxyHosts?: string[];
   widgetType?: "url" | "html" | "json";
   customFields?: TCustomFields;
   customAssets?: ICustomAssets[];
@@ -2,6 +2,7 @@ import React, { useMemo } from "react";
 import { IWidgetElement } from "../../types/manifest";
 import { useDynamicTextStore } from "../stores/useVariableStore";
 import { parseDynamicText } from "../utils/utils";
+import useTemplate from "../useTemplate";
 
 interface TextComponentProps {
   component: IWidgetElement;
@@ -9,9 +10,11 @@ interface TextComponentProps {
 
 const TextComponent: React.FC<TextComponentProps> = ({ component }) => {
   const textVariables = useDynamicTextStore();
+  const template = component.data?.text || "Text";
+  const rendered = useTemplate(template);
   const text = useMemo(
-    () => parseDynamicText(component.data?.text || "Text", textVariables),
-    [textVariables]
+    () => rendered ?? parseDynamicText(template, textVariables),
+    [rendered, template, textVariables]
   );
   return (
     <div
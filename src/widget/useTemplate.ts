@@ -0,0 +1,57 @@
+import { listen } from "@tauri-apps/api/event";
+import { useEffect, useState } from "react";
+import { commands } from "../common/commands";
+
+// Filters (`| bytes`), conditionals and arithmetic are evaluated in Rust
+const EXPRESSION_REGEX = /\{\{[^}]*(\||\?|&&|==|!=|[()"'*\/+<>%]| - )[^}]*\}\}/;
+
+export const hasExpression = (text: string) => EXPRESSION_REGEX.test(text);
+
+/**
+ * Renders a template with expressions in the backend and keeps it up to
+ * date. Returns `null` for plain templates, which `parseDynamicText` handles.
+ */
+function useTemplate(template: string) {
+  const isExpression = hasExpression(template);
+  const [text, setText] = useState<string | null>(null);
+
+  useEffect(() => {
+    if (!isExpression) {
+      setText(null);
+      return;
+    }
+    let id: string | null = null;
+    let cancelled = false;
+    const unlisten = listen<{ id: string; text: string }>(
+      "template-updated",
+      (event) => {
+        if (event.payload.id === id) {
+          setText(event.payload.text);
+        }
+      },
+    );
+    commands
+      .subscribeTemplate({ template })
+      .then((update) => {
+        id = update.id;
+        if (cancelled) {
+          commands.unsubscribeTemplate({ id });
+          return;
+        }
+        setText(update.text);
+      })
+      .catch(console.error);
+
+    return () => {
+      cancelled = true;
+      unlisten.then((fn) => fn());
+      if (id) {
+        commands.unsubscribeTemplate({ id });
+      }
+    };
+  }, [template, isExpression]);
+
+  return isExpression ? (text ?? "Loading...") : null;
+}
+
+export default useTemplate;